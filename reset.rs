@@ -6,23 +6,113 @@
 
 use crate::{
     bindings,
+    delay::coarse_sleep,
     device::{self,RawDevice},
-    error::{code::*, Error, Result, from_result},
+    error::{code::*, Error, Result, from_result, to_result},
+    pr_debug,
     pr_err,
     pr_warn,
     platform,
+    str::CStr,
+    sync::smutex::Mutex,
     types::{Opaque, ForeignOwnable},
+    ThisModule,
 };
 
+use alloc::vec::Vec;
 use core::{ 
     cell::UnsafeCell, 
     ffi::c_void,
     marker::{PhantomData, PhantomPinned}, 
     pin::Pin,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+    time::Duration,
 };
 
 use macros::vtable;
 
+#[cfg(CONFIG_ACPI)]
+mod acpi;
+mod arbiter;
+mod auxiliary;
+mod blocklist;
+mod bulk;
+mod cdev;
+mod closure;
+mod composite;
+mod control;
+mod coredump;
+mod data;
+mod debugfs;
+mod driver;
+mod done;
+mod emergency;
+mod fpga;
+mod line_lock;
+mod lines;
+mod lookup;
+mod mfd;
+mod named;
+mod of;
+mod offload;
+pub mod prelude;
+#[cfg(CONFIG_POWER_SEQUENCING)]
+mod pwrseq;
+mod quarantine;
+mod quirks;
+mod ratelimit;
+mod registry;
+mod retry;
+#[cfg(CONFIG_RESET_RUST_SELFTEST)]
+mod selftest;
+mod sequence;
+mod snapshot;
+mod stats;
+mod thread;
+mod topology;
+mod uevent;
+mod work;
+
+pub use arbiter::{ArbiterClient, ArbiterNotify, ResetArbiter, Vote};
+pub use auxiliary::described_device;
+pub use bulk::{BulkDeassertGuard, BulkSharedDeassert, ResetControlArray, ResetControlBulk};
+pub use cdev::{RESET_IOC_PULSE, RESET_IOC_STATUS};
+pub use closure::{FnResetDriver, FnResetOps, ResetFn};
+pub use composite::{CompositeMap, CompositeResetDriver};
+pub use control::{
+    AcquiredResetControl, Exclusive, ExclusiveResetControl, Released, ReleasedResetControl,
+    ResetCompletion, ResetControl, ResetKind, Shared, SharedDeassert, SharedResetControl,
+};
+pub use data::{LockedResetData, SpinLockedResetData};
+pub use done::{poll_done, DoneTimeouts, ResetDoneIrq};
+pub use driver::HasResetRegistration;
+pub use emergency::EmergencyAction;
+pub use fpga::{FpgaLoadFn, FpgaRegionResets};
+pub use line_lock::{LineLocks, MAX_LOCKED_LINES};
+pub use lines::LineId;
+pub use lookup::{add_lookups, ResetLookup};
+pub use mfd::parent_regmap;
+pub use named::NamedResetControls;
+pub use of::{OfPhandleArgs, OfXlate, OfXlateEntry};
+#[cfg(CONFIG_POWER_SEQUENCING)]
+pub use pwrseq::{PwrseqDelays, ResetPwrseq};
+pub use quarantine::{add_quarantine_listener, QuarantineListener, QuarantineNotify};
+pub use registry::{registered_controllers, ResetDevices};
+pub use retry::RetryPolicy;
+pub use sequence::{PowerOnSequence, PowerOnSequenceBuilder};
+pub use snapshot::LineSnapshot;
+pub use stats::AssertedTime;
+pub use topology::{LineHolders, RemovalNotify};
+#[doc(hidden)]
+pub use stats::LineTime;
+pub use work::{ResetAction, ResetWorkItem};
+
+use blocklist::DeassertBlocklist;
+use offload::OffloadQueue;
+use thread::OpThread;
+use ratelimit::Ratelimit;
+use stats::{LineTimes, OpHistory};
+
 /// Wraps the kernel's `struct reset_controller_dev`.
 ///
 /// # Invariants
@@ -48,12 +138,108 @@ impl ResetDevice {
     pub fn as_ptr(&self) -> *mut bindings::reset_controller_dev {
         self.0.get()
     }
+
+    /// Returns the device tree node of the controller, which may be null.
+    pub fn of_node(&self) -> *mut bindings::device_node {
+        // SAFETY: By the type invariants, the pointer is valid.
+        unsafe { (*self.as_ptr()).of_node }
+    }
+
+    /// Returns the firmware node of the device of the controller, which may be null.
+    pub fn fwnode(&self) -> *mut bindings::fwnode_handle {
+        // SAFETY: By the type invariants, the pointer is valid, and so is the device of
+        // registered controllers.
+        unsafe { bindings::dev_fwnode((*self.as_ptr()).dev) }
+    }
+
+    /// Returns the number of lines of the controller.
+    pub fn nr_resets(&self) -> u32 {
+        // SAFETY: By the type invariants, the pointer is valid.
+        unsafe { (*self.as_ptr()).nr_resets }
+    }
+
+    fn call_op(&self, op: Option<RawOpFn>, id: u64) -> Result<i32> {
+        let f = op.ok_or(ENOTSUPP)?;
+        // SAFETY: By the type invariants, the pointer is valid.
+        let ret = unsafe { f(self.as_ptr(), id as _) };
+        to_result(ret)?;
+        Ok(ret)
+    }
+
+    fn ops(&self) -> &bindings::reset_control_ops {
+        // SAFETY: By the type invariants, the pointer is valid, and registered controllers have
+        // operations that live at least as long as them.
+        unsafe { &*(*self.as_ptr()).ops }
+    }
+
+    /// Returns whether the controller implements operation `op`.
+    pub fn supports(&self, op: ResetOp) -> bool {
+        let ops = self.ops();
+        match op {
+            ResetOp::Reset => ops.reset.is_some(),
+            ResetOp::Assert => ops.assert.is_some(),
+            ResetOp::Deassert => ops.deassert.is_some(),
+            ResetOp::Status => ops.status.is_some(),
+        }
+    }
+
+    /// Triggers a self-deasserting reset of line `id`, bypassing the consumer API.
+    pub(crate) fn reset(&self, id: u64) -> Result {
+        self.call_op(self.ops().reset, id).map(|_| ())
+    }
+
+    /// Asserts line `id`, bypassing the consumer API.
+    pub(crate) fn assert(&self, id: u64) -> Result {
+        self.call_op(self.ops().assert, id).map(|_| ())
+    }
+
+    /// Deasserts line `id`, bypassing the consumer API.
+    pub(crate) fn deassert(&self, id: u64) -> Result {
+        self.call_op(self.ops().deassert, id).map(|_| ())
+    }
+
+    /// Returns whether line `id` is asserted.
+    pub(crate) fn status(&self, id: u64) -> Result<bool> {
+        self.call_op(self.ops().status, id).map(|v| v > 0)
+    }
 }
 
+/// Signature of the operations in `struct reset_control_ops`.
+type RawOpFn = unsafe extern "C" fn(
+    *mut bindings::reset_controller_dev,
+    core::ffi::c_ulong,
+) -> core::ffi::c_int;
+
+// SAFETY: `ResetDevice` only allows reading fields that the C core never changes after
+// registration, so it can be shared between threads.
+unsafe impl Sync for ResetDevice {}
+
 /// A registration of a reset controller.
 pub struct ResetRegistration<T: ResetDriverOps> {
     rcdev: UnsafeCell<bindings::reset_controller_dev>,
     dev: Option<device::Device>,
+    data_pointer: *mut c_void,
+    line_times: LineTimes,
+    history: OpHistory,
+    /// Rate limiting of the failures, slow-operation warnings and blocklist warnings, each on
+    /// its own.
+    ratelimit: Ratelimit,
+    slow_ratelimit: Ratelimit,
+    blocklist_ratelimit: Ratelimit,
+    offload: Option<OffloadQueue>,
+    thread: Option<OpThread>,
+    blocklist: DeassertBlocklist,
+    deassert_delay: Duration,
+    slow_op: Duration,
+    quarantine: quarantine::Quarantine,
+    cdev: Option<cdev::Cdev>,
+    uevent_lines: &'static [u64],
+    cdev_lines: &'static [u64],
+    uevents: Option<uevent::UeventQueue>,
+    xlate: Mutex<OfXlate>,
+    max_resets: u32,
+    live_resets: AtomicU32,
+    kept: control::KeptLines,
     registered: bool,
     _p: PhantomData<T>,
     _pin: PhantomPinned,
@@ -61,9 +247,23 @@ pub struct ResetRegistration<T: ResetDriverOps> {
 
 impl <T: ResetDriverOps> Drop  for ResetRegistration<T> {
     fn drop(&mut self) {
-        // Free data as well.
-        // SAFETY: `data_pointer` was returned by `into_foreign` during registration.
-        pr_err!("reset controller dropped.\n")
+        if self.registered {
+            self.kept.unlink();
+            // Consumers may still quiesce their devices through the controller.
+            topology::provider_removed(self.rcdev.get());
+            // SAFETY: `rcdev` was registered by `register`.
+            unsafe { bindings::reset_controller_unregister(self.rcdev.get()) };
+            registry::remove(self.rcdev.get());
+            emergency::remove_controller(self.rcdev.get());
+            self.cdev = None;
+            // Run the queued operations and events before the data goes away.
+            self.offload = None;
+            self.thread = None;
+            self.uevents = None;
+            // SAFETY: `data_pointer` was returned by `into_foreign` during registration, and
+            // nothing can borrow it anymore.
+            unsafe { T::Data::from_foreign(self.data_pointer) };
+        }
     }
 }
 
@@ -71,10 +271,34 @@ impl<T: ResetDriverOps> ResetRegistration<T> {
     /// Creates a new [`ResetRegistration`] but does not register it yet.
     ///
     /// It is allowed to move.
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
+        Self::with_line_times(LineTimes::new())
+    }
+
+    const fn with_line_times(line_times: LineTimes) -> Self {
         Self {
-            rcdev: UnsafeCell::new(bindings::reset_controller_dev::default()),
+            rcdev: UnsafeCell::new(zeroed_rcdev()),
             dev: None,
+            data_pointer: core::ptr::null_mut(),
+            line_times,
+            history: OpHistory::new(),
+            ratelimit: Ratelimit::new(),
+            slow_ratelimit: Ratelimit::new(),
+            blocklist_ratelimit: Ratelimit::new(),
+            offload: None,
+            thread: None,
+            blocklist: DeassertBlocklist::new(),
+            deassert_delay: Duration::ZERO,
+            slow_op: Duration::ZERO,
+            quarantine: quarantine::Quarantine::new(),
+            cdev: None,
+            uevent_lines: &[],
+            cdev_lines: &[],
+            uevents: None,
+            xlate: Mutex::new(T::OF_XLATE),
+            max_resets: 0,
+            live_resets: AtomicU32::new(0),
+            kept: control::KeptLines::new(),
             registered: false,
             _pin: PhantomPinned,
             _p: PhantomData,
@@ -83,12 +307,63 @@ impl<T: ResetDriverOps> ResetRegistration<T> {
 
     /// Registers a reset controller with the rest of the kernel.
     /// 
-    /// use `devm_reset_controller_register` to register this device.
+    /// The controller is unregistered when the registration is dropped, usually along with the
+    /// driver data it is part of, see [`HasResetRegistration`].
+    ///
+    /// The controller uses the device tree node of `dev`. MFD cells sharing the node of their
+    /// parent use [`ResetRegistration::register_mfd_cell`] instead.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
     pub fn register(
         self: Pin<&mut Self>,
         dev:  &mut platform::Device,
         nr_resets: u32,
         data: T::Data,
+    ) -> Result {
+        // SAFETY: `dev` is a valid device.
+        let of_node = unsafe { (*dev.raw_device()).of_node };
+        self.register_inner(dev, of_node, nr_resets, data)
+    }
+
+    /// Registers a reset controller hosted by a device on any bus, e.g., a PCIe endpoint
+    /// exposing reset lines of downstream components through its BARs.
+    ///
+    /// The controller belongs to `dev` and uses its device tree node, if any, so consumers
+    /// find it through the node describing `dev`. The node of the parent is never used: the
+    /// parent of a PCI device is its bridge.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn register_device(
+        self: Pin<&mut Self>,
+        dev: &impl RawDevice,
+        nr_resets: u32,
+        data: T::Data,
+    ) -> Result {
+        // SAFETY: `dev` is a valid device.
+        let of_node = unsafe { (*dev.raw_device()).of_node };
+        self.register_inner(dev, of_node, nr_resets, data)
+    }
+
+    /// Registers a reset controller without firmware description, e.g., one hosted by a USB or
+    /// I2C dongle.
+    ///
+    /// The controller has no device tree node, even if `dev` or its parent do, so consumers only
+    /// find it through entries added with [`add_lookups`], which name it by the name of `dev`.
+    /// The index of an entry is the id of the line.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn register_anonymous(
+        self: Pin<&mut Self>,
+        dev: &impl RawDevice,
+        nr_resets: u32,
+        data: T::Data,
+    ) -> Result {
+        self.register_inner(dev, core::ptr::null_mut(), nr_resets, data)
+    }
+
+    fn register_inner(
+        self: Pin<&mut Self>,
+        dev: &impl RawDevice,
+        of_node: *mut bindings::device_node,
+        nr_resets: u32,
+        data: T::Data,
     ) -> Result {
         // SAFETY: We never move out of `this`.
         let this = unsafe { self.get_unchecked_mut() };
@@ -97,31 +372,494 @@ impl<T: ResetDriverOps> ResetRegistration<T> {
             return Err(EINVAL);
         }
         
+        if this.cdev_lines.iter().any(|&id| id >= nr_resets as u64) {
+            return Err(EINVAL);
+        }
+        this.line_times.init(nr_resets.max(this.max_resets))?;
+        this.live_resets.store(nr_resets, Ordering::Relaxed);
+        this.deassert_delay = quirks::deassert_delay(dev.name());
+        this.slow_op = quirks::slow_op_threshold(dev.name(), T::SLOW_OP_THRESHOLD);
+        let threshold = quirks::quarantine_after(dev.name(), T::QUARANTINE_AFTER_FAILURES);
+        this.quarantine.init(dev, threshold)?;
+        let xlate = *this.xlate.lock();
         let rcdev = this.rcdev.get_mut();
 
         rcdev.dev = dev.raw_device();
         rcdev.nr_resets = nr_resets;
-        rcdev.of_node = unsafe {(*rcdev.dev).of_node};
+        rcdev.of_node = of_node;
+        rcdev.of_reset_n_cells = xlate.n_cells() as _;
+        rcdev.of_xlate = Some(Adapter::<T>::of_xlate_callback);
         rcdev.ops = Adapter::<T>::build();
 
         let data_pointer = <T::Data as ForeignOwnable>::into_foreign(data) as *mut c_void;
 
-        unsafe { bindings::dev_set_drvdata(rcdev.dev, data_pointer)};
-        let ret: i32 = unsafe { bindings::devm_reset_controller_register(rcdev.dev, this.rcdev.get()) };
-        if ret < 0 {
+        // Validate before anything is started, so that there is nothing to stop on failure.
+        // SAFETY: `data_pointer` was just returned by `into_foreign`.
+        if let Err(e) = T::validate(unsafe { T::Data::borrow(data_pointer) }, nr_resets) {
+            pr_warn!("{} {}: validation failed: {:?}\n", T::NAME, dev.name(), e);
             // SAFETY: `data_pointer` was returned by `into_foreign` above.
             unsafe { T::Data::from_foreign(data_pointer) };
+            return Err(e);
+        }
+        // Operations may be called as soon as the controller is registered.
+        this.data_pointer = data_pointer;
+        if let Err(e) = this.start(dev, xlate) {
+            // SAFETY: `data_pointer` was set to the value returned by `into_foreign` above, and
+            // the controller is not registered, so nothing borrows it.
+            unsafe { this.abort_register() };
+            return Err(e);
+        }
+        let many: Option<registry::ManyFn> = if T::HAS_ASSERT_MANY || T::HAS_DEASSERT_MANY {
+            Some(Adapter::<T>::call_many)
+        } else {
+            None
+        };
+        let lines: *const LineTimes = &this.line_times;
+        // SAFETY: `rcdev` and `line_times` are pinned and removed from the registry when `this` is
+        // dropped, and `many` may be called on `rcdev` while it is registered.
+        if let Err(e) = unsafe { registry::add(this.rcdev.get(), T::NAME, many, lines) } {
+            // SAFETY: As above.
+            unsafe { this.abort_register() };
+            return Err(e);
+        }
+        // SAFETY: `rcdev` is pinned and unregistered when `this` is dropped.
+        let ret: i32 = unsafe { bindings::reset_controller_register(this.rcdev.get()) };
+        if ret < 0 {
+            registry::remove(this.rcdev.get());
+            // SAFETY: As above, and the registry no longer borrows it.
+            unsafe { this.abort_register() };
             return Err(Error::from_errno(ret));
         }
+        if !this.cdev_lines.is_empty() {
+            // SAFETY: `rcdev` is registered, and the character device is dropped before it goes
+            // away.
+            match unsafe { cdev::Cdev::try_new(dev.name(), this.rcdev.get(), this.cdev_lines) } {
+                Ok(cdev) => this.cdev = Some(cdev),
+                Err(e) => {
+                    // SAFETY: `rcdev` was registered above.
+                    unsafe { bindings::reset_controller_unregister(this.rcdev.get()) };
+                    registry::remove(this.rcdev.get());
+                    // SAFETY: As above, and neither the C core nor the registry borrow it
+                    // anymore.
+                    unsafe { this.abort_register() };
+                    return Err(e);
+                }
+            }
+        }
         
+        // Lines kept asserted in suspend are asserted with interrupts disabled.
+        if !T::OPS_SLEEP && this.offload.is_none() && this.thread.is_none() {
+            // SAFETY: `kept` is pinned, and unlinked when `this` is dropped.
+            unsafe { this.kept.link(this.rcdev.get()) };
+        }
         this.dev = Some(device::Device::from_dev(dev));
         this.registered = true;
+
+        #[cfg(CONFIG_RESET_RUST_SELFTEST)]
+        {
+            // SAFETY: `rcdev` is registered and lives as long as `this`.
+            let rcdev = unsafe { ResetDevice::from_raw(this.rcdev.get()) };
+            selftest::run(dev.name(), rcdev, T::SELFTEST_SAFE_LINES);
+        }
+        Ok(())
+    }
+
+    /// Starts the queues and threads of the controller, once its data is set.
+    fn start(&mut self, dev: &impl RawDevice, xlate: OfXlate) -> Result {
+        if !self.uevent_lines.is_empty() {
+            self.uevents = Some(uevent::UeventQueue::try_new(dev, xlate)?);
+        }
+        if T::OFFLOAD_FROM_ATOMIC {
+            // SAFETY: `rcdev` is pinned, and the queue is dropped with `self`.
+            self.offload = Some(unsafe {
+                OffloadQueue::try_new(dev.name(), self.rcdev.get(), Adapter::<T>::run_queued)?
+            });
+        }
+        if T::THREADED_OPS {
+            // SAFETY: `rcdev` is pinned, and the thread is stopped when `self` is dropped.
+            self.thread = Some(unsafe {
+                OpThread::try_new(dev.name(), self.rcdev.get(), Adapter::<T>::run_queued)?
+            });
+        }
+        Ok(())
+    }
+
+    /// Undoes a failed registration: stops what [`ResetRegistration::start`] started and frees
+    /// the data.
+    ///
+    /// # Safety
+    ///
+    /// `self.data_pointer` must have been returned by `into_foreign`, and nothing may borrow it
+    /// anymore.
+    unsafe fn abort_register(&mut self) {
+        self.offload = None;
+        self.thread = None;
+        self.uevents = None;
+        let data_pointer = core::mem::replace(&mut self.data_pointer, core::ptr::null_mut());
+        // SAFETY: By the safety requirements, `data_pointer` was returned by `into_foreign`.
+        unsafe { T::Data::from_foreign(data_pointer) };
+    }
+
+    /// Refuses deasserts on the lines in `list`, a comma-separated list of line ids or names.
+    ///
+    /// This is meant for silicon bring-up, with `list` coming from a module parameter, so that
+    /// blocks known to hang the system stay in reset. Names are those known to the translation in
+    /// use, see [`ResetRegistration::set_of_xlate`]. Must be called before
+    /// [`ResetRegistration::register`], fails with `EINVAL` otherwise or if an entry cannot be
+    /// parsed.
+    pub fn set_deassert_blocklist(self: Pin<&mut Self>, list: &[u8]) -> Result {
+        // SAFETY: We never move out of `this`.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.registered {
+            return Err(EINVAL);
+        }
+        this.blocklist = DeassertBlocklist::parse(list, &this.xlate.lock())?;
+        Ok(())
+    }
+
+    /// Sends a uevent to userspace whenever one of `lines` is asserted, deasserted or reset.
+    ///
+    /// This lets system health daemons react to, e.g., coprocessor resets without polling. Must be
+    /// called before [`ResetRegistration::register`], fails with `EINVAL` otherwise.
+    pub fn set_uevent_lines(self: Pin<&mut Self>, lines: &'static [u64]) -> Result {
+        // SAFETY: We never move out of `this`.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.registered {
+            return Err(EINVAL);
+        }
+        this.uevent_lines = lines;
+        Ok(())
+    }
+
+    /// Uses `xlate` to translate device tree specifiers instead of [`ResetDriverOps::OF_XLATE`].
+    ///
+    /// This lets one driver serve controllers with different bindings, e.g., with a line table
+    /// per SoC. Must be called before [`ResetRegistration::register`] and before
+    /// [`ResetRegistration::set_deassert_blocklist`], fails with `EINVAL` otherwise.
+    pub fn set_of_xlate(self: Pin<&mut Self>, xlate: OfXlate) -> Result {
+        // SAFETY: We never move out of `this`.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.registered {
+            return Err(EINVAL);
+        }
+        *this.xlate.lock() = xlate;
+        Ok(())
+    }
+
+    /// Selects the one-to-one translation of specifiers with `n_cells` cells, 1 for
+    /// [`OfXlate::OneCell`] and 2 for [`OfXlate::TwoCell`].
+    ///
+    /// Fails with `EINVAL` for other cell counts, and if the controller is registered.
+    pub fn set_of_cells(self: Pin<&mut Self>, n_cells: u32) -> Result {
+        let xlate = match n_cells {
+            1 => OfXlate::OneCell,
+            2 => OfXlate::TwoCell,
+            _ => return Err(EINVAL),
+        };
+        self.set_of_xlate(xlate)
+    }
+
+    /// Makes the controller hold a reference to `module` while consumers hold references to its
+    /// lines, so that the module providing the operations cannot be unloaded under them.
+    ///
+    /// Must be called before [`ResetRegistration::register`], fails with `EINVAL` otherwise.
+    pub fn set_owner(self: Pin<&mut Self>, module: &'static ThisModule) -> Result {
+        // SAFETY: We never move out of `this`.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.registered {
+            return Err(EINVAL);
+        }
+        this.rcdev.get_mut().owner = module.as_ptr();
         Ok(())
     }
+
+    /// Registers a reset controller whose line count is computed by [`ResetDriverOps::nr_resets`].
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn register_counted(
+        self: Pin<&mut Self>,
+        dev: &mut platform::Device,
+        data: T::Data,
+    ) -> Result {
+        let nr_resets = T::nr_resets(dev, &data)?;
+        self.register(dev, nr_resets, data)
+    }
+
+    /// Returns the request for line `id`, with the flags recorded when it was translated.
+    fn request(&self, id: u64) -> ResetRequest {
+        ResetRequest::new(id, self.line_times.flags(id))
+    }
+
+    /// Returns the data passed to [`ResetRegistration::register`].
+    ///
+    /// Returns `None` if the controller is not registered.
+    pub fn data(&self) -> Option<<T::Data as ForeignOwnable>::Borrowed<'_>> {
+        if !self.registered {
+            return None;
+        }
+        // SAFETY: `data_pointer` was returned by `into_foreign` during registration, and it is
+        // never freed while the registration exists.
+        Some(unsafe { T::Data::borrow(self.data_pointer) })
+    }
+
+    /// Asserts line `id` when a thermal zone trips the cooling device of the controller.
+    ///
+    /// The controller is registered as a cooling device with its device tree node, so zones
+    /// bind it to a trip point with `cooling-maps`, without any support from their drivers. The
+    /// thermal core handles critical trips by shutting down without driving cooling devices, so
+    /// the device is bound to a `passive` or `active` trip below the critical one to quiesce the
+    /// hardware first. When the zone cools down, the lines are left asserted for their
+    /// consumers to deassert, and asserted again on the next trip. Fails with `ENODEV` if the
+    /// controller has no node, and with `ENOTSUPP` without `CONFIG_THERMAL_OF`.
+    pub fn assert_on_thermal_trip(&self, id: u64) -> Result {
+        if !self.registered || id >= self.nr_resets() as u64 {
+            return Err(EINVAL);
+        }
+        // SAFETY: `rcdev` is registered, and its lines are removed when `self` is dropped.
+        let trigger = unsafe { emergency::thermal_trip(self.rcdev.get()) }?;
+        self.add_emergency(trigger, EmergencyAction::Assert, id)
+    }
+
+    /// Asserts or pulses line `id` when the watchdog with id `watchdog` (or any watchdog, if
+    /// `None`) hits its pretimeout.
+    ///
+    /// The `reset` pretimeout governor must be selected for the watchdog, through its
+    /// `pretimeout_governor` sysfs attribute. Pretimeouts fire in interrupt context, so this
+    /// fails with `EINVAL` for controllers whose operations sleep, unless they are queued, see
+    /// [`ResetDriverOps::OFFLOAD_FROM_ATOMIC`] and [`ResetDriverOps::THREADED_OPS`].
+    pub fn fire_on_watchdog_pretimeout(
+        &self,
+        watchdog: Option<i32>,
+        id: u64,
+        action: EmergencyAction,
+    ) -> Result {
+        if T::OPS_SLEEP && self.offload.is_none() && self.thread.is_none() {
+            return Err(EINVAL);
+        }
+        let trigger = emergency::watchdog_pretimeout(watchdog)?;
+        self.add_emergency(trigger, action, id)
+    }
+
+    /// Asserts line `id` when the kernel panics, e.g., to stop a DMA-capable device before kdump
+    /// captures memory.
+    ///
+    /// This is best effort: it runs from a panic notifier, with other CPUs stopped. Panic
+    /// notifiers only run before kdump when `crash_kexec_post_notifiers` is on the kernel
+    /// command line. Fails with `EINVAL` for controllers whose operations sleep, offloaded or
+    /// run on a thread, which cannot run from the notifier.
+    pub fn assert_on_panic(&self, id: u64) -> Result {
+        if T::OPS_SLEEP || self.offload.is_some() || self.thread.is_some() {
+            return Err(EINVAL);
+        }
+        let trigger = emergency::panic()?;
+        self.add_emergency(trigger, EmergencyAction::Assert, id)
+    }
+
+    /// Asserts line `id` once all devices are suspended, and deasserts it before devices resume.
+    ///
+    /// This runs from syscore operations, on one CPU with interrupts disabled, after the `noirq`
+    /// suspend of all devices. Lines are asserted in the order they were added, and deasserted
+    /// in the reverse order. Failures are logged but do not abort the suspend. Fails with
+    /// `EINVAL` for controllers whose operations sleep, offloaded or run on a thread, which
+    /// cannot run from there.
+    pub fn assert_in_syscore_suspend(&self, id: u64) -> Result {
+        if T::OPS_SLEEP || self.offload.is_some() || self.thread.is_some() {
+            return Err(EINVAL);
+        }
+        self.add_emergency(emergency::syscore_suspend(), EmergencyAction::Assert, id)
+    }
+
+    /// Returns who holds line `id`, among the consumers using the Rust abstractions.
+    ///
+    /// Providers use it, e.g., to refuse a destructive global reset while lines are held.
+    /// Consumers written in C are not tracked, so [`LineHolders::Free`] is only a hint.
+    pub fn line_holders(&self, id: u64) -> LineHolders {
+        topology::holders(self.rcdev.get(), id)
+    }
+
+    /// Returns whether any line of the controller is held, see
+    /// [`ResetRegistration::line_holders`].
+    pub fn any_line_held(&self) -> bool {
+        topology::any_held(self.rcdev.get())
+    }
+
+    /// Exposes the lines in `whitelist` to userspace through the `/dev/reset-<dev>` character
+    /// device, see [`RESET_IOC_STATUS`] and [`RESET_IOC_PULSE`].
+    ///
+    /// This is meant for manufacturing test and bring-up. Only lines whose reset is harmless
+    /// while the system runs belong in `whitelist`. The character device is created by
+    /// [`ResetRegistration::register`], which fails if a line does not exist. Must be called
+    /// before it, fails with `EINVAL` otherwise.
+    pub fn expose_chardev(self: Pin<&mut Self>, whitelist: &'static [u64]) -> Result {
+        // SAFETY: We never move out of `this`.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.registered {
+            return Err(EINVAL);
+        }
+        this.cdev_lines = whitelist;
+        Ok(())
+    }
+
+    /// Returns whether the controller is quarantined, see
+    /// [`ResetDriverOps::QUARANTINE_AFTER_FAILURES`].
+    pub fn is_quarantined(&self) -> bool {
+        self.quarantine.is_quarantined()
+    }
+
+    /// Lets operations reach the controller again after it was quarantined, e.g., once its
+    /// firmware is reloaded.
+    pub fn lift_quarantine(&self) {
+        self.quarantine.lift();
+    }
+
+    fn add_emergency(
+        &self,
+        trigger: emergency::Trigger,
+        action: EmergencyAction,
+        id: u64,
+    ) -> Result {
+        if !self.registered || id >= self.nr_resets() as u64 {
+            return Err(EINVAL);
+        }
+        let direct: emergency::DirectFn = Adapter::<T>::call_direct;
+        // SAFETY: `rcdev` is registered, and its lines are removed when `self` is dropped, before
+        // its data is freed.
+        unsafe { emergency::add(trigger, action, self.rcdev.get(), id, direct) }
+    }
+
+    /// Returns the name of the device of the controller, for messages.
+    ///
+    /// Must only be called once `rcdev` is set up by [`ResetRegistration::register`].
+    fn dev_name(&self) -> &CStr {
+        // SAFETY: `rcdev.dev` is a valid device, which outlives the registration.
+        unsafe { CStr::from_char_ptr(bindings::dev_name((*self.rcdev.get()).dev)) }
+    }
+
+    fn nr_resets(&self) -> u32 {
+        self.live_resets.load(Ordering::Relaxed)
+    }
+
+    /// Makes room for up to `max_resets` lines, so that the controller can grow with
+    /// [`ResetRegistration::resize`], e.g., when an FPGA is partially reconfigured.
+    ///
+    /// Must be called before [`ResetRegistration::register`], fails with `EINVAL` otherwise.
+    pub fn set_max_resets(self: Pin<&mut Self>, max_resets: u32) -> Result {
+        // SAFETY: We never move out of `this`.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.registered {
+            return Err(EINVAL);
+        }
+        this.max_resets = max_resets;
+        Ok(())
+    }
+
+    /// Changes the number of lines of the registered controller to `nr_resets`, and its
+    /// translation to `xlate` if set, e.g., to the line table of a new FPGA image.
+    ///
+    /// Consumer lookups see either the old or the new lines, never a mix. Operations on lines
+    /// removed by a shrink fail with `ENODEV`, consumers should put them. Fails with `EINVAL` if
+    /// the controller is not registered, `nr_resets` exceeds the room made by
+    /// [`ResetRegistration::set_max_resets`] or `xlate` uses another number of cells.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn resize(&self, nr_resets: u32, xlate: Option<OfXlate>) -> Result {
+        if !self.registered || nr_resets as usize > self.line_times.len() {
+            return Err(EINVAL);
+        }
+        // Lookups translate specifiers under the lock, so they are serialized with the update.
+        let mut current = self.xlate.lock();
+        if let Some(xlate) = xlate {
+            if xlate.n_cells() != current.n_cells() {
+                return Err(EINVAL);
+            }
+            *current = xlate;
+        }
+        self.live_resets.store(nr_resets, Ordering::Relaxed);
+        // SAFETY: `rcdev` is valid. The C core reads `nr_resets` without synchronization, and
+        // only to check line ids, so a racing reader sees either value.
+        unsafe { core::ptr::addr_of_mut!((*self.rcdev.get()).nr_resets).write_volatile(nr_resets) };
+        Ok(())
+    }
+
+    /// Returns the time line `id` has spent asserted, or `None` if there is no such line.
+    pub fn asserted_time(&self, id: u64) -> Option<AssertedTime> {
+        self.line_times.get(id)
+    }
+
+    /// Waits for the operations queued from atomic context so far to have run, see
+    /// [`ResetDriverOps::OFFLOAD_FROM_ATOMIC`] and [`ResetDriverOps::THREADED_OPS`].
+    ///
+    /// Providers call it, e.g., before a suspend or a firmware reload. Fails with `EINTR` if
+    /// interrupted by a signal.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn flush_queued(&self) -> Result {
+        if let Some(thread) = &self.thread {
+            thread.flush()?;
+        }
+        if let Some(offload) = &self.offload {
+            offload.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Captures whether each line of the registered controller is asserted.
+    ///
+    /// The state is read with [`ResetDriverOps::status`] if the controller has it, lines whose
+    /// status fails are left unknown. Otherwise it is the state the lines were put in through
+    /// the framework, lines never asserted being taken as deasserted. Fails with `EINVAL` if the
+    /// controller is not registered.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn snapshot(&self) -> Result<LineSnapshot> {
+        if !self.registered {
+            return Err(EINVAL);
+        }
+        let nr_resets = self.nr_resets() as u64;
+        let mut lines = Vec::try_with_capacity(nr_resets as usize)?;
+        for id in 0..nr_resets {
+            let state = if T::HAS_STATUS {
+                // SAFETY: `rcdev` is registered.
+                let ret = unsafe { Adapter::<T>::status_callback(self.rcdev.get(), id as _) };
+                to_result(ret).ok().map(|_| ret > 0)
+            } else {
+                self.line_times.get(id).map(|t| t.current.is_some())
+            };
+            lines.try_push(state)?;
+        }
+        Ok(LineSnapshot { lines })
+    }
+
+    /// Puts the lines of the registered controller back in the state captured in `snapshot`.
+    ///
+    /// Asserted lines are asserted first, then deasserted lines are deasserted, both in
+    /// increasing order. Lines whose state is unknown, and lines not in both the snapshot and
+    /// the controller, e.g., after [`ResetRegistration::resize`], are left alone. All lines are
+    /// restored even if some fail, the first error is returned. Fails with `EINVAL` if the
+    /// controller is not registered.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn restore(&self, snapshot: &LineSnapshot) -> Result {
+        if !self.registered {
+            return Err(EINVAL);
+        }
+        let nr_resets = (self.nr_resets() as u64).min(snapshot.len() as u64);
+        let mut ret = Ok(());
+        for asserted in [true, false] {
+            let callback = match asserted {
+                true if T::HAS_ASSERT => Adapter::<T>::assert_callback,
+                false if T::HAS_DEASSERT => Adapter::<T>::deassert_callback,
+                _ => continue,
+            };
+            for id in (0..nr_resets).filter(|&id| snapshot.is_asserted(id) == Some(asserted)) {
+                // SAFETY: `rcdev` is registered.
+                let r = to_result(unsafe { callback(self.rcdev.get(), id as _) });
+                if ret.is_ok() {
+                    ret = r;
+                }
+            }
+        }
+        ret
+    }
 }
 
-// SAFETY: `Registration` doesn't offer any methods or access to fields when shared between threads
-// or CPUs, so it is safe to share it.
+// SAFETY: `Registration` only gives access to `T::Data` when shared between threads or CPUs, which
+// is `Sync`, so it is safe to share it.
 unsafe impl<T: ResetDriverOps> Sync for ResetRegistration<T> {}
 
 // SAFETY: Registration with and unregistration from the gpio subsystem can happen from any thread.
@@ -130,46 +868,372 @@ unsafe impl<T: ResetDriverOps> Sync for ResetRegistration<T> {}
 #[allow(clippy::non_send_fields_in_send_ty)]
 unsafe impl<T: ResetDriverOps> Send for ResetRegistration<T> {}
 
-/// Registers a gpio chip with the rest of the kernel.
+const fn zeroed_rcdev() -> bindings::reset_controller_dev {
+    // SAFETY: All fields of `reset_controller_dev` are integers or (function) pointers, for which
+    // all zeroes is a valid value.
+    unsafe {
+        core::mem::transmute([0u8; core::mem::size_of::<bindings::reset_controller_dev>()])
+    }
+}
+
+/// Returns a `reset_control_ops` without operations.
+const fn zeroed_ops() -> bindings::reset_control_ops {
+    // SAFETY: All fields of `reset_control_ops` are optional function pointers, for which all
+    // zeroes is `None`.
+    unsafe { core::mem::transmute([0u8; core::mem::size_of::<bindings::reset_control_ops>()]) }
+}
+
+/// A registration of a built-in reset controller, living in a `static`.
+///
+/// Declared with [`crate::static_reset_registration`], it needs no run-time initialisation and
+/// keeps the accounting of its lines in static storage, so registering it at init time does not
+/// allocate for them.
+pub struct StaticResetRegistration<T: ResetDriverOps> {
+    reg: UnsafeCell<ResetRegistration<T>>,
+    taken: AtomicBool,
+}
+
+impl<T: ResetDriverOps> StaticResetRegistration<T> {
+    /// Creates a registration of a controller with `lines.len()` lines.
+    ///
+    /// Only public for [`crate::static_reset_registration`].
+    #[doc(hidden)]
+    pub const fn new(lines: &'static [LineTime]) -> Self {
+        Self {
+            reg: UnsafeCell::new(ResetRegistration::with_line_times(LineTimes::new_static(lines))),
+            taken: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the registration, to register it.
+    ///
+    /// Its number of lines must be the one the static was declared with. Fails with `EBUSY` if
+    /// the registration was already returned, a static registration can only be used once.
+    pub fn get(&'static self) -> Result<Pin<&'static mut ResetRegistration<T>>> {
+        if self.taken.swap(true, Ordering::AcqRel) {
+            return Err(EBUSY);
+        }
+        // SAFETY: `taken` ensures that only one mutable reference is ever created, and the
+        // registration never moves as it lives in a `static`.
+        Ok(unsafe { Pin::new_unchecked(&mut *self.reg.get()) })
+    }
+}
+
+// SAFETY: The registration is only reachable through the single mutable reference returned by
+// `get`, and `ResetRegistration` is `Sync` and `Send`.
+unsafe impl<T: ResetDriverOps> Sync for StaticResetRegistration<T> {}
+
+/// Declares a [`StaticResetRegistration`] of a controller with a fixed number of lines.
+///
+/// ```ignore
+/// static_reset_registration!(static RESET: StaticResetRegistration<FooReset> = 32);
+///
+/// reset_controller_register!(RESET.get()?, pdev, 32, (), lines: FooLines::OF_XLATE_TABLE)?;
+/// ```
+#[macro_export]
+macro_rules! static_reset_registration {
+    ($vis:vis static $name:ident: StaticResetRegistration<$t:ty> = $nr_resets:expr $(;)?) => {
+        $vis static $name: $crate::reset::StaticResetRegistration<$t> = {
+            static LINES: [$crate::reset::LineTime; $nr_resets] =
+                [$crate::reset::LineTime::NEW; $nr_resets];
+            $crate::reset::StaticResetRegistration::new(&LINES)
+        };
+    };
+}
+
+/// Registers a reset controller with the rest of the kernel.
+///
+/// After the registration, device and line count of [`ResetRegistration::register`], it accepts
+/// optional settings, applied in order before registering:
 ///
-/// It automatically defines the required lock classes.
+/// - `xlate: <OfXlate>`, see [`ResetRegistration::set_of_xlate`],
+/// - `cells: <u32>`, see [`ResetRegistration::set_of_cells`],
+/// - `lines: <&'static [OfXlateEntry]>`, a line table translating specifiers, e.g., one generated
+///   by [`crate::declare_reset_lines`],
+/// - `owner: <&'static ThisModule>`, see [`ResetRegistration::set_owner`], usually
+///   `&THIS_MODULE`,
+/// - `chardev: <&'static [u64]>`, see [`ResetRegistration::expose_chardev`].
+///
+/// ```ignore
+/// reset_controller_register!(
+///     reg.as_mut(),
+///     pdev,
+///     FooLines::ALL.len() as u32,
+///     data,
+///     lines: FooLines::OF_XLATE_TABLE,
+///     owner: &THIS_MODULE,
+/// )?;
+/// ```
+///
+/// The settings need no lock classes or other statics, the queues created by the registration
+/// define their own.
 #[macro_export]
 macro_rules! reset_controller_register {
-    ($reg:expr, $dev:expr, $nr_resets:expr, $data:expr $(,)?) => {{
-        $crate::reset::ResetRegistration::register(
-            $reg,
-            $dev,
-            $nr_resets,
-            $data,
-        )
+    ($reg:expr, $dev:expr, $nr_resets:expr, $data:expr $(, $key:ident: $value:expr)* $(,)?) => {{
+        let mut reg: ::core::pin::Pin<&mut $crate::reset::ResetRegistration<_>> = $reg;
+        (|| -> $crate::error::Result {
+            $($crate::reset_controller_register!(@set reg, $key, $value);)*
+            $crate::reset::ResetRegistration::register(reg, $dev, $nr_resets, $data)
+        })()
     }};
+    (@set $reg:ident, xlate, $value:expr) => {
+        $reg.as_mut().set_of_xlate($value)?
+    };
+    (@set $reg:ident, cells, $value:expr) => {
+        $reg.as_mut().set_of_cells($value)?
+    };
+    (@set $reg:ident, lines, $value:expr) => {
+        $reg.as_mut().set_of_xlate($crate::reset::OfXlate::Table($value))?
+    };
+    (@set $reg:ident, owner, $value:expr) => {
+        $reg.as_mut().set_owner($value)?
+    };
+    (@set $reg:ident, chardev, $value:expr) => {
+        $reg.as_mut().expose_chardev($value)?
+    };
+}
+
+/// Flags decoded from the flags cell of a device tree reset specifier.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResetFlags(u32);
+
+impl ResetFlags {
+    /// The line is active low.
+    pub const ACTIVE_LOW: Self = Self(1 << 0);
+    /// The line is shared with other devices.
+    pub const SHARED: Self = Self(1 << 1);
+    /// The line only supports pulsing, not holding it asserted.
+    pub const PULSE: Self = Self(1 << 2);
+
+    const ALL: u32 = Self::ACTIVE_LOW.0 | Self::SHARED.0 | Self::PULSE.0;
+
+    /// Returns an empty set of flags.
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Creates flags from the raw cell value, fails with `EINVAL` on unknown bits.
+    pub fn from_bits(bits: u32) -> Result<Self> {
+        if bits & !Self::ALL != 0 {
+            return Err(EINVAL);
+        }
+        Ok(Self(bits))
+    }
+
+    /// Returns the raw value of the flags.
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+
+    /// Returns whether all flags of `other` are set.
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl core::ops::BitOr for ResetFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// A request for an operation on a reset line.
+///
+/// Carries the line id together with the flags decoded by the translation. Only the id is passed
+/// to the C core, so that all specifiers of a line share its exclusive and shared accounting; the
+/// flags are kept by the registration, per line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResetRequest {
+    id: u64,
+    flags: ResetFlags,
+}
+
+impl ResetRequest {
+    /// Creates a new request for line `id`.
+    pub const fn new(id: u64, flags: ResetFlags) -> Self {
+        Self { id, flags }
+    }
+
+    /// Returns the line id.
+    pub const fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the line, e.g., to match it against lines declared with
+    /// [`crate::declare_reset_lines`].
+    pub const fn line(&self) -> LineId {
+        LineId::new(self.id)
+    }
+
+    /// Returns the flags of the line.
+    pub const fn flags(&self) -> ResetFlags {
+        self.flags
+    }
 }
 
 /// Reset controller's operations
+///
+/// Operations that cannot proceed because the controller is transiently busy, e.g., firmware
+/// handling another request, return `EAGAIN`. Consumers retry those with
+/// [`ResetControl::retry_busy`].
+///
+/// Methods only called from process context carry a klint `preempt_count` annotation. The
+/// operations on single lines have none, as they run in atomic context for some consumers while
+/// controllers setting [`ResetDriverOps::OPS_SLEEP`] legitimately sleep in them.
 #[vtable]
 pub trait ResetDriverOps {
     /// User data that will be accessible to all operations
     type Data: ForeignOwnable + Send + Sync ;
 
+    /// Name of the driver, prefixing its messages and listed in
+    /// `/sys/kernel/debug/reset/controllers`.
+    ///
+    /// Together with the device name, it tells controllers apart on systems with several of
+    /// them.
+    const NAME: &'static CStr = crate::c_str!("reset");
+
+    /// Translation of device tree reset specifiers into line ids, unless overridden with
+    /// [`ResetRegistration::set_of_xlate`].
+    const OF_XLATE: OfXlate = OfXlate::OneCell;
+
+    /// Whether operations invoked from atomic context are queued to a workqueue.
+    ///
+    /// Controllers whose operations sleep set this. Resets, asserts and deasserts from atomic
+    /// context then return success immediately and run in order on a per-controller workqueue,
+    /// and status queries fail with `EAGAIN`. The provider waits for them with
+    /// [`ResetRegistration::flush_queued`].
+    const OFFLOAD_FROM_ATOMIC: bool = false;
+
+    /// Whether all operations run one at a time, in submission order, on a kernel thread of the
+    /// controller.
+    ///
+    /// Controllers fronting firmware mailboxes that cannot reorder or interleave commands set
+    /// this. Callers in process context wait for their operation, callers in atomic context only
+    /// queue it, see [`ResetDriverOps::OFFLOAD_FROM_ATOMIC`] for what they get back. Takes
+    /// precedence over [`ResetDriverOps::OFFLOAD_FROM_ATOMIC`].
+    const THREADED_OPS: bool = false;
+
+    /// Whether the operations sleep, e.g., because they go through USB transfers.
+    ///
+    /// Operations invoked from atomic context then fail with `EAGAIN` instead of sleeping, unless
+    /// [`ResetDriverOps::OFFLOAD_FROM_ATOMIC`] is also set. Controllers hosted by USB interfaces,
+    /// registered with [`ResetRegistration::register_device`], must set it.
+    const OPS_SLEEP: bool = false;
+
+    /// Duration above which an operation is reported as slow, zero to never report them.
+    ///
+    /// Slow operations are a frequent cause of probe timeouts, hard to attribute otherwise. The
+    /// warning, rate limited, names the controller, the operation and the line. Overridden by
+    /// the `reset.slow_op_us` kernel parameter.
+    const SLOW_OP_THRESHOLD: Duration = Duration::ZERO;
+
+    /// Lines that the self-test may assert and deassert, see `CONFIG_RESET_RUST_SELFTEST`.
+    ///
+    /// Only lines of blocks that are unused at probe time and survive being reset belong here.
+    const SELFTEST_SAFE_LINES: &'static [u64] = &[];
+
+    /// Number of consecutive failed operations on a line after which a device coredump is
+    /// captured, 0 to never capture one.
+    ///
+    /// The dump holds the state of the lines, the last operations of the controller and what
+    /// [`ResetDriverOps::coredump`] adds. Failures in atomic context are counted but never dumped.
+    /// Requires `CONFIG_DEV_COREDUMP`.
+    const COREDUMP_AFTER_FAILURES: u32 = 0;
+
+    /// Number of consecutive failed operations, on any line, after which the controller is
+    /// quarantined, 0 to never quarantine it.
+    ///
+    /// Operations of a quarantined controller fail with `ENOTRECOVERABLE` without being called,
+    /// until [`ResetRegistration::lift_quarantine`]. Overridden by the `reset.quarantine_after`
+    /// kernel parameter.
+    const QUARANTINE_AFTER_FAILURES: u32 = 0;
+
+    /// Returns the number of lines of the controller, for [`ResetRegistration::register_counted`].
+    ///
+    /// Drivers supporting a family of SoCs compute it at probe time, e.g., from an ID register
+    /// or by counting device tree children.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    fn nr_resets(_dev: &mut platform::Device, _data: &Self::Data) -> Result<u32> {
+        Err(ENOTSUPP)
+    }
+
+    /// Checks the hardware before the controller is registered, e.g., that registers read back
+    /// what is written to them or that the firmware version is supported.
+    ///
+    /// Errors abort the registration, which then fails with them, before any consumer can reach
+    /// the controller.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    fn validate(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>, _nr_resets: u32) -> Result {
+        Ok(())
+    }
+
     /// for self-deasserting resets, does all necessary things to reset the device
-    fn reset(_data:<Self::Data as ForeignOwnable>::Borrowed<'_> , _id: u64) -> Result<i32> {
+    fn reset(_data:<Self::Data as ForeignOwnable>::Borrowed<'_> , _req: ResetRequest) -> Result<i32> {
         Err(ENOTSUPP)
     }
 
     /// manually assert the reset line, if supported
-    fn assert(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>, _id: u64) -> Result<i32> {
+    fn assert(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>, _req: ResetRequest) -> Result<i32> {
         Err(ENOTSUPP)
     }
 
     /// manually deassert the reset line, if supported
-    fn deassert(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>, _id: u64) -> Result<i32> {
+    fn deassert(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>, _req: ResetRequest) -> Result<i32> {
         Err(ENOTSUPP)
     }
 
     /// return the status of the reset line, if supported
-    fn status(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>, _id: u64) -> Result<i32> {
+    fn status(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>, _req: ResetRequest) -> Result<i32> {
+        Err(ENOTSUPP)
+    }
+
+    /// Asserts the lines of `reqs` at once, e.g., through a mask register.
+    ///
+    /// Used instead of [`ResetDriverOps::assert`] on each line by the exclusive, acquired
+    /// [`ResetControlBulk`] and [`ResetControlArray`] whose lines all belong to this controller.
+    /// Shared lines are always operated on one by one, as the C reset core counts their
+    /// deassertions.
+    /// Returning `ENOTSUPP`, e.g., for lines spread over several registers, falls back to the
+    /// operations on each line. Only called from process context.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    fn assert_many(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _reqs: &[ResetRequest],
+    ) -> Result {
+        Err(ENOTSUPP)
+    }
+
+    /// Deasserts the lines of `reqs` at once, see [`ResetDriverOps::assert_many`].
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    fn deassert_many(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _reqs: &[ResetRequest],
+    ) -> Result {
         Err(ENOTSUPP)
     }
+
+    /// Appends provider-specific state, e.g., raw register values, to a device coredump.
+    ///
+    /// See [`ResetDriverOps::COREDUMP_AFTER_FAILURES`]. Called from process context.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    fn coredump(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>, _buf: &mut Vec<u8>) -> Result {
+        Ok(())
+    }
+}
+
+/// An operation of a reset controller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResetOp {
+    /// [`ResetDriverOps::reset`].
+    Reset,
+    /// [`ResetDriverOps::assert`].
+    Assert,
+    /// [`ResetDriverOps::deassert`].
+    Deassert,
+    /// [`ResetDriverOps::status`].
+    Status,
 }
 
 pub(crate) struct Adapter<T:ResetDriverOps>(PhantomData<T>);
@@ -181,74 +1245,406 @@ impl<T: ResetDriverOps> Adapter<T> {
     }
 
     /// Reset Control Operations Vtable
-    const VTABLE: bindings::reset_control_ops = bindings::reset_control_ops {
-        reset: if T::HAS_RESET {
-            Some(Adapter::<T>::reset_callback)
-        } else {
-            None
-        },
-        assert: if T::HAS_ASSERT {
-            Some(Adapter::<T>::assert_callback)
-        } else {
-            None
-        },
-        deassert: if T::HAS_DEASSERT {
-            Some(Adapter::<T>::deassert_callback)
-        } else {
-            None
-        },
-        status: if T::HAS_STATUS {
-            Some(Adapter::<T>::status_callback)
-        } else {
-            None
-        },
+    ///
+    /// Built from an all-zeroes value, so that operations added to `struct reset_control_ops` by
+    /// newer kernels are left unset instead of breaking the build. Support for such an operation
+    /// is added by setting it here under the `cfg` of the kernels that have it.
+    const VTABLE: bindings::reset_control_ops = {
+        let mut ops = zeroed_ops();
+        if T::HAS_RESET {
+            ops.reset = Some(Adapter::<T>::reset_callback);
+        }
+        if T::HAS_ASSERT {
+            ops.assert = Some(Adapter::<T>::assert_callback);
+        }
+        if T::HAS_DEASSERT {
+            ops.deassert = Some(Adapter::<T>::deassert_callback);
+        }
+        if T::HAS_STATUS {
+            ops.status = Some(Adapter::<T>::status_callback);
+        }
+        ops
     };
 
-    unsafe extern "C" fn reset_callback(
+    unsafe extern "C" fn of_xlate_callback(
         rcdev: *mut bindings::reset_controller_dev,
-        id: core::ffi::c_ulong,
+        reset_spec: *const bindings::of_phandle_args,
     ) -> core::ffi::c_int {
         from_result(||{
-            let data_pointer = unsafe { bindings::dev_get_drvdata((*rcdev).dev) };
-            let data = unsafe { T::Data::borrow(data_pointer) };
-            let v = T::reset(data, id)?;
-            Ok(v as _)
+            // SAFETY: The C core passes a valid specifier.
+            let spec = unsafe { OfPhandleArgs::from_raw(reset_spec) };
+            // SAFETY: The C core only calls this for controllers set up by
+            // `ResetRegistration::register`, which are embedded in a registration.
+            let reg = unsafe { &*crate::container_of!(rcdev, ResetRegistration<T>, rcdev) };
+            let xlate = reg.xlate.lock();
+            let nr_resets = reg.nr_resets();
+            let req = xlate.xlate(spec, nr_resets)?;
+            // Table and custom translations may map to lines the controller does not have.
+            if req.id() >= nr_resets as u64 {
+                pr_warn!(
+                    "{} {}: line {} out of range, the controller has {} lines\n",
+                    T::NAME,
+                    reg.dev_name(),
+                    req.id(),
+                    nr_resets
+                );
+                return Err(EINVAL);
+            }
+            let id = core::ffi::c_int::try_from(req.id()).map_err(|_| EINVAL)?;
+            if let Err(e) = reg.line_times.record_flags(req.id(), req.flags()) {
+                pr_warn!(
+                    "{} {}: conflicting flags for line {}\n",
+                    T::NAME,
+                    reg.dev_name(),
+                    req.id()
+                );
+                return Err(e);
+            }
+            Ok(id)
         })
     }
 
+    unsafe extern "C" fn reset_callback(
+        rcdev: *mut bindings::reset_controller_dev,
+        id: core::ffi::c_ulong,
+    ) -> core::ffi::c_int {
+        // SAFETY: The C core passes the controller set up by `ResetRegistration::register`.
+        unsafe { Self::dispatch(rcdev, id, ResetOp::Reset, T::reset) }
+    }
+
     unsafe extern "C" fn assert_callback(
         rcdev: *mut bindings::reset_controller_dev,
         id: core::ffi::c_ulong,
     ) -> core::ffi::c_int {
-        from_result(||{
-            let data_pointer = unsafe { bindings::dev_get_drvdata((*rcdev).dev) };
-            let data = unsafe { T::Data::borrow(data_pointer) };
-            let v = T::assert(data, id)?;
-            Ok(v as _)
-        })
+        // SAFETY: The C core passes the controller set up by `ResetRegistration::register`.
+        unsafe { Self::dispatch(rcdev, id, ResetOp::Assert, T::assert) }
     }
 
     unsafe extern "C" fn deassert_callback(
         rcdev: *mut bindings::reset_controller_dev,
         id: core::ffi::c_ulong,
     ) -> core::ffi::c_int {
-        from_result(||{
-            let data_pointer = unsafe { bindings::dev_get_drvdata((*rcdev).dev) };
-            let data = unsafe { T::Data::borrow(data_pointer) };
-            let v = T::deassert(data, id)?;
-            Ok(v as _)
-        })
+        // SAFETY: The C core passes the controller set up by `ResetRegistration::register`.
+        unsafe { Self::dispatch(rcdev, id, ResetOp::Deassert, T::deassert) }
     }
 
     unsafe extern "C" fn status_callback(
         rcdev: *mut bindings::reset_controller_dev,
         id: core::ffi::c_ulong,
+    ) -> core::ffi::c_int {
+        // SAFETY: The C core passes the controller set up by `ResetRegistration::register`.
+        unsafe { Self::dispatch(rcdev, id, ResetOp::Status, T::status) }
+    }
+
+    /// Runs `action` on line `id` of `rcdev` with the operations of the provider only.
+    ///
+    /// Bypasses the checks, accounting, logging and queueing of [`Adapter::dispatch`], for the
+    /// panic notifier, which must not lock or queue anything.
+    ///
+    /// # Safety
+    ///
+    /// `rcdev` must be the controller embedded in a registered [`ResetRegistration<T>`].
+    unsafe fn call_direct(
+        rcdev: *mut bindings::reset_controller_dev,
+        action: EmergencyAction,
+        id: u64,
+    ) -> Result {
+        // SAFETY: By the safety requirements, `rcdev` is embedded in a registration.
+        let reg = unsafe { &*crate::container_of!(rcdev, ResetRegistration<T>, rcdev) };
+        // SAFETY: `reg` is registered, so `data_pointer` was returned by `into_foreign`.
+        let data = unsafe { T::Data::borrow(reg.data_pointer) };
+        let req = reg.request(id);
+        match action {
+            EmergencyAction::Assert => T::assert(data, req),
+            EmergencyAction::Pulse => T::reset(data, req),
+        }
+        .map(|_| ())
+    }
+
+    /// Calls `f` for operation `op` on line `id` of `rcdev`.
+    ///
+    /// # Safety
+    ///
+    /// `rcdev` must be the controller embedded in a registered [`ResetRegistration<T>`].
+    unsafe fn dispatch(
+        rcdev: *mut bindings::reset_controller_dev,
+        id: core::ffi::c_ulong,
+        op: ResetOp,
+        f: OpFn<T>,
     ) -> core::ffi::c_int {
         from_result(||{
-            let data_pointer = unsafe { bindings::dev_get_drvdata((*rcdev).dev) };
-            let data = unsafe { T::Data::borrow(data_pointer) };
-            let v = T::status(data, id)?;
+            // SAFETY: By the safety requirements, `rcdev` is embedded in a registration.
+            let reg = unsafe { &*crate::container_of!(rcdev, ResetRegistration<T>, rcdev) };
+            let req = reg.request(id as u64);
+            if req.id() >= reg.nr_resets() as u64 {
+                // The line was removed by `ResetRegistration::resize`.
+                return Err(ENODEV);
+            }
+            reg.quarantine.check()?;
+            if let Some(thread) = &reg.thread {
+                return Ok(thread.submit(op, id as u64)? as _);
+            }
+            if (reg.offload.is_some() || T::OPS_SLEEP) && offload::in_atomic_context() {
+                let offload = match &reg.offload {
+                    Some(offload) if op != ResetOp::Status => offload,
+                    // Sleeping here would be a bug, refuse instead.
+                    _ => return Err(EAGAIN),
+                };
+                offload.push(op, id as u64)?;
+                pr_debug!("{} {}: {:?} of line {} queued\n", T::NAME, reg.dev_name(), op, req.id());
+                return Ok(0);
+            }
+            // SAFETY: By the safety requirements, `rcdev` is embedded in a registration.
+            let v = unsafe { Self::call(reg, op, req, f) }?;
             Ok(v as _)
         })
     }
+
+    /// Runs an operation queued by [`Adapter::dispatch`].
+    ///
+    /// # Safety
+    ///
+    /// `rcdev` must be the controller embedded in a registered [`ResetRegistration<T>`].
+    unsafe fn run_queued(
+        rcdev: *mut bindings::reset_controller_dev,
+        op: ResetOp,
+        id: u64,
+    ) -> Result<i32> {
+        // SAFETY: By the safety requirements, `rcdev` is embedded in a registration.
+        let reg = unsafe { &*crate::container_of!(rcdev, ResetRegistration<T>, rcdev) };
+        let f: OpFn<T> = match op {
+            ResetOp::Reset => T::reset,
+            ResetOp::Assert => T::assert,
+            ResetOp::Deassert => T::deassert,
+            ResetOp::Status => T::status,
+        };
+        // SAFETY: By the safety requirements, `rcdev` is embedded in a registration.
+        unsafe { Self::call(reg, op, reg.request(id), f) }
+    }
+
+    /// Calls `f` for operation `op` of `req` on the registered controller `reg`.
+    ///
+    /// # Safety
+    ///
+    /// `reg` must be registered.
+    unsafe fn call(
+        reg: &ResetRegistration<T>,
+        op: ResetOp,
+        req: ResetRequest,
+        f: OpFn<T>,
+    ) -> Result<i32> {
+        // Queued operations may have been submitted before the controller was quarantined.
+        reg.quarantine.check()?;
+        if op == ResetOp::Deassert && reg.blocklist.contains(req.id()) {
+            if reg.blocklist_ratelimit.check().is_some() {
+                pr_warn!(
+                    "{} {}: refusing to deassert blocklisted line {}\n",
+                    T::NAME,
+                    reg.dev_name(),
+                    req.id()
+                );
+            }
+            return Err(EPERM);
+        }
+        // SAFETY: `reg` is registered, so `data_pointer` was returned by `into_foreign`.
+        let data = unsafe { T::Data::borrow(reg.data_pointer) };
+        let start = Self::start_timing(reg);
+        let ret = f(data, req);
+        Self::check_timing(reg, start, op, req.id(), 1);
+        pr_debug!("{} {}: {:?} of line {}: {:?}\n", T::NAME, reg.dev_name(), op, req.id(), ret);
+        reg.history.record(op, req.id(), &ret);
+        let v = match ret {
+            Ok(v) => v,
+            Err(e) => {
+                // SAFETY: `reg` is registered.
+                unsafe { Self::failed(reg, op, req.id(), e) };
+                return Err(e);
+            }
+        };
+        Self::completed(reg, op, req.id());
+        Self::deassert_delay(reg, op);
+        Ok(v)
+    }
+
+    /// Calls [`ResetDriverOps::assert_many`] or [`ResetDriverOps::deassert_many`], for `op`, on
+    /// `reqs`.
+    ///
+    /// Fails with `ENOTSUPP`, for the caller to fall back to the operations on each line, when
+    /// the controller does not implement it or a line cannot go through it.
+    ///
+    /// # Safety
+    ///
+    /// `rcdev` must be the controller embedded in a registered [`ResetRegistration<T>`].
+    unsafe fn call_many(
+        rcdev: *mut bindings::reset_controller_dev,
+        op: ResetOp,
+        reqs: &[ResetRequest],
+    ) -> Result {
+        // SAFETY: By the safety requirements, `rcdev` is embedded in a registration.
+        let reg = unsafe { &*crate::container_of!(rcdev, ResetRegistration<T>, rcdev) };
+        let f: ManyFn<T> = match op {
+            // Operations on several lines would not be ordered with the threaded ones.
+            _ if reg.thread.is_some() => return Err(ENOTSUPP),
+            ResetOp::Assert if T::HAS_ASSERT_MANY => T::assert_many,
+            ResetOp::Deassert if T::HAS_DEASSERT_MANY => T::deassert_many,
+            _ => return Err(ENOTSUPP),
+        };
+        reg.quarantine.check()?;
+        // Removed and blocklisted lines are refused, and reported, by the operations on each
+        // line.
+        let nr_resets = reg.nr_resets() as u64;
+        if reqs.iter().any(|req| {
+            req.id() >= nr_resets || (op == ResetOp::Deassert && reg.blocklist.contains(req.id()))
+        }) {
+            return Err(ENOTSUPP);
+        }
+        // SAFETY: `reg` is registered, so `data_pointer` was returned by `into_foreign`.
+        let data = unsafe { T::Data::borrow(reg.data_pointer) };
+        let start = Self::start_timing(reg);
+        let ret = f(data, reqs);
+        let first = reqs.first().map_or(0, |req| req.id());
+        Self::check_timing(reg, start, op, first, reqs.len());
+        pr_debug!("{} {}: {:?} of {} lines: {:?}\n", T::NAME, reg.dev_name(), op, reqs.len(), ret);
+        if ret == Err(ENOTSUPP) {
+            return ret;
+        }
+        for req in reqs {
+            reg.history.record(op, req.id(), &ret);
+            match ret {
+                Ok(()) => Self::completed(reg, op, req.id()),
+                // SAFETY: `reg` is registered.
+                Err(e) => unsafe { Self::failed(reg, op, req.id(), e) },
+            }
+        }
+        ret?;
+        Self::deassert_delay(reg, op);
+        Ok(())
+    }
+
+    /// Returns the time an operation starts, if slow operations of `reg` are reported.
+    fn start_timing(reg: &ResetRegistration<T>) -> Option<i64> {
+        // SAFETY: `ktime_get` has no safety requirements.
+        (!reg.slow_op.is_zero()).then(|| unsafe { bindings::ktime_get() })
+    }
+
+    /// Reports operation `op` on `count` lines starting with `id` if it took too long since
+    /// `start`.
+    fn check_timing(
+        reg: &ResetRegistration<T>,
+        start: Option<i64>,
+        op: ResetOp,
+        id: u64,
+        count: usize,
+    ) {
+        let start = match start {
+            Some(start) => start,
+            None => return,
+        };
+        // SAFETY: `ktime_get` has no safety requirements.
+        let elapsed = unsafe { bindings::ktime_get() }.saturating_sub(start).max(0) as u64;
+        if elapsed <= reg.slow_op.as_nanos() as u64 || reg.slow_ratelimit.check().is_none() {
+            return;
+        }
+        let name = reg.dev_name();
+        let us = elapsed / 1000;
+        if count > 1 {
+            pr_warn!("{} {}: {:?} of {} lines took {} us\n", T::NAME, name, op, count, us);
+        } else {
+            pr_warn!("{} {}: {:?} of line {} took {} us\n", T::NAME, name, op, id, us);
+        }
+    }
+
+    /// Accounts for the success of operation `op` on line `id`.
+    fn completed(reg: &ResetRegistration<T>, op: ResetOp, id: u64) {
+        reg.line_times.record(op, id);
+        reg.quarantine.succeeded();
+        if let Some(uevents) = &reg.uevents {
+            if op != ResetOp::Status && reg.uevent_lines.contains(&id) {
+                uevents.push(op, id);
+            }
+        }
+    }
+
+    /// Sleeps for the deassert delay set with `reset.deassert_delay_ms`, after a deassert.
+    fn deassert_delay(reg: &ResetRegistration<T>, op: ResetOp) {
+        // The delay cannot be honoured in atomic context, where it is skipped.
+        let delay = reg.deassert_delay;
+        if op == ResetOp::Deassert && !delay.is_zero() && !offload::in_atomic_context() {
+            coarse_sleep(delay);
+        }
+    }
+
+    /// Accounts for failure `e` of operation `op` on line `id`, capturing a coredump if the line
+    /// has failed [`ResetDriverOps::COREDUMP_AFTER_FAILURES`] times in a row, and quarantining
+    /// the controller past [`ResetDriverOps::QUARANTINE_AFTER_FAILURES`].
+    ///
+    /// The failure is logged, rate limited so that consumers retrying in a loop do not flood the
+    /// log. `EAGAIN`, returned while the controller is transiently busy, is not a failure: it is
+    /// only logged at debug level.
+    ///
+    /// # Safety
+    ///
+    /// `reg` must be registered.
+    unsafe fn failed(reg: &ResetRegistration<T>, op: ResetOp, id: u64, e: Error) {
+        if e == EAGAIN {
+            pr_debug!("{} {}: {:?} of line {} busy\n", T::NAME, reg.dev_name(), op, id);
+            return;
+        }
+        let count = reg.line_times.record_failure(id);
+        reg.quarantine.failed(T::NAME, reg.dev_name(), op, id, e);
+        if let Some(missed) = reg.ratelimit.check() {
+            let name = reg.dev_name();
+            if missed != 0 {
+                pr_err!("{} {}: {} reset errors suppressed\n", T::NAME, name, missed);
+            }
+            pr_err!("{} {}: {:?} of line {} failed: {:?}\n", T::NAME, name, op, id, e);
+        }
+        if T::COREDUMP_AFTER_FAILURES == 0 || count != T::COREDUMP_AFTER_FAILURES {
+            return;
+        }
+        let dev = match &reg.dev {
+            Some(dev) => dev,
+            None => return,
+        };
+        if offload::in_atomic_context() {
+            pr_warn!(
+                "{} {}: line {} keeps failing, no coredump in atomic context\n",
+                T::NAME,
+                dev.name(),
+                id
+            );
+            return;
+        }
+
+        let mut blob = Vec::new();
+        // SAFETY: `reg` is registered, so `data_pointer` was returned by `into_foreign`.
+        let data = unsafe { T::Data::borrow(reg.data_pointer) };
+        if let Err(e) = T::coredump(data, &mut blob) {
+            pr_warn!("{} {}: provider coredump failed: {:?}\n", T::NAME, dev.name(), e);
+            blob.clear();
+        }
+        let xlate = *reg.xlate.lock();
+        let failure = coredump::Failure {
+            driver: T::NAME,
+            op,
+            id,
+            count,
+            xlate: &xlate,
+            times: &reg.line_times,
+            history: &reg.history,
+        };
+        coredump::capture(dev, &failure, &blob);
+    }
 }
+
+/// Signature of the operations of [`ResetDriverOps`].
+type OpFn<T> = for<'a> fn(
+    <<T as ResetDriverOps>::Data as ForeignOwnable>::Borrowed<'a>,
+    ResetRequest,
+) -> Result<i32>;
+
+/// Signature of the operations of [`ResetDriverOps`] on several lines.
+type ManyFn<T> = for<'a, 'b> fn(
+    <<T as ResetDriverOps>::Data as ForeignOwnable>::Borrowed<'a>,
+    &'b [ResetRequest],
+) -> Result;
@@ -0,0 +1,108 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Consumer lines described by ACPI `_DSD` device properties.
+//!
+//! The C reset core only resolves lines through device tree nodes and lookup entries. On ACPI
+//! platforms, consumers describe their lines with the same `resets` and `reset-names` properties
+//! as on device tree, in the `_DSD` of their device:
+//!
+//! ```text
+//! Name (_DSD, Package () {
+//!     ToUUID ("daffd814-6eba-4d8c-8a91-bc9bbf4aa301"),
+//!     Package () {
+//!         Package () { "resets", Package () { ^RST0, 3 } },
+//!         Package () { "reset-names", Package () { "phy" } },
+//!     }
+//! })
+//! ```
+//!
+//! The consumer getters turn such references into lookup entries before getting the lines, so
+//! drivers use the same getters as on device tree. References take a single argument, the line
+//! id, and only point to controllers registered from Rust, found through the registry.
+
+use super::{lookup, registry};
+use crate::{
+    bindings, c_str,
+    device::RawDevice,
+    error::{code::*, to_result, Result},
+    str::CStr,
+};
+
+/// Adds the lookup entry of the line of `dev` named `id` in its `_DSD`, if there is one.
+///
+/// Does nothing for devices with a device tree node or without such a line, leaving the C reset
+/// core to report missing lines. Fails with `EPROBE_DEFER` while the controller is not
+/// registered.
+pub(crate) fn add_dsd_lookup(dev: &impl RawDevice, id: Option<&CStr>) -> Result {
+    let raw_dev = dev.raw_device();
+    // SAFETY: `raw_dev` is a valid device.
+    if !unsafe { (*raw_dev).of_node }.is_null() {
+        return Ok(());
+    }
+    // SAFETY: `raw_dev` is a valid device.
+    let fwnode = unsafe { bindings::dev_fwnode(raw_dev) };
+    let resets = c_str!("resets").as_char_ptr();
+    // SAFETY: `fwnode` is valid, and `resets` is a valid C string.
+    if fwnode.is_null() || !unsafe { bindings::fwnode_property_present(fwnode, resets) } {
+        return Ok(());
+    }
+
+    let index = match id {
+        Some(id) => {
+            // SAFETY: `fwnode` is valid, and both strings are valid C strings.
+            let ret = unsafe {
+                bindings::fwnode_property_match_string(
+                    fwnode,
+                    c_str!("reset-names").as_char_ptr(),
+                    id.as_char_ptr(),
+                )
+            };
+            if ret < 0 {
+                return Ok(());
+            }
+            ret as u32
+        }
+        None => 0,
+    };
+
+    // SAFETY: `fwnode_reference_args` is a plain C struct, for which all zeroes is valid.
+    let mut args: bindings::fwnode_reference_args = unsafe { core::mem::zeroed() };
+    // SAFETY: `fwnode` is valid, `resets` is a valid C string and `args` is writable.
+    let ret = to_result(unsafe {
+        bindings::fwnode_property_get_reference_args(
+            fwnode,
+            resets,
+            core::ptr::null(),
+            1,
+            index,
+            &mut args,
+        )
+    });
+    match ret {
+        Ok(()) => {}
+        Err(ENOENT) => return Ok(()),
+        Err(e) => return Err(e),
+    }
+
+    let line = if args.nargs > 0 { args.args[0] } else { 0 };
+    let ret = (|| {
+        let controllers = registry::registered_controllers();
+        let rcdev = controllers
+            .find_by_fwnode(args.fwnode)
+            .ok_or(EPROBE_DEFER)?;
+        if line >= rcdev.nr_resets() as u64 {
+            return Err(EINVAL);
+        }
+        // SAFETY: `raw_dev` and the device of the registered controller are valid.
+        let (provider, dev_id) = unsafe {
+            (
+                CStr::from_char_ptr(bindings::dev_name((*rcdev.as_ptr()).dev)),
+                CStr::from_char_ptr(bindings::dev_name(raw_dev)),
+            )
+        };
+        lookup::add_owned(provider, line as u32, dev_id, id)
+    })();
+    // SAFETY: `fwnode_property_get_reference_args` returned a reference to `args.fwnode`.
+    unsafe { bindings::fwnode_handle_put(args.fwnode) };
+    ret
+}
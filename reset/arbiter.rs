@@ -0,0 +1,179 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Voting on one exclusive line between several in-kernel clients.
+//!
+//! Shared controls only let sharers keep a line deasserted. A [`ResetArbiter`] fronts an
+//! exclusive control instead, and lets each of its clients vote for the line to be asserted or
+//! deasserted, or abstain. The line is asserted while any client votes for it, deasserted while
+//! at least one client votes for it and none votes against, and left alone while all abstain:
+//!
+//! ```ignore
+//! let arbiter = ResetArbiter::try_new(ResetControl::get_exclusive(&pdev, None)?)?;
+//! let loader = ResetArbiter::add_client(&arbiter, None)?;
+//! let monitor = ResetArbiter::add_client(&arbiter, Some(Box::try_new(|asserted| {
+//!     pr_info!("line asserted: {}\n", asserted);
+//! })?))?;
+//!
+//! monitor.vote(Vote::Deassert)?;
+//! loader.vote(Vote::Assert)?; // Stays asserted until the firmware is loaded.
+//! loader.vote(Vote::Abstain)?;
+//! ```
+
+use super::ExclusiveResetControl;
+use crate::{
+    error::Result,
+    sync::{smutex::Mutex, Arc},
+};
+
+use alloc::{boxed::Box, vec::Vec};
+
+/// Called with the new state of the line (`true` when asserted) after it changed.
+///
+/// Runs with the arbiter locked, so it must not vote.
+pub type ArbiterNotify = Box<dyn Fn(bool) + Send + Sync>;
+
+/// The vote of a client of a [`ResetArbiter`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Vote {
+    /// The client does not care about the state of the line.
+    Abstain,
+    /// The client needs the line asserted.
+    Assert,
+    /// The client needs the line deasserted.
+    Deassert,
+}
+
+struct Client {
+    id: u32,
+    vote: Vote,
+    notify: Option<ArbiterNotify>,
+}
+
+struct Inner {
+    clients: Vec<Client>,
+    next_id: u32,
+    /// The state the arbiter last put the line in, `None` before the first change.
+    asserted: Option<bool>,
+}
+
+impl Inner {
+    /// Returns the state the votes ask for, `None` when all clients abstain.
+    fn outcome(&self) -> Option<bool> {
+        let votes = || self.clients.iter().map(|c| c.vote);
+        if votes().any(|v| v == Vote::Assert) {
+            Some(true)
+        } else if votes().any(|v| v == Vote::Deassert) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+/// An exclusive line whose state is decided by the votes of its clients.
+pub struct ResetArbiter {
+    control: ExclusiveResetControl,
+    inner: Mutex<Inner>,
+}
+
+impl ResetArbiter {
+    /// Creates an arbiter, without clients, driving `control`.
+    pub fn try_new(control: ExclusiveResetControl) -> Result<Arc<Self>> {
+        Arc::try_new(Self {
+            control,
+            inner: Mutex::new(Inner {
+                clients: Vec::new(),
+                next_id: 0,
+                asserted: None,
+            }),
+        })
+    }
+
+    /// Adds a client to `arbiter`, initially abstaining, notified of changes with `notify`.
+    pub fn add_client(arbiter: &Arc<Self>, notify: Option<ArbiterNotify>) -> Result<ArbiterClient> {
+        let mut inner = arbiter.inner.lock();
+        let id = inner.next_id;
+        inner.clients.try_push(Client {
+            id,
+            vote: Vote::Abstain,
+            notify,
+        })?;
+        inner.next_id = id.wrapping_add(1);
+        Ok(ArbiterClient {
+            arbiter: arbiter.clone(),
+            id,
+        })
+    }
+
+    /// Returns whether the arbiter put the line in the asserted state, `None` if it never changed
+    /// it.
+    pub fn asserted(&self) -> Option<bool> {
+        self.inner.lock().asserted
+    }
+
+    /// Sets the vote of client `id`, or removes it when `vote` is `None`, and applies the outcome
+    /// to the line.
+    ///
+    /// If the line cannot be changed, the vote is reverted and the error returned. Clients are
+    /// removed regardless.
+    fn set_vote(&self, id: u32, vote: Option<Vote>) -> Result {
+        let mut inner = self.inner.lock();
+        let index = match inner.clients.iter().position(|c| c.id == id) {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        let old = inner.clients[index].vote;
+        inner.clients[index].vote = vote.unwrap_or(Vote::Abstain);
+
+        let outcome = inner.outcome();
+        if outcome.is_some() && outcome != inner.asserted {
+            let ret = match outcome {
+                Some(true) => self.control.assert(),
+                _ => self.control.deassert(),
+            };
+            if let Err(e) = ret {
+                match vote {
+                    Some(_) => inner.clients[index].vote = old,
+                    None => drop(inner.clients.remove(index)),
+                }
+                return Err(e);
+            }
+            inner.asserted = outcome;
+            for notify in inner.clients.iter().filter_map(|c| c.notify.as_ref()) {
+                notify(outcome == Some(true));
+            }
+        }
+        if vote.is_none() {
+            inner.clients.remove(index);
+        }
+        Ok(())
+    }
+}
+
+/// A client of a [`ResetArbiter`], whose vote is withdrawn when dropped.
+pub struct ArbiterClient {
+    arbiter: Arc<ResetArbiter>,
+    id: u32,
+}
+
+impl ArbiterClient {
+    /// Casts `vote`, changing the line if the outcome changes.
+    ///
+    /// Fails, keeping the previous vote, if the line cannot be changed.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn vote(&self, vote: Vote) -> Result {
+        self.arbiter.set_vote(self.id, Some(vote))
+    }
+
+    /// Returns the arbiter of the client.
+    pub fn arbiter(&self) -> &Arc<ResetArbiter> {
+        &self.arbiter
+    }
+}
+
+impl Drop for ArbiterClient {
+    fn drop(&mut self) {
+        // Withdrawing the vote removes the client even if the line cannot be changed.
+        let _ = self.arbiter.set_vote(self.id, None);
+    }
+}
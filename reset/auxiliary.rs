@@ -0,0 +1,73 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Consumer lines of auxiliary devices.
+//!
+//! Drivers split into an auxiliary-bus core and function drivers (e.g., the Ethernet function of
+//! a multi-function controller) describe the reset lines on the device of the core, auxiliary
+//! devices having no firmware node of their own. The getters of this module resolve the lines
+//! of an auxiliary device on its closest ancestor with a firmware node:
+//!
+//! ```ignore
+//! let rstc = ExclusiveResetControl::get_exclusive_auxiliary(adev, Some(c_str!("mac")))?;
+//! ```
+//!
+//! The lines are then held on behalf of that ancestor, so exclusive lines already held by the
+//! core driver fail with `EBUSY`, and shared lines count as one more sharer.
+
+use super::{Exclusive, ResetControl, Shared};
+use crate::{
+    bindings,
+    device::{self, RawDevice},
+    error::{code::*, Result},
+    str::CStr,
+};
+
+/// Returns the closest ancestor of `dev`, or `dev` itself, that has a firmware node.
+///
+/// Fails with `ENODEV` if there is none.
+pub fn described_device(dev: &impl RawDevice) -> Result<device::Device> {
+    let mut raw = dev.raw_device();
+    while !raw.is_null() {
+        // SAFETY: `raw` is `dev` or one of its ancestors, which outlive it.
+        if !unsafe { bindings::dev_fwnode(raw) }.is_null() {
+            // SAFETY: `raw` is valid, as above.
+            return Ok(unsafe { device::Device::new(raw) });
+        }
+        // SAFETY: `raw` is valid, as above.
+        raw = unsafe { (*raw).parent };
+    }
+    Err(ENODEV)
+}
+
+impl ResetControl<Exclusive> {
+    /// Gets an exclusive reference to the reset line named `id` of auxiliary device `adev`,
+    /// described on its closest ancestor with a firmware node.
+    pub fn get_exclusive_auxiliary(adev: &impl RawDevice, id: Option<&CStr>) -> Result<Self> {
+        Self::get_exclusive(&described_device(adev)?, id)
+    }
+
+    /// Like [`ResetControl::get_exclusive_auxiliary`], but returns `None` if there is no such
+    /// line.
+    pub fn get_optional_exclusive_auxiliary(
+        adev: &impl RawDevice,
+        id: Option<&CStr>,
+    ) -> Result<Option<Self>> {
+        Self::get_optional_exclusive(&described_device(adev)?, id)
+    }
+}
+
+impl ResetControl<Shared> {
+    /// Gets a shared reference to the reset line named `id` of auxiliary device `adev`,
+    /// described on its closest ancestor with a firmware node.
+    pub fn get_shared_auxiliary(adev: &impl RawDevice, id: Option<&CStr>) -> Result<Self> {
+        Self::get_shared(&described_device(adev)?, id)
+    }
+
+    /// Like [`ResetControl::get_shared_auxiliary`], but returns `None` if there is no such line.
+    pub fn get_optional_shared_auxiliary(
+        adev: &impl RawDevice,
+        id: Option<&CStr>,
+    ) -> Result<Option<Self>> {
+        Self::get_optional_shared(&described_device(adev)?, id)
+    }
+}
@@ -0,0 +1,63 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Lines that must never be deasserted, for silicon bring-up.
+//!
+//! Drivers opt in by passing the value of one of their module parameters to
+//! [`ResetRegistration::set_deassert_blocklist`]:
+//!
+//! ```ignore
+//! module_platform_driver! {
+//!     type: MyReset,
+//!     name: "my_reset",
+//!     license: "GPL",
+//!     params: {
+//!         blocked_lines: str {
+//!             default: b"",
+//!             permissions: 0o444,
+//!             description: "Comma-separated reset line ids or names never to deassert",
+//!         },
+//!     },
+//! }
+//!
+//! let lock = THIS_MODULE.kernel_param_lock();
+//! reg.as_mut().set_deassert_blocklist(blocked_lines.read(&lock))?;
+//! ```
+//!
+//! [`ResetRegistration::set_deassert_blocklist`]: super::ResetRegistration::set_deassert_blocklist
+
+use super::OfXlate;
+use crate::error::{code::*, Result};
+
+use alloc::vec::Vec;
+
+/// A list of lines on which deasserts are refused.
+pub(crate) struct DeassertBlocklist(Vec<u64>);
+
+impl DeassertBlocklist {
+    /// Creates an empty list.
+    pub(crate) const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Parses a comma-separated list of line ids or names.
+    ///
+    /// Names are resolved with the line names known to `xlate`. Fails with `EINVAL` on entries
+    /// that are neither a number nor a known name.
+    pub(crate) fn parse(list: &[u8], xlate: &OfXlate) -> Result<Self> {
+        let list = core::str::from_utf8(list).map_err(|_| EINVAL)?;
+        let mut ids = Vec::new();
+        for entry in list.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let id = match entry.parse::<u64>() {
+                Ok(id) => id,
+                Err(_) => xlate.id_of(entry).ok_or(EINVAL)?,
+            };
+            ids.try_push(id)?;
+        }
+        Ok(Self(ids))
+    }
+
+    /// Returns whether line `id` is blocked.
+    pub(crate) fn contains(&self, id: u64) -> bool {
+        self.0.contains(&id)
+    }
+}
@@ -0,0 +1,575 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Bulk reset controller consumers.
+//!
+//! C header: [`include/linux/reset.h`](../../../../include/linux/reset.h)
+
+use super::{
+    of::OfSpec,
+    offload, registry,
+    topology::{self, TopologyHandle},
+    Exclusive, ResetControl, ResetFlags, ResetKind, ResetOp, ResetRequest, Shared,
+};
+use crate::{
+    bindings,
+    device::RawDevice,
+    error::{to_result, Result},
+    str::CStr,
+};
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+/// The lines of a set of exclusive lines that all belong to one controller registered from Rust
+/// with operations on several lines, see [`ResetDriverOps::assert_many`].
+///
+/// The operations on several lines call the controller directly, bypassing the `acquired` and
+/// `deassert_count` accounting of the C reset core. That is only sound for exclusive acquired
+/// lines, which no other consumer can operate on: shared lines always go through the C reset
+/// core, one by one, so that a sharer cannot assert a line under the feet of the others.
+///
+/// # Invariants
+///
+/// `np` is null or holds a reference to the device tree node of the controller, and the lines
+/// are exclusive and acquired.
+///
+/// [`ResetDriverOps::assert_many`]: super::ResetDriverOps::assert_many
+struct ManyPath<R> {
+    np: *mut bindings::device_node,
+    reqs: R,
+}
+
+impl<R: AsRef<[ResetRequest]> + AsMut<[ResetRequest]>> ManyPath<R> {
+    /// Translates the lines called `names` of `dev` into `reqs`, if they all belong to one such
+    /// controller and are exclusive and `acquired`.
+    fn resolve<K: ResetKind>(
+        dev: &impl RawDevice,
+        names: &[&'static CStr],
+        acquired: bool,
+        reqs: R,
+    ) -> Option<Self> {
+        if K::SHARED || !acquired {
+            return None;
+        }
+        // INVARIANT: The lines are exclusive and acquired.
+        let mut this = Self {
+            np: core::ptr::null_mut(),
+            reqs,
+        };
+        for (name, req) in names.iter().zip(this.reqs.as_mut()) {
+            let spec = OfSpec::parse(dev, Some(name))?;
+            if this.np.is_null() {
+                // INVARIANT: The reference is released when `this` is dropped.
+                // SAFETY: `spec` holds a reference to its node.
+                this.np = unsafe { bindings::of_node_get(spec.node()) };
+            } else if spec.node() != this.np {
+                return None;
+            }
+            *req = registry::many_request(&spec)?;
+        }
+        (!this.np.is_null()).then_some(this)
+    }
+
+    /// Runs `op` on all lines at once, returns `None` if the lines have to be operated on one by
+    /// one instead.
+    fn run(&self, op: ResetOp) -> Option<Result> {
+        // The registry lock sleeps.
+        if offload::in_atomic_context() {
+            return None;
+        }
+        registry::call_many(self.np, op, self.reqs.as_ref())
+    }
+}
+
+impl<R> Drop for ManyPath<R> {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, `np` is null or we own a reference to it.
+        unsafe { bindings::of_node_put(self.np) };
+    }
+}
+
+// SAFETY: The node is only compared against those of the controllers, and released.
+unsafe impl<R: Send> Send for ManyPath<R> {}
+
+// SAFETY: The node is never dereferenced through a shared reference.
+unsafe impl<R: Sync> Sync for ManyPath<R> {}
+
+/// A set of reset lines of one consumer device, operated on together.
+///
+/// # Invariants
+///
+/// Every `rstc` in `data` is a valid pointer returned by the C reset core, or null for optional
+/// lines that are missing, which the C reset core treats as no-ops. The references are released
+/// when [`ResetControlBulk`] is dropped.
+pub struct ResetControlBulk<K: ResetKind = Exclusive> {
+    data: Vec<bindings::reset_control_bulk_data>,
+    /// The topology handle of each line of `data`, if recorded.
+    topology: Vec<Option<TopologyHandle>>,
+    many: Option<ManyPath<Vec<ResetRequest>>>,
+    _kind: PhantomData<K>,
+}
+
+impl<K: ResetKind> ResetControlBulk<K> {
+    fn get(
+        dev: &impl RawDevice,
+        names: &[&'static CStr],
+        optional: bool,
+        acquired: bool,
+    ) -> Result<Self> {
+        let mut data = Vec::try_with_capacity(names.len())?;
+        for name in names {
+            #[cfg(CONFIG_ACPI)]
+            super::acpi::add_dsd_lookup(dev, Some(name))?;
+            data.try_push(bindings::reset_control_bulk_data {
+                id: name.as_char_ptr(),
+                rstc: core::ptr::null_mut(),
+            })?;
+        }
+        // Everything is allocated before the get, which would leak its references on failure.
+        let mut handles = Vec::try_with_capacity(names.len())?;
+        let mut reqs = Vec::new();
+        if !K::SHARED && acquired {
+            for _ in names {
+                reqs.try_push(ResetRequest::new(0, ResetFlags::empty()))?;
+            }
+        }
+
+        // SAFETY: `dev` is a valid device and `data` has `names.len()` entries whose ids are
+        // valid C strings that live forever.
+        to_result(unsafe {
+            bindings::__reset_control_bulk_get(
+                dev.raw_device(),
+                data.len() as _,
+                data.as_mut_ptr(),
+                K::SHARED,
+                optional,
+                acquired,
+            )
+        })?;
+
+        for name in names {
+            // The topology is best effort, a line missing from it is not an error.
+            let spec = OfSpec::parse(dev, Some(name));
+            let handle = spec.and_then(|spec| topology::record(dev, &spec, K::SHARED));
+            // There is room for all handles, so this cannot fail.
+            let _ = handles.try_push(handle);
+        }
+
+        let many = ManyPath::resolve::<K>(dev, names, acquired, reqs);
+
+        // INVARIANT: On success the C reset core filled in all the `rstc` pointers.
+        Ok(Self {
+            data,
+            topology: handles,
+            many,
+            _kind: PhantomData,
+        })
+    }
+
+    /// Returns the number of lines in the set.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Fails with `ENODEV` once the controller of a line was unregistered, see
+    /// [`ResetControl::on_provider_removed`].
+    ///
+    /// [`ResetControl::on_provider_removed`]: super::ResetControl::on_provider_removed
+    fn check_providers(&self) -> Result {
+        self.topology.iter().flatten().try_for_each(|t| t.check_not_removed())
+    }
+
+    /// Triggers a self-deasserting reset of all lines.
+    pub fn reset_all(&self) -> Result {
+        self.check_providers()?;
+        // SAFETY: By the type invariants, all entries of `data` are valid.
+        to_result(unsafe {
+            bindings::reset_control_bulk_reset(self.data.len() as _, self.data.as_ptr() as *mut _)
+        })
+    }
+}
+
+impl ResetControlBulk<Exclusive> {
+    /// Gets exclusive references to the reset lines called `names` of `dev`.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn get_exclusive(dev: &impl RawDevice, names: &[&'static CStr]) -> Result<Self> {
+        Self::get(dev, names, false, true)
+    }
+
+    /// Like [`ResetControlBulk::get_exclusive`], but lines missing from the device tree are
+    /// left out instead of failing.
+    ///
+    /// Operations on the set skip the missing lines, so boards that lack some of them use the
+    /// same code path.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn get_optional_exclusive(dev: &impl RawDevice, names: &[&'static CStr]) -> Result<Self> {
+        Self::get(dev, names, true, true)
+    }
+
+    /// Asserts all lines.
+    ///
+    /// Lines that all belong to one controller with [`ResetDriverOps::assert_many`] are asserted
+    /// at once.
+    ///
+    /// [`ResetDriverOps::assert_many`]: super::ResetDriverOps::assert_many
+    pub fn assert_all(&self) -> Result {
+        self.check_providers()?;
+        if let Some(ret) = self.many.as_ref().and_then(|m| m.run(ResetOp::Assert)) {
+            return ret;
+        }
+        // SAFETY: By the type invariants, all entries of `data` are valid.
+        to_result(unsafe {
+            bindings::reset_control_bulk_assert(self.data.len() as _, self.data.as_ptr() as *mut _)
+        })
+    }
+
+    /// Deasserts all lines.
+    ///
+    /// Lines that all belong to one controller with [`ResetDriverOps::deassert_many`] are
+    /// deasserted at once.
+    ///
+    /// [`ResetDriverOps::deassert_many`]: super::ResetDriverOps::deassert_many
+    pub fn deassert_all(&self) -> Result {
+        self.check_providers()?;
+        if let Some(ret) = self.many.as_ref().and_then(|m| m.run(ResetOp::Deassert)) {
+            return ret;
+        }
+        // SAFETY: By the type invariants, all entries of `data` are valid.
+        to_result(unsafe {
+            bindings::reset_control_bulk_deassert(
+                self.data.len() as _,
+                self.data.as_ptr() as *mut _,
+            )
+        })
+    }
+
+    /// Deasserts all lines and returns a guard that asserts them again when dropped.
+    ///
+    /// This is meant for probe error paths: on success, the caller keeps the lines deasserted
+    /// with [`BulkDeassertGuard::keep`].
+    pub fn deassert_all_guarded(&self) -> Result<BulkDeassertGuard<'_>> {
+        self.deassert_all()?;
+        Ok(BulkDeassertGuard { bulk: self })
+    }
+}
+
+impl ResetControlBulk<Shared> {
+    /// Gets shared references to the reset lines called `names` of `dev`.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn get_shared(dev: &impl RawDevice, names: &[&'static CStr]) -> Result<Self> {
+        Self::get(dev, names, false, false)
+    }
+
+    /// Like [`ResetControlBulk::get_shared`], but lines missing from the device tree are left
+    /// out instead of failing.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn get_optional_shared(dev: &impl RawDevice, names: &[&'static CStr]) -> Result<Self> {
+        Self::get(dev, names, true, false)
+    }
+
+    /// Deasserts all lines on behalf of this sharer.
+    ///
+    /// The lines are kept deasserted until every returned [`BulkSharedDeassert`] is dropped, so
+    /// a sharer can never assert the lines under the feet of the others. The lines are always
+    /// deasserted one by one through the C reset core, which counts the deassertions, even if
+    /// their controller has [`ResetDriverOps::deassert_many`].
+    ///
+    /// [`ResetDriverOps::deassert_many`]: super::ResetDriverOps::deassert_many
+    pub fn deassert_all(&self) -> Result<BulkSharedDeassert<'_>> {
+        self.check_providers()?;
+        // SAFETY: By the type invariants, all entries of `data` are valid.
+        to_result(unsafe {
+            bindings::reset_control_bulk_deassert(
+                self.data.len() as _,
+                self.data.as_ptr() as *mut _,
+            )
+        })?;
+        Ok(BulkSharedDeassert { bulk: self })
+    }
+}
+
+impl<K: ResetKind> Drop for ResetControlBulk<K> {
+    fn drop(&mut self) {
+        if self.check_providers().is_ok() {
+            // SAFETY: By the type invariants, all entries of `data` are valid and we own
+            // references to them.
+            unsafe {
+                bindings::reset_control_bulk_put(self.data.len() as _, self.data.as_mut_ptr())
+            };
+            return;
+        }
+        // Putting the lines of unregistered controllers would go through them, they are leaked.
+        for (data, handle) in self.data.iter().zip(&self.topology) {
+            if handle.as_ref().map_or(false, |h| h.is_removed()) {
+                continue;
+            }
+            // SAFETY: By the type invariants, `rstc` is valid or null and we own a reference to
+            // it.
+            unsafe { bindings::reset_control_put(data.rstc) };
+        }
+    }
+}
+
+// SAFETY: The C reset core serializes operations on a `reset_control` internally, so the set can
+// be used and released from any thread.
+unsafe impl<K: ResetKind> Send for ResetControlBulk<K> {}
+
+// SAFETY: All methods taking `&self` are safe to call concurrently, the C reset core handles the
+// required synchronization.
+unsafe impl<K: ResetKind> Sync for ResetControlBulk<K> {}
+
+/// Deassertion of a set of lines, returned by [`ResetControlBulk::deassert_all_guarded`].
+///
+/// Asserts all lines again, in reverse order, when dropped.
+pub struct BulkDeassertGuard<'a> {
+    bulk: &'a ResetControlBulk<Exclusive>,
+}
+
+impl BulkDeassertGuard<'_> {
+    /// Keeps the lines deasserted.
+    pub fn keep(self) {
+        core::mem::forget(self);
+    }
+}
+
+impl Drop for BulkDeassertGuard<'_> {
+    fn drop(&mut self) {
+        if self.bulk.check_providers().is_err() {
+            return;
+        }
+        for data in self.bulk.data.iter().rev() {
+            // SAFETY: By the type invariants of `ResetControlBulk`, `rstc` is valid.
+            let _ = unsafe { bindings::reset_control_assert(data.rstc) };
+        }
+    }
+}
+
+/// A deassertion of a set of shared lines, returned by [`ResetControlBulk::deassert_all`].
+///
+/// Gives up the deassertion when dropped.
+pub struct BulkSharedDeassert<'a> {
+    bulk: &'a ResetControlBulk<Shared>,
+}
+
+impl Drop for BulkSharedDeassert<'_> {
+    fn drop(&mut self) {
+        if self.bulk.check_providers().is_err() {
+            return;
+        }
+        // The C core only asserts a line once the last sharer gives up its deassertion.
+        // SAFETY: By the type invariants of `ResetControlBulk`, all entries of `data` are valid.
+        let _ = unsafe {
+            bindings::reset_control_bulk_assert(
+                self.bulk.data.len() as _,
+                self.bulk.data.as_ptr() as *mut _,
+            )
+        };
+    }
+}
+
+/// A fixed-size set of reset lines of one consumer device, operated on together.
+///
+/// Unlike [`ResetControlBulk`], it never allocates, so it can be used on memory-constrained
+/// systems. The lines are not recorded in the topology, so the removal of their controller is
+/// not detected: the array must be dropped before the controller is unregistered.
+///
+/// # Invariants
+///
+/// Every `rstc` in `data` is a valid pointer returned by the C reset core, and the references are
+/// released when [`ResetControlArray`] is dropped.
+pub struct ResetControlArray<const N: usize, K: ResetKind = Exclusive> {
+    data: [bindings::reset_control_bulk_data; N],
+    many: Option<ManyPath<[ResetRequest; N]>>,
+    _kind: PhantomData<K>,
+}
+
+impl<const N: usize, K: ResetKind> ResetControlArray<N, K> {
+    fn get(
+        dev: &impl RawDevice,
+        names: &[&'static CStr; N],
+        optional: bool,
+        acquired: bool,
+    ) -> Result<Self> {
+        #[cfg(CONFIG_ACPI)]
+        for name in names {
+            super::acpi::add_dsd_lookup(dev, Some(name))?;
+        }
+        let mut data = names.map(|name| bindings::reset_control_bulk_data {
+            id: name.as_char_ptr(),
+            rstc: core::ptr::null_mut(),
+        });
+
+        // SAFETY: `dev` is a valid device and `data` has `N` entries whose ids are valid C
+        // strings that live forever.
+        to_result(unsafe {
+            bindings::__reset_control_bulk_get(
+                dev.raw_device(),
+                N as _,
+                data.as_mut_ptr(),
+                K::SHARED,
+                optional,
+                acquired,
+            )
+        })?;
+
+        let reqs = [ResetRequest::new(0, ResetFlags::empty()); N];
+        let many = ManyPath::resolve::<K>(dev, names, acquired, reqs);
+
+        // INVARIANT: On success the C reset core filled in all the `rstc` pointers.
+        Ok(Self {
+            data,
+            many,
+            _kind: PhantomData,
+        })
+    }
+
+    /// Returns the number of lines in the set.
+    pub const fn len(&self) -> usize {
+        N
+    }
+
+    /// Returns whether the set is empty.
+    pub const fn is_empty(&self) -> bool {
+        N == 0
+    }
+
+    /// Triggers a self-deasserting reset of all lines.
+    pub fn reset_all(&self) -> Result {
+        // SAFETY: By the type invariants, all entries of `data` are valid.
+        to_result(unsafe {
+            bindings::reset_control_bulk_reset(N as _, self.data.as_ptr() as *mut _)
+        })
+    }
+}
+
+impl<const N: usize> ResetControlArray<N, Exclusive> {
+    /// Gets exclusive references to the reset lines called `names` of `dev`.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn get_exclusive(dev: &impl RawDevice, names: &[&'static CStr; N]) -> Result<Self> {
+        Self::get(dev, names, false, true)
+    }
+
+    /// Like [`ResetControlArray::get_exclusive`], but lines the device does not have are left
+    /// out, and operations ignore them.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn get_optional_exclusive(
+        dev: &impl RawDevice,
+        names: &[&'static CStr; N],
+    ) -> Result<Self> {
+        Self::get(dev, names, true, true)
+    }
+
+    /// Asserts all lines, at once if they allow it, see [`ResetControlBulk::assert_all`].
+    pub fn assert_all(&self) -> Result {
+        if let Some(ret) = self.many.as_ref().and_then(|m| m.run(ResetOp::Assert)) {
+            return ret;
+        }
+        // SAFETY: By the type invariants, all entries of `data` are valid.
+        to_result(unsafe {
+            bindings::reset_control_bulk_assert(N as _, self.data.as_ptr() as *mut _)
+        })
+    }
+
+    /// Deasserts all lines, at once if they allow it, see [`ResetControlBulk::deassert_all`].
+    pub fn deassert_all(&self) -> Result {
+        if let Some(ret) = self.many.as_ref().and_then(|m| m.run(ResetOp::Deassert)) {
+            return ret;
+        }
+        // SAFETY: By the type invariants, all entries of `data` are valid.
+        to_result(unsafe {
+            bindings::reset_control_bulk_deassert(N as _, self.data.as_ptr() as *mut _)
+        })
+    }
+}
+
+impl<const N: usize> ResetControlArray<N, Shared> {
+    /// Gets shared references to the reset lines called `names` of `dev`.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn get_shared(dev: &impl RawDevice, names: &[&'static CStr; N]) -> Result<Self> {
+        Self::get(dev, names, false, false)
+    }
+
+    /// Like [`ResetControlArray::get_shared`], but lines the device does not have are left out,
+    /// and operations ignore them.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn get_optional_shared(dev: &impl RawDevice, names: &[&'static CStr; N]) -> Result<Self> {
+        Self::get(dev, names, true, false)
+    }
+}
+
+impl<const N: usize, K: ResetKind> Drop for ResetControlArray<N, K> {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, all entries of `data` are valid and we own references
+        // to them.
+        unsafe { bindings::reset_control_bulk_put(N as _, self.data.as_mut_ptr()) };
+    }
+}
+
+// SAFETY: The C reset core serializes operations on a `reset_control` internally, so the set can
+// be used and released from any thread.
+unsafe impl<const N: usize, K: ResetKind> Send for ResetControlArray<N, K> {}
+
+// SAFETY: All methods taking `&self` are safe to call concurrently, the C reset core handles the
+// required synchronization.
+unsafe impl<const N: usize, K: ResetKind> Sync for ResetControlArray<N, K> {}
+
+/// Gets the lines called `names` of `dev`, one [`ResetControl`] per line.
+///
+/// Missing lines are `None` if `optional` is set, and fail with `ENOENT` otherwise. Lines are
+/// no longer got after a failure, and the ones already got are released.
+fn get_array<const N: usize, K: ResetKind>(
+    dev: &impl RawDevice,
+    names: &[&'static CStr; N],
+    optional: bool,
+    acquired: bool,
+) -> Result<[Option<ResetControl<K>>; N]> {
+    let mut failed = None;
+    let controls = names.map(|name| {
+        if failed.is_some() {
+            return None;
+        }
+        ResetControl::<K>::get(dev, Some(name), optional, acquired).unwrap_or_else(|e| {
+            failed = Some(e);
+            None
+        })
+    });
+    match failed {
+        // Dropping the controls releases the lines got before the failure.
+        Some(e) => Err(e),
+        None => Ok(controls),
+    }
+}
+
+impl ResetControl<Exclusive> {
+    /// Gets exclusive references to the lines called `names` of `dev`, one control per line.
+    ///
+    /// The controls are returned by value, so they can be moved into storage reserved ahead of
+    /// time, e.g., in the driver's state. Each control still allocates its own state, use
+    /// [`ResetControlArray`] to operate on the lines without allocating. Lines the device does
+    /// not have are `None` if `optional` is set, and fail with `ENOENT` otherwise.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn get_exclusive_array<const N: usize>(
+        dev: &impl RawDevice,
+        names: &[&'static CStr; N],
+        optional: bool,
+    ) -> Result<[Option<Self>; N]> {
+        get_array(dev, names, optional, true)
+    }
+}
+
+impl ResetControl<Shared> {
+    /// Like [`ResetControl::get_exclusive_array`], but gets shared references.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn get_shared_array<const N: usize>(
+        dev: &impl RawDevice,
+        names: &[&'static CStr; N],
+        optional: bool,
+    ) -> Result<[Option<Self>; N]> {
+        get_array(dev, names, optional, false)
+    }
+}
@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Character device exposing whitelisted lines to userspace, for manufacturing test and bring-up.
+//!
+//! Providers opt in with [`ResetRegistration::expose_chardev`], listing the lines userspace may
+//! touch. The misc device `/dev/reset-<dev>` then accepts the following ioctls, whose argument is
+//! the line id, from callers with `CAP_SYS_ADMIN`:
+//!
+//! - `RESET_IOC_STATUS` (`_IO('R', 0)`): returns 1 if the line is asserted, 0 otherwise.
+//! - `RESET_IOC_PULSE` (`_IO('R', 1)`): triggers a self-deasserting reset of the line.
+//!
+//! Lines that are not whitelisted fail with `EPERM`, and all ioctls fail with `ENODEV` once the
+//! controller is gone.
+//!
+//! [`ResetRegistration::expose_chardev`]: super::ResetRegistration::expose_chardev
+
+use super::ResetDevice;
+use crate::{
+    bindings,
+    error::{code::*, Result},
+    file::{self, File, IoctlCommand, IoctlHandler},
+    fmt,
+    ioctl::_IO,
+    miscdev,
+    str::CStr,
+    sync::{smutex::Mutex, Arc, ArcBorrow},
+};
+
+use core::pin::Pin;
+use macros::vtable;
+
+/// Returns whether line `id` is asserted.
+pub const RESET_IOC_STATUS: u32 = _IO(b'R' as u32, 0);
+
+/// Triggers a self-deasserting reset of line `id`.
+pub const RESET_IOC_PULSE: u32 = _IO(b'R' as u32, 1);
+
+struct Controller(*mut bindings::reset_controller_dev);
+
+// SAFETY: The controller is only used to call its operations, which can be done from any thread.
+unsafe impl Send for Controller {}
+
+/// The lines exposed by a character device, shared with its open files.
+pub(crate) struct CdevLines {
+    rcdev: Mutex<Option<Controller>>,
+    whitelist: &'static [u64],
+}
+
+impl CdevLines {
+    /// Stops the open files from reaching the controller, which is going away.
+    pub(crate) fn detach(&self) {
+        *self.rcdev.lock() = None;
+    }
+
+    fn with_line<R>(&self, id: usize, f: impl FnOnce(&ResetDevice, u64) -> Result<R>) -> Result<R> {
+        // SAFETY: `capable` has no safety requirements.
+        if !unsafe { bindings::capable(bindings::CAP_SYS_ADMIN as _) } {
+            return Err(EPERM);
+        }
+        let id = id as u64;
+        if !self.whitelist.contains(&id) {
+            return Err(EPERM);
+        }
+        let rcdev = self.rcdev.lock();
+        let rcdev = rcdev.as_ref().ok_or(ENODEV)?;
+        // SAFETY: The controller is detached, under the lock we hold, before it goes away.
+        f(unsafe { ResetDevice::from_raw(rcdev.0) }, id)
+    }
+}
+
+impl IoctlHandler for CdevLines {
+    type Target<'a> = ArcBorrow<'a, CdevLines>;
+
+    fn pure(this: ArcBorrow<'_, CdevLines>, _file: &File, cmd: u32, arg: usize) -> Result<i32> {
+        match cmd {
+            RESET_IOC_STATUS => this.with_line(arg, |rcdev, id| Ok(rcdev.status(id)? as i32)),
+            RESET_IOC_PULSE => this.with_line(arg, |rcdev, id| rcdev.reset(id).map(|_| 0)),
+            _ => Err(ENOTTY),
+        }
+    }
+}
+
+struct ResetCdev;
+
+#[vtable]
+impl file::Operations for ResetCdev {
+    type OpenData = Arc<CdevLines>;
+    type Data = Arc<CdevLines>;
+
+    fn open(lines: &Arc<CdevLines>, _file: &File) -> Result<Arc<CdevLines>> {
+        Ok(lines.clone())
+    }
+
+    fn ioctl(lines: ArcBorrow<'_, CdevLines>, file: &File, cmd: &mut IoctlCommand) -> Result<i32> {
+        cmd.dispatch::<CdevLines>(lines, file)
+    }
+}
+
+/// The character device of a controller, deregistered when dropped.
+pub(crate) struct Cdev {
+    lines: Arc<CdevLines>,
+    _reg: Pin<alloc::boxed::Box<miscdev::Registration<ResetCdev>>>,
+}
+
+impl Cdev {
+    /// Registers `/dev/reset-<name>` for the lines in `whitelist` of `rcdev`.
+    ///
+    /// # Safety
+    ///
+    /// `rcdev` must be valid until the returned value is dropped.
+    pub(crate) unsafe fn try_new(
+        name: &CStr,
+        rcdev: *mut bindings::reset_controller_dev,
+        whitelist: &'static [u64],
+    ) -> Result<Self> {
+        let lines = Arc::try_new(CdevLines {
+            rcdev: Mutex::new(Some(Controller(rcdev))),
+            whitelist,
+        })?;
+        let reg = miscdev::Registration::new_pinned(fmt!("reset-{}", name), lines.clone())?;
+        Ok(Self { lines, _reg: reg })
+    }
+}
+
+impl Drop for Cdev {
+    fn drop(&mut self) {
+        // Files may still be open, they keep `lines` alive but must not reach the controller.
+        self.lines.detach();
+    }
+}
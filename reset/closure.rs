@@ -0,0 +1,75 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Reset controllers built from closures.
+//!
+//! Tiny drivers and tests can register a controller without defining their own type and
+//! [`ResetDriverOps`] implementation:
+//!
+//! ```ignore
+//! let ops = FnResetOps {
+//!     assert: FnResetOps::op(|req| regs.set_bit(req.id()))?,
+//!     deassert: FnResetOps::op(|req| regs.clear_bit(req.id()))?,
+//!     ..Default::default()
+//! };
+//! reset_controller_register!(reg.as_mut(), &mut pdev, 32, Box::try_new(ops)?)?;
+//! ```
+
+use super::{ResetDriverOps, ResetRequest};
+use crate::error::{code::*, Result};
+
+use alloc::boxed::Box;
+use macros::vtable;
+
+/// A single operation of a [`FnResetOps`].
+pub type ResetFn = Box<dyn Fn(ResetRequest) -> Result<i32> + Send + Sync>;
+
+/// The operations of a reset controller, as closures.
+///
+/// Operations left as `None` fail with `ENOTSUPP`.
+#[derive(Default)]
+pub struct FnResetOps {
+    /// Implementation of [`ResetDriverOps::reset`].
+    pub reset: Option<ResetFn>,
+    /// Implementation of [`ResetDriverOps::assert`].
+    pub assert: Option<ResetFn>,
+    /// Implementation of [`ResetDriverOps::deassert`].
+    pub deassert: Option<ResetFn>,
+    /// Implementation of [`ResetDriverOps::status`].
+    pub status: Option<ResetFn>,
+}
+
+impl FnResetOps {
+    /// Boxes `f` for use as one of the operations.
+    pub fn op<F>(f: F) -> Result<Option<ResetFn>>
+    where
+        F: Fn(ResetRequest) -> Result<i32> + Send + Sync + 'static,
+    {
+        Ok(Some(Box::try_new(f)?))
+    }
+}
+
+/// The [`ResetDriverOps`] implementation for controllers built from [`FnResetOps`].
+///
+/// Register it with a `Box<FnResetOps>` as data.
+pub struct FnResetDriver;
+
+#[vtable]
+impl ResetDriverOps for FnResetDriver {
+    type Data = Box<FnResetOps>;
+
+    fn reset(data: &FnResetOps, req: ResetRequest) -> Result<i32> {
+        data.reset.as_ref().ok_or(ENOTSUPP)?(req)
+    }
+
+    fn assert(data: &FnResetOps, req: ResetRequest) -> Result<i32> {
+        data.assert.as_ref().ok_or(ENOTSUPP)?(req)
+    }
+
+    fn deassert(data: &FnResetOps, req: ResetRequest) -> Result<i32> {
+        data.deassert.as_ref().ok_or(ENOTSUPP)?(req)
+    }
+
+    fn status(data: &FnResetOps, req: ResetRequest) -> Result<i32> {
+        data.status.as_ref().ok_or(ENOTSUPP)?(req)
+    }
+}
@@ -0,0 +1,122 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Composite reset controllers, fronting several child controllers behind a single node.
+//!
+//! Chiplet and multi-die designs often have one reset controller per die, while firmware
+//! describes a single logical controller. The composite exposes the lines of its children in one
+//! flat id space, in the order the children were added:
+//!
+//! ```ignore
+//! let mut map = CompositeMap::new();
+//! // SAFETY: Both dies are unregistered after the composite.
+//! unsafe {
+//!     map.try_push(die0)?;
+//!     map.try_push(die1)?;
+//! }
+//! let nr_resets = map.nr_resets();
+//! reset_controller_register!(reg.as_mut(), &mut pdev, nr_resets, Box::try_new(map)?)?;
+//! ```
+
+use super::{ResetDevice, ResetDriverOps, ResetRequest};
+use crate::{
+    bindings,
+    error::{code::*, Result},
+};
+
+use alloc::{boxed::Box, vec::Vec};
+use macros::vtable;
+
+struct Child {
+    rcdev: *mut bindings::reset_controller_dev,
+    base: u64,
+    nr_resets: u64,
+}
+
+/// The mapping from the flat ids of a composite controller to the lines of its children.
+#[derive(Default)]
+pub struct CompositeMap {
+    children: Vec<Child>,
+}
+
+// SAFETY: The children are only used to call their operations, which can be done from any thread.
+unsafe impl Send for CompositeMap {}
+
+// SAFETY: The children are only used to call their operations, which can be done concurrently.
+unsafe impl Sync for CompositeMap {}
+
+impl CompositeMap {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends all the lines of `child` to the flat id space.
+    ///
+    /// # Safety
+    ///
+    /// `child` must stay registered as long as the composite controller using the map.
+    pub unsafe fn try_push(&mut self, child: &ResetDevice) -> Result {
+        let base = self.nr_resets() as u64;
+        let nr_resets = child.nr_resets() as u64;
+        if base + nr_resets > u32::MAX as u64 {
+            return Err(EINVAL);
+        }
+        self.children.try_push(Child {
+            rcdev: child.as_ptr(),
+            base,
+            nr_resets,
+        })?;
+        Ok(())
+    }
+
+    /// Returns the number of lines of the composite controller.
+    pub fn nr_resets(&self) -> u32 {
+        self.children
+            .last()
+            .map_or(0, |c| (c.base + c.nr_resets) as u32)
+    }
+
+    /// Returns the child controller of flat line `id` and the id of the line in that child.
+    ///
+    /// Fails with `EINVAL` if there is no such line.
+    pub fn map(&self, id: u64) -> Result<(&ResetDevice, u64)> {
+        let child = self
+            .children
+            .iter()
+            .find(|c| id >= c.base && id < c.base + c.nr_resets)
+            .ok_or(EINVAL)?;
+        // SAFETY: By the safety requirements of `try_push`, the child is still registered.
+        Ok((unsafe { ResetDevice::from_raw(child.rcdev) }, id - child.base))
+    }
+}
+
+/// The [`ResetDriverOps`] implementation for composite controllers.
+///
+/// Register it with a `Box<CompositeMap>` as data and [`CompositeMap::nr_resets`] lines.
+/// Operations the child of a line does not implement fail with `ENOTSUPP`.
+pub struct CompositeResetDriver;
+
+#[vtable]
+impl ResetDriverOps for CompositeResetDriver {
+    type Data = Box<CompositeMap>;
+
+    fn reset(data: &CompositeMap, req: ResetRequest) -> Result<i32> {
+        let (child, id) = data.map(req.id())?;
+        child.reset(id).map(|_| 0)
+    }
+
+    fn assert(data: &CompositeMap, req: ResetRequest) -> Result<i32> {
+        let (child, id) = data.map(req.id())?;
+        child.assert(id).map(|_| 0)
+    }
+
+    fn deassert(data: &CompositeMap, req: ResetRequest) -> Result<i32> {
+        let (child, id) = data.map(req.id())?;
+        child.deassert(id).map(|_| 0)
+    }
+
+    fn status(data: &CompositeMap, req: ResetRequest) -> Result<i32> {
+        let (child, id) = data.map(req.id())?;
+        child.status(id).map(|asserted| asserted as i32)
+    }
+}
@@ -0,0 +1,1229 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Reset controller consumers.
+//!
+//! C header: [`include/linux/reset.h`](../../../../include/linux/reset.h)
+//!
+//! Every operation is logged with `pr_debug!` together with the consumer device and the name of
+//! the line in its `reset-names`, e.g.:
+//!
+//! ```text
+//! 1c30000.ethernet: assert of reset line reset-controller@1000[12] (ahb): Ok(())
+//! ```
+//!
+//! The C core does not pass the consumer to providers, so operations through C consumers, and
+//! the messages of the providers, do not name it. The consumers holding a line are known from
+//! [`ResetRegistration::line_holders`].
+//!
+//! [`ResetRegistration::line_holders`]: super::ResetRegistration::line_holders
+
+use super::{
+    of::OfSpec,
+    registry,
+    retry::{self, RetryPolicy},
+    topology::{self, RemovalNotify, TopologyHandle},
+    ResetOp,
+};
+use crate::{
+    bindings, c_str,
+    delay::coarse_sleep,
+    device::RawDevice,
+    error::{code::*, from_kernel_err_ptr, to_result, Result},
+    fmt, pr_debug, pr_err, spawn_work_item,
+    str::{CStr, CString},
+    sync::{smutex::Mutex, Arc, CondVar, SpinLock, UniqueArc},
+    types::ForeignOwnable,
+    workqueue,
+};
+
+use core::{
+    ffi::{c_int, c_void},
+    marker::PhantomData,
+    pin::Pin,
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU8, Ordering},
+    time::Duration,
+};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// The kind of a [`ResetControl`], either [`Exclusive`], [`Released`] or [`Shared`].
+pub trait ResetKind: sealed::Sealed {
+    /// Whether controls of this kind are shared with other consumers.
+    const SHARED: bool;
+}
+
+/// Marker for controls that are exclusively owned by one consumer.
+pub enum Exclusive {}
+
+impl sealed::Sealed for Exclusive {}
+
+impl ResetKind for Exclusive {
+    const SHARED: bool = false;
+}
+
+/// Marker for exclusive controls that must be acquired before they can be used.
+pub enum Released {}
+
+impl sealed::Sealed for Released {}
+
+impl ResetKind for Released {
+    const SHARED: bool = false;
+}
+
+/// Marker for controls that are shared between consumers.
+pub enum Shared {}
+
+impl sealed::Sealed for Shared {}
+
+impl ResetKind for Shared {
+    const SHARED: bool = true;
+}
+
+/// A reset control that only its owner may assert and deassert.
+pub type ExclusiveResetControl = ResetControl<Exclusive>;
+
+/// An exclusive reset control that is acquired and released at run time.
+pub type ReleasedResetControl = ResetControl<Released>;
+
+/// A reset control shared with other consumers, which can only be triggered and rearmed.
+pub type SharedResetControl = ResetControl<Shared>;
+
+/// What backs a [`ResetControl`].
+enum Backend {
+    /// A line of a reset controller.
+    Core(NonNull<bindings::reset_control>),
+    /// A `reset-gpios` GPIO, high when the line is asserted.
+    Gpio(NonNull<bindings::gpio_desc>),
+}
+
+/// The reference to the line held by a [`ResetControl`].
+///
+/// # Invariants
+///
+/// `backend` holds a valid pointer returned by the C reset core or the GPIO library, and it holds
+/// a reference that is released when [`RawControl`] is dropped, unless `owned` is cleared.
+struct RawControl {
+    backend: Backend,
+    /// Cleared when the reference is handed over to C code by [`ResetControl::into_raw`].
+    owned: AtomicBool,
+    provider_ops: Option<ProviderOps>,
+    info: LineInfo,
+    state: AtomicU8,
+    suspend: SuspendState,
+    topology: Option<TopologyHandle>,
+}
+
+/// Handling of a line across system suspend, see
+/// [`ExclusiveResetControl::set_keep_asserted_in_suspend`].
+struct SuspendState {
+    /// Whether the line must stay asserted while the system is suspended, set while the control
+    /// is in the [`KeptLines`] of its controller.
+    keep_asserted: AtomicBool,
+    /// The next control in the [`KeptLines`] of the controller.
+    next: AtomicPtr<RawControl>,
+    /// Set between [`RawControl::suspend`] and [`RawControl::resume`].
+    suspended: AtomicBool,
+    /// The state the line is put back in on resume.
+    resume_state: AtomicU8,
+}
+
+impl SuspendState {
+    const fn new() -> Self {
+        Self {
+            keep_asserted: AtomicBool::new(false),
+            next: AtomicPtr::new(core::ptr::null_mut()),
+            suspended: AtomicBool::new(false),
+            resume_state: AtomicU8::new(STATE_UNKNOWN),
+        }
+    }
+}
+
+/// What is known about a line, for diagnostics.
+struct LineInfo {
+    /// The name of the consumer device.
+    consumer: Option<CString>,
+    provider: Option<CString>,
+    id: Option<u32>,
+    name: Option<CString>,
+}
+
+impl LineInfo {
+    fn new(
+        consumer: &impl RawDevice,
+        provider: Option<CString>,
+        id: Option<u32>,
+        name: Option<&CStr>,
+    ) -> Result<Self> {
+        let name = match name {
+            Some(name) => Some(CString::try_from_fmt(fmt!("{}", name))?),
+            None => None,
+        };
+        Ok(Self {
+            consumer: Some(CString::try_from_fmt(fmt!("{}", consumer.name()))?),
+            provider,
+            id,
+            name,
+        })
+    }
+}
+
+/// Formats the line as `provider[id] (name)`, e.g., `reset-controller@1000[12] (ahb)`.
+impl core::fmt::Display for LineInfo {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match &self.provider {
+            Some(provider) => write!(f, "{}", provider)?,
+            None => f.write_str("?")?,
+        }
+        if let Some(id) = self.id {
+            write!(f, "[{}]", id)?;
+        }
+        if let Some(name) = &self.name {
+            write!(f, " ({})", name)?;
+        }
+        Ok(())
+    }
+}
+
+/// The controls of the lines of a controller that must stay asserted while the system is
+/// suspended, see [`ExclusiveResetControl::set_keep_asserted_in_suspend`].
+///
+/// Embedded in the registration of the controller, which links it while the controller is
+/// registered. The controls are asserted by the syscore operations, once all devices are
+/// suspended, so only controllers whose operations can run with interrupts disabled link it.
+///
+/// The lists of controllers and of their controls are intrusive, as the syscore operations run
+/// with interrupts disabled and cannot take the lock serialising their updates. They run on the
+/// only CPU left online, so they just refuse to suspend while an update is in progress.
+pub(crate) struct KeptLines {
+    rcdev: AtomicPtr<bindings::reset_controller_dev>,
+    /// The first control, linked through [`SuspendState::next`].
+    head: AtomicPtr<RawControl>,
+    /// The next linked controller.
+    next: AtomicPtr<KeptLines>,
+}
+
+/// The first linked controller, see [`KeptLines`].
+static KEPT_LINES: AtomicPtr<KeptLines> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Serialises the updates of the lists of [`KeptLines`].
+static KEPT_LINES_LOCK: Mutex<()> = Mutex::new(());
+
+/// Set while the lists of [`KeptLines`] are updated, see [`with_kept_lines`].
+static KEPT_LINES_UPDATING: AtomicBool = AtomicBool::new(false);
+
+/// Runs `f` with the lists of [`KeptLines`] locked, flagging it for the syscore operations.
+fn with_kept_lines<R>(f: impl FnOnce() -> R) -> R {
+    let _guard = KEPT_LINES_LOCK.lock();
+    KEPT_LINES_UPDATING.store(true, Ordering::Release);
+    let ret = f();
+    KEPT_LINES_UPDATING.store(false, Ordering::Release);
+    ret
+}
+
+/// Calls `f` on every linked controller.
+///
+/// The lists must be locked, or the caller must be the syscore operations.
+fn for_each_kept_lines(mut f: impl FnMut(&KeptLines)) {
+    let mut kept = KEPT_LINES.load(Ordering::Acquire);
+    // SAFETY: Controllers are unlinked, under the lock, before they go away.
+    while let Some(k) = unsafe { kept.as_ref() } {
+        f(k);
+        kept = k.next.load(Ordering::Acquire);
+    }
+}
+
+impl KeptLines {
+    pub(crate) const fn new() -> Self {
+        Self {
+            rcdev: AtomicPtr::new(core::ptr::null_mut()),
+            head: AtomicPtr::new(core::ptr::null_mut()),
+            next: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
+    /// Links the controls of `rcdev`, so that consumers can keep its lines asserted.
+    ///
+    /// # Safety
+    ///
+    /// `self` must not move, and must be unlinked with [`KeptLines::unlink`] before it goes away.
+    pub(crate) unsafe fn link(&self, rcdev: *mut bindings::reset_controller_dev) {
+        super::emergency::register_syscore();
+        with_kept_lines(|| {
+            self.rcdev.store(rcdev, Ordering::Relaxed);
+            self.next.store(KEPT_LINES.load(Ordering::Relaxed), Ordering::Release);
+            KEPT_LINES.store(self as *const _ as *mut _, Ordering::Release);
+        });
+    }
+
+    /// Unlinks the controls of the controller, if linked.
+    ///
+    /// The controls still linked are left asserted in suspend no more.
+    pub(crate) fn unlink(&self) {
+        with_kept_lines(|| {
+            let this = self as *const _ as *mut KeptLines;
+            let mut link = &KEPT_LINES;
+            // SAFETY: The lists are locked, see `for_each_kept_lines`.
+            while let Some(k) = unsafe { link.load(Ordering::Relaxed).as_ref() } {
+                if core::ptr::eq(k, this) {
+                    link.store(k.next.load(Ordering::Relaxed), Ordering::Release);
+                    break;
+                }
+                link = &k.next;
+            }
+            self.head.store(core::ptr::null_mut(), Ordering::Release);
+        });
+    }
+
+    /// Returns the linked controls of `rcdev`, the lists must be locked.
+    fn find<'a>(rcdev: *mut bindings::reset_controller_dev) -> Option<&'a KeptLines> {
+        let mut kept = KEPT_LINES.load(Ordering::Acquire);
+        // SAFETY: The lists are locked, so the controllers stay linked until the caller unlocks
+        // them.
+        while let Some(k) = unsafe { kept.as_ref() } {
+            if k.rcdev.load(Ordering::Relaxed) == rcdev {
+                return Some(k);
+            }
+            kept = k.next.load(Ordering::Acquire);
+        }
+        None
+    }
+
+    /// Calls `f` on every linked control.
+    fn for_each(&self, mut f: impl FnMut(&RawControl)) {
+        let mut control = self.head.load(Ordering::Acquire);
+        // SAFETY: Controls are unlinked, under the lock, before they go away.
+        while let Some(c) = unsafe { control.as_ref() } {
+            f(c);
+            control = c.suspend.next.load(Ordering::Acquire);
+        }
+    }
+
+    /// Links `control`, the lists must be locked.
+    fn push(&self, control: &RawControl) {
+        control.suspend.next.store(self.head.load(Ordering::Relaxed), Ordering::Release);
+        self.head.store(control as *const _ as *mut _, Ordering::Release);
+    }
+
+    /// Unlinks `control` if it is linked, the lists must be locked.
+    fn remove(&self, control: &RawControl) {
+        let mut link = &self.head;
+        // SAFETY: The lists are locked, see `KeptLines::for_each`.
+        while let Some(c) = unsafe { link.load(Ordering::Relaxed).as_ref() } {
+            if core::ptr::eq(c, control) {
+                link.store(c.suspend.next.load(Ordering::Relaxed), Ordering::Release);
+                return;
+            }
+            link = &c.suspend.next;
+        }
+    }
+}
+
+/// Asserts the lines consumers keep asserted while the system is suspended, see
+/// [`ExclusiveResetControl::set_keep_asserted_in_suspend`].
+///
+/// Called by the syscore operations. On failure, the lines already asserted are put back in
+/// their state, and the suspend fails. Fails with `EBUSY` if the lists are being updated.
+pub(crate) fn suspend_kept_lines() -> Result {
+    if KEPT_LINES_UPDATING.load(Ordering::Acquire) {
+        return Err(EBUSY);
+    }
+    let mut ret = Ok(());
+    for_each_kept_lines(|k| {
+        k.for_each(|control| {
+            if ret.is_ok() {
+                ret = control.suspend();
+            }
+        })
+    });
+    if ret.is_err() {
+        // Only the lines asserted above are restored.
+        let _ = resume_kept_lines();
+    }
+    ret
+}
+
+/// Puts the lines asserted by [`suspend_kept_lines`] back in the state last requested by their
+/// consumers.
+///
+/// Called by the syscore operations. Lines failing to be restored are reported, the others are
+/// still restored.
+pub(crate) fn resume_kept_lines() -> Result {
+    let mut ret = Ok(());
+    for_each_kept_lines(|k| {
+        k.for_each(|control| {
+            if let Err(e) = control.resume() {
+                pr_err!("reset line {} not restored after suspend: {:?}\n", control.info, e);
+                ret = Err(e);
+            }
+        })
+    });
+    ret
+}
+
+/// Last state a line was put in through a control, stored in [`RawControl::state`].
+const STATE_UNKNOWN: u8 = 0;
+const STATE_ASSERTED: u8 = 1;
+const STATE_DEASSERTED: u8 = 2;
+
+/// The operations implemented by the controller of a line.
+#[derive(Clone, Copy)]
+struct ProviderOps {
+    reset: bool,
+    assert: bool,
+    deassert: bool,
+    status: bool,
+}
+
+impl ProviderOps {
+    /// Returns the operations of the controller `spec` points to, if it was registered from Rust.
+    ///
+    /// Controllers registered from C are private to the C core, what they support is unknown.
+    fn of_provider(spec: &OfSpec) -> Option<Self> {
+        let controllers = registry::registered_controllers();
+        let rcdev = controllers.find_by_of_node(spec.node())?;
+        Some(Self {
+            reset: rcdev.supports(ResetOp::Reset),
+            assert: rcdev.supports(ResetOp::Assert),
+            deassert: rcdev.supports(ResetOp::Deassert),
+            status: rcdev.supports(ResetOp::Status),
+        })
+    }
+}
+
+impl RawControl {
+    /// Returns the C reset control, which is null for GPIOs, treated as optional by the C core.
+    fn as_ptr(&self) -> *mut bindings::reset_control {
+        match self.backend {
+            Backend::Core(ptr) => ptr.as_ptr(),
+            Backend::Gpio(_) => core::ptr::null_mut(),
+        }
+    }
+
+    fn set_gpio(gpio: NonNull<bindings::gpio_desc>, asserted: bool) {
+        // SAFETY: By the type invariants, `gpio` is valid.
+        unsafe {
+            if bindings::gpiod_cansleep(gpio.as_ptr()) != 0 {
+                bindings::gpiod_set_value_cansleep(gpio.as_ptr(), asserted as _);
+            } else {
+                bindings::gpiod_set_value(gpio.as_ptr(), asserted as _);
+            }
+        }
+    }
+
+    fn read_gpio(gpio: NonNull<bindings::gpio_desc>) -> c_int {
+        // SAFETY: By the type invariants, `gpio` is valid.
+        unsafe {
+            if bindings::gpiod_cansleep(gpio.as_ptr()) != 0 {
+                bindings::gpiod_get_value_cansleep(gpio.as_ptr())
+            } else {
+                bindings::gpiod_get_value(gpio.as_ptr())
+            }
+        }
+    }
+
+    /// Logs the result of `op` with the consumer, see the [module] documentation.
+    ///
+    /// [module]: self
+    fn log<R: core::fmt::Debug>(&self, op: &str, ret: &Result<R>) {
+        let consumer = self.info.consumer.as_deref().unwrap_or(c_str!("?"));
+        pr_debug!("{}: {} of reset line {}: {:?}\n", consumer, op, self.info, ret);
+    }
+
+    /// Logs the result of `op`, and records that the line was put in `state` if it is a success.
+    fn track(&self, op: &str, state: u8, ret: Result) -> Result {
+        self.log(op, &ret);
+        if ret.is_ok() {
+            self.state.store(state, Ordering::Relaxed);
+        }
+        ret
+    }
+
+    /// Fails with `ENODEV` once the controller of the line was unregistered.
+    fn check_provider(&self) -> Result {
+        match &self.topology {
+            Some(topology) => topology.check_not_removed(),
+            None => Ok(()),
+        }
+    }
+
+    /// Fails with `EBUSY` while the line is held asserted for system suspend.
+    fn check_not_suspended(&self) -> Result {
+        if self.suspend.suspended.load(Ordering::Acquire) {
+            return Err(EBUSY);
+        }
+        Ok(())
+    }
+
+    /// Asserts the line for system suspend.
+    ///
+    /// When the controller reports the status of its lines, checks that the line is actually
+    /// asserted and fails with `EIO` otherwise. Deasserts and resets are refused until
+    /// [`RawControl::resume`].
+    fn suspend(&self) -> Result {
+        let requested = self.state.load(Ordering::Relaxed);
+        self.assert()?;
+        match self.status() {
+            Ok(false) => {
+                pr_err!("reset line {} still deasserted after suspend assert\n", self.info);
+                self.state.store(requested, Ordering::Relaxed);
+                return Err(EIO);
+            }
+            Ok(true) | Err(ENOTSUPP) => {}
+            Err(e) => return Err(e),
+        }
+        self.suspend.resume_state.store(requested, Ordering::Relaxed);
+        self.suspend.suspended.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Puts the line back in the state last requested by the consumer before
+    /// [`RawControl::suspend`].
+    ///
+    /// Lines whose state was never set by the consumer are left asserted.
+    fn resume(&self) -> Result {
+        if !self.suspend.suspended.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+        match self.suspend.resume_state.load(Ordering::Relaxed) {
+            STATE_DEASSERTED => self.deassert(),
+            _ => Ok(()),
+        }
+    }
+
+    fn reset(&self) -> Result {
+        self.check_provider()?;
+        self.check_not_suspended()?;
+        self.track("reset", STATE_DEASSERTED, self.reset_backend())
+    }
+
+    fn reset_backend(&self) -> Result {
+        match self.backend {
+            Backend::Core(ptr) => {
+                // SAFETY: By the type invariants, `ptr` is valid.
+                to_result(unsafe { bindings::reset_control_reset(ptr.as_ptr()) })
+            }
+            Backend::Gpio(gpio) => {
+                Self::set_gpio(gpio, true);
+                coarse_sleep(GPIO_PULSE);
+                Self::set_gpio(gpio, false);
+                Ok(())
+            }
+        }
+    }
+
+    fn rearm(&self) -> Result {
+        self.check_provider()?;
+        // SAFETY: By the type invariants, `self.as_ptr()` is valid or null.
+        to_result(unsafe { bindings::reset_control_rearm(self.as_ptr()) })
+    }
+
+    fn assert(&self) -> Result {
+        self.check_provider()?;
+        self.track("assert", STATE_ASSERTED, self.assert_backend())
+    }
+
+    fn assert_backend(&self) -> Result {
+        match self.backend {
+            Backend::Core(ptr) => {
+                // SAFETY: By the type invariants, `ptr` is valid.
+                to_result(unsafe { bindings::reset_control_assert(ptr.as_ptr()) })
+            }
+            Backend::Gpio(gpio) => {
+                Self::set_gpio(gpio, true);
+                Ok(())
+            }
+        }
+    }
+
+    fn deassert(&self) -> Result {
+        self.check_provider()?;
+        self.check_not_suspended()?;
+        self.track("deassert", STATE_DEASSERTED, self.deassert_backend())
+    }
+
+    fn deassert_backend(&self) -> Result {
+        match self.backend {
+            Backend::Core(ptr) => {
+                // SAFETY: By the type invariants, `ptr` is valid.
+                to_result(unsafe { bindings::reset_control_deassert(ptr.as_ptr()) })
+            }
+            Backend::Gpio(gpio) => {
+                Self::set_gpio(gpio, false);
+                Ok(())
+            }
+        }
+    }
+
+    fn status(&self) -> Result<bool> {
+        self.check_provider()?;
+        let ret = match self.backend {
+            // SAFETY: By the type invariants, `ptr` is valid.
+            Backend::Core(ptr) => unsafe { bindings::reset_control_status(ptr.as_ptr()) },
+            Backend::Gpio(gpio) => Self::read_gpio(gpio),
+        };
+        let ret = to_result(ret).map(|()| ret > 0);
+        self.log("status", &ret);
+        ret
+    }
+}
+
+/// How long a `reset-gpios` line is held asserted by [`ResetControl::reset`].
+const GPIO_PULSE: Duration = Duration::from_millis(1);
+
+impl Drop for RawControl {
+    fn drop(&mut self) {
+        if self.suspend.keep_asserted.load(Ordering::Relaxed) {
+            let controller = self.topology.as_ref().and_then(|t| t.controller());
+            with_kept_lines(|| {
+                if let Some(kept) = controller.and_then(KeptLines::find) {
+                    kept.remove(self);
+                }
+            });
+        }
+        if !self.owned.load(Ordering::Relaxed) {
+            return;
+        }
+        if self.check_provider().is_err() {
+            // Putting the reference would go through the controller, which the C core already
+            // dropped, so the reference is leaked instead.
+            return;
+        }
+        match self.backend {
+            // SAFETY: By the type invariants, `ptr` is valid and we own a reference to it.
+            Backend::Core(ptr) => unsafe { bindings::reset_control_put(ptr.as_ptr()) },
+            // SAFETY: By the type invariants, `gpio` is valid and we own it.
+            Backend::Gpio(gpio) => unsafe { bindings::gpiod_put(gpio.as_ptr()) },
+        }
+    }
+}
+
+// SAFETY: The C reset core serializes operations on a `reset_control` internally, and GPIO
+// descriptors may be used from any thread, so it can be used and released from any thread.
+unsafe impl Send for RawControl {}
+
+// SAFETY: All methods taking `&self` are safe to call concurrently, the C reset core and the GPIO
+// library handle the required synchronization.
+unsafe impl Sync for RawControl {}
+
+/// A reference to a reset line obtained from a reset controller.
+///
+/// Whether the line is exclusively owned or shared with other consumers is part of the type, see
+/// [`ExclusiveResetControl`], [`ReleasedResetControl`] and [`SharedResetControl`].
+pub struct ResetControl<K: ResetKind = Exclusive> {
+    raw: Arc<RawControl>,
+    _kind: PhantomData<K>,
+}
+
+/// Formats the line as `provider[id] (name)`, e.g., `reset-controller@1000[12] (ahb)`.
+impl<K: ResetKind> core::fmt::Display for ResetControl<K> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Display::fmt(&self.raw.info, f)
+    }
+}
+
+impl<K: ResetKind> core::fmt::Debug for ResetControl<K> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let state = match self.raw.state.load(Ordering::Relaxed) {
+            STATE_ASSERTED => "asserted",
+            STATE_DEASSERTED => "deasserted",
+            _ => "unknown",
+        };
+        f.debug_struct("ResetControl")
+            .field("provider", &self.provider_name())
+            .field("id", &self.id())
+            .field("name", &self.name())
+            .field("shared", &K::SHARED)
+            .field("state", &state)
+            .finish()
+    }
+}
+
+impl<K: ResetKind> ResetControl<K> {
+    pub(crate) fn get(
+        dev: &impl RawDevice,
+        id: Option<&CStr>,
+        optional: bool,
+        acquired: bool,
+    ) -> Result<Option<Self>> {
+        #[cfg(CONFIG_ACPI)]
+        super::acpi::add_dsd_lookup(dev, id)?;
+        let raw_id = id.map_or(core::ptr::null(), |id| id.as_char_ptr());
+        // SAFETY: `dev` is a valid device and `raw_id` is either null or a valid C string.
+        let ptr = unsafe {
+            bindings::__reset_control_get(
+                dev.raw_device(),
+                raw_id,
+                0,
+                K::SHARED,
+                optional,
+                acquired,
+            )
+        };
+        let raw = match from_kernel_err_ptr(ptr).map(NonNull::new) {
+            Ok(Some(ptr)) => {
+                let spec = OfSpec::parse(dev, id);
+                let info = LineInfo::new(
+                    dev,
+                    spec.as_ref().and_then(|spec| spec.provider_name().ok()),
+                    spec.as_ref().and_then(|spec| spec.args().first().copied()),
+                    id,
+                )?;
+                // INVARIANT: The pointer was just returned by the C reset core.
+                RawControl {
+                    backend: Backend::Core(ptr),
+                    owned: AtomicBool::new(true),
+                    provider_ops: spec.as_ref().and_then(ProviderOps::of_provider),
+                    info,
+                    state: AtomicU8::new(STATE_UNKNOWN),
+                    suspend: SuspendState::new(),
+                    topology: spec.and_then(|spec| topology::record(dev, &spec, K::SHARED)),
+                }
+            }
+            // Acquired exclusive lines may also be wired to a GPIO instead of a reset controller,
+            // which cannot be released.
+            Ok(None) | Err(ENOENT) if !K::SHARED && acquired => match Self::get_gpio(dev, id)? {
+                Some(gpio) => gpio,
+                None if optional => return Ok(None),
+                None => return Err(ENOENT),
+            },
+            Ok(None) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let raw = Arc::try_new(raw)?;
+        Ok(Some(Self {
+            raw,
+            _kind: PhantomData,
+        }))
+    }
+
+    /// Gets the `reset-gpios` GPIO of `dev`, or the `<id>-reset-gpios` one if `id` is set.
+    ///
+    /// The GPIO is driven to the asserted level right away, as its state is unknown.
+    fn get_gpio(dev: &impl RawDevice, id: Option<&CStr>) -> Result<Option<RawControl>> {
+        let con_id = match id {
+            Some(id) => CString::try_from_fmt(fmt!("{}-reset", id))?,
+            None => CString::try_from_fmt(fmt!("reset"))?,
+        };
+        // SAFETY: `dev` is a valid device and `con_id` is a valid C string.
+        let gpio = from_kernel_err_ptr(unsafe {
+            bindings::gpiod_get_optional(
+                dev.raw_device(),
+                con_id.as_char_ptr(),
+                bindings::gpiod_flags_GPIOD_OUT_HIGH,
+            )
+        })?;
+        let gpio = match NonNull::new(gpio) {
+            Some(gpio) => gpio,
+            None => return Ok(None),
+        };
+        let info = LineInfo::new(dev, Some(CString::try_from_fmt(fmt!("gpio"))?), None, id)?;
+        // INVARIANT: The pointer was just returned by the GPIO library.
+        Ok(Some(RawControl {
+            backend: Backend::Gpio(gpio),
+            owned: AtomicBool::new(true),
+            provider_ops: Some(ProviderOps {
+                reset: true,
+                assert: true,
+                deassert: true,
+                status: true,
+            }),
+            info,
+            state: AtomicU8::new(STATE_ASSERTED),
+            suspend: SuspendState::new(),
+            topology: None,
+        }))
+    }
+
+    /// Returns a raw pointer to the inner C struct.
+    #[inline]
+    pub(crate) fn as_ptr(&self) -> *mut bindings::reset_control {
+        self.raw.as_ptr()
+    }
+
+    /// Returns the C reset control, e.g., to pass it to C helpers of a mixed C/Rust driver.
+    ///
+    /// The pointer is valid as long as the control is alive. It is null for lines wired to a
+    /// GPIO, which the C reset API treats as a missing optional line: operations on it succeed
+    /// without doing anything.
+    pub fn as_raw(&self) -> *mut bindings::reset_control {
+        self.raw.as_ptr()
+    }
+
+    /// Wraps a reference to a line obtained by C code, e.g., while converting a large C driver
+    /// to Rust one component at a time.
+    ///
+    /// The control takes over the reference and releases it when dropped, or right away on
+    /// failure. Fails with `EINVAL` if `ptr` is null, as returned by the optional getters for
+    /// missing lines. The line is not recorded in the topology, and what the controller supports
+    /// is unknown.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be null or a reference returned by the C reset core, obtained with the
+    /// semantics of `K` (exclusive, exclusive released or shared), and owned by the caller, who
+    /// must not use it afterwards.
+    pub unsafe fn from_raw(ptr: *mut bindings::reset_control) -> Result<Self> {
+        let ptr = NonNull::new(ptr).ok_or(EINVAL)?;
+        // INVARIANT: The caller transfers its reference, returned by the C reset core.
+        let raw = RawControl {
+            backend: Backend::Core(ptr),
+            owned: AtomicBool::new(true),
+            provider_ops: None,
+            info: LineInfo {
+                consumer: None,
+                provider: None,
+                id: None,
+                name: None,
+            },
+            state: AtomicU8::new(STATE_UNKNOWN),
+            suspend: SuspendState::new(),
+            topology: None,
+        };
+        // On failure, `raw` is dropped, which releases the reference.
+        Ok(Self {
+            raw: Arc::try_new(raw)?,
+            _kind: PhantomData,
+        })
+    }
+
+    /// Hands the reference to the line over to the caller, who must release it with
+    /// `reset_control_put`.
+    ///
+    /// Fails, giving the control back, for lines wired to a GPIO, which have no C reset
+    /// control.
+    ///
+    /// # Safety
+    ///
+    /// Clones of a [`SharedResetControl`], [`SharedDeassert`] guards and resets queued with
+    /// [`ResetControl::reset_async`] keep using the reference, so the caller must not release it
+    /// before they are gone.
+    pub unsafe fn into_raw(self) -> core::result::Result<*mut bindings::reset_control, Self> {
+        match self.raw.backend {
+            Backend::Core(ptr) => {
+                self.raw.owned.store(false, Ordering::Relaxed);
+                Ok(ptr.as_ptr())
+            }
+            Backend::Gpio(_) => Err(self),
+        }
+    }
+
+    /// Releases the reference to the line before the control goes out of scope.
+    ///
+    /// Long-lived driver structs keep the control in an `Option` and put it once bring-up is
+    /// finished, making an exclusive line available to other consumers. Resets queued with
+    /// [`ResetControl::reset_async`] keep the line referenced until they have run.
+    pub fn put(self) {
+        drop(self);
+    }
+
+    /// Hands the control over to `dev`, which puts it when it is unbound.
+    ///
+    /// This is meant for lines that must stay referenced (e.g., exclusively claimed) while the
+    /// driver is bound, but that the driver does not operate after probe: the reference can
+    /// then not be leaked by a forgotten error path. The control is put right away on failure.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn into_devm(self, dev: &impl RawDevice) -> Result {
+        let ptr = self.raw.into_foreign() as *mut c_void;
+        // SAFETY: `dev` is a valid device, and `ptr` is only freed by `devm_release`.
+        let ret = unsafe { bindings::devm_add_action(dev.raw_device(), Some(devm_release), ptr) };
+        if let Err(e) = to_result(ret) {
+            // SAFETY: `ptr` was returned by `into_foreign` above and the action was not added.
+            drop(unsafe { Arc::<RawControl>::from_foreign(ptr) });
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Returns the name of the line in the consumer's `reset-names`, if it was got by name.
+    pub fn name(&self) -> Option<&CStr> {
+        self.raw.info.name.as_deref()
+    }
+
+    /// Returns the full name of the provider node, or `gpio` for `reset-gpios` lines.
+    ///
+    /// Returns `None` for lines that cannot be resolved through the device tree.
+    pub fn provider_name(&self) -> Option<&CStr> {
+        self.raw.info.provider.as_deref()
+    }
+
+    /// Returns the line id, as found in the first cell of the consumer's reset specifier.
+    pub fn id(&self) -> Option<u32> {
+        self.raw.info.id
+    }
+
+    /// Triggers a self-deasserting reset of the line.
+    ///
+    /// On shared lines the reset is only triggered once, until all sharers have called
+    /// [`SharedResetControl::rearm`].
+    pub fn reset(&self) -> Result {
+        self.raw.reset()
+    }
+
+    /// Returns whether the reset line is asserted.
+    pub fn status(&self) -> Result<bool> {
+        self.raw.status()
+    }
+
+    /// Calls `notify` when the controller of the line is about to be unregistered, e.g., on
+    /// hot-unplug or device tree overlay removal, so that the consumer can quiesce its device.
+    ///
+    /// Once the controller is unregistered, operations on the control fail with `ENODEV` instead
+    /// of reaching it, even if it comes back: consumers must get the line again. The reference
+    /// to the line is then leaked when the control is dropped, as releasing it would go through
+    /// the controller. Setting a new callback replaces the previous one. Fails with `ENOTSUPP`
+    /// for controllers registered from C and lines wired to a GPIO, whose removal is not known,
+    /// and with `ENODEV` if the controller is already gone.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn on_provider_removed(&self, notify: RemovalNotify) -> Result {
+        match &self.raw.topology {
+            Some(topology) => topology.set_removal_notify(notify),
+            None => Err(ENOTSUPP),
+        }
+    }
+
+    /// Returns whether the controller of the line was unregistered, see
+    /// [`ResetControl::on_provider_removed`].
+    pub fn is_provider_removed(&self) -> bool {
+        self.raw.topology.as_ref().map_or(false, |t| t.is_removed())
+    }
+
+    /// Returns whether the controller can trigger self-deasserting resets.
+    ///
+    /// Returns `None` if it is unknown, for controllers registered from C.
+    pub fn supports_reset(&self) -> Option<bool> {
+        self.raw.provider_ops.map(|ops| ops.reset)
+    }
+
+    /// Returns whether the controller can assert the line.
+    ///
+    /// Returns `None` if it is unknown, for controllers registered from C.
+    pub fn supports_assert(&self) -> Option<bool> {
+        self.raw.provider_ops.map(|ops| ops.assert)
+    }
+
+    /// Returns whether the controller can deassert the line.
+    ///
+    /// Returns `None` if it is unknown, for controllers registered from C.
+    pub fn supports_deassert(&self) -> Option<bool> {
+        self.raw.provider_ops.map(|ops| ops.deassert)
+    }
+
+    /// Returns whether the controller can report the status of the line.
+    ///
+    /// Returns `None` if it is unknown, for controllers registered from C. Consumers then fall
+    /// back to a fixed delay, or to calling [`ResetControl::status`] and handling `ENOTSUPP`.
+    pub fn supports_status(&self) -> Option<bool> {
+        self.raw.provider_ops.map(|ops| ops.status)
+    }
+
+    /// Calls `op` on the control, retrying while the controller reports being busy.
+    ///
+    /// `op` is called up to `attempts` times while it fails with `EAGAIN`, sleeping `backoff`
+    /// before the first retry and doubling the sleep before each of the next ones. The last
+    /// error is returned if all attempts fail.
+    ///
+    /// See [`ResetControl::retry`] for other policies.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn retry_busy<R>(
+        &self,
+        attempts: u32,
+        backoff: Duration,
+        op: impl FnMut(&Self) -> Result<R>,
+    ) -> Result<R> {
+        let policy = RetryPolicy {
+            max_attempts: attempts,
+            backoff,
+            max_backoff: Duration::MAX,
+            errors: retry::BUSY,
+        };
+        self.retry(&policy, op)
+    }
+
+    /// Calls `op` on the control, retrying it as described by `policy`.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn retry<R>(
+        &self,
+        policy: &RetryPolicy,
+        mut op: impl FnMut(&Self) -> Result<R>,
+    ) -> Result<R> {
+        policy.run(|| op(self))
+    }
+
+    /// Triggers a reset and waits until the line reports being deasserted.
+    ///
+    /// The status is polled every `poll_interval`, and `ETIMEDOUT` is returned if the line is
+    /// still asserted after `timeout`. Errors reading the status, including `ENOTSUPP` when the
+    /// controller cannot report it, are returned as is.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn reset_with_timeout(&self, timeout: Duration, poll_interval: Duration) -> Result {
+        self.reset()?;
+
+        // SAFETY: `ktime_get` has no safety requirements.
+        let deadline = unsafe { bindings::ktime_get() }
+            .saturating_add(i64::try_from(timeout.as_nanos()).unwrap_or(i64::MAX));
+        loop {
+            // Sample the clock before the status, so that a read delayed past the deadline still
+            // gets to report success.
+            // SAFETY: `ktime_get` has no safety requirements.
+            let expired = unsafe { bindings::ktime_get() } > deadline;
+            if !self.status()? {
+                return Ok(());
+            }
+            if expired {
+                return Err(ETIMEDOUT);
+            }
+            coarse_sleep(poll_interval);
+        }
+    }
+
+    /// Like [`ResetControl::reset_with_timeout`], but retries the whole reset as described by
+    /// `policy`, e.g., when firmware sometimes misses a request.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn reset_with_timeout_retry(
+        &self,
+        timeout: Duration,
+        poll_interval: Duration,
+        policy: &RetryPolicy,
+    ) -> Result {
+        policy.run(|| self.reset_with_timeout(timeout, poll_interval))
+    }
+
+    /// Queues a reset of the line on the system workqueue.
+    ///
+    /// Returns a [`ResetCompletion`] that can be polled or waited on, so that long resets (e.g.,
+    /// ones mediated by firmware) can overlap with other initialisation. The line is kept
+    /// referenced until the reset has run, even if the control and the completion are dropped.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn reset_async(&self) -> Result<ResetCompletion> {
+        let mut state = Pin::from(UniqueArc::try_new(AsyncState {
+            // SAFETY: `spinlock_init` is called below.
+            result: unsafe { SpinLock::new(None) },
+            // SAFETY: `condvar_init` is called below.
+            done: unsafe { CondVar::new() },
+        })?);
+
+        // SAFETY: `result` is pinned when `state` is.
+        let result = unsafe { state.as_mut().map_unchecked_mut(|s| &mut s.result) };
+        crate::spinlock_init!(result, "ResetCompletion::result");
+
+        // SAFETY: `done` is pinned when `state` is.
+        let done = unsafe { state.as_mut().map_unchecked_mut(|s| &mut s.done) };
+        crate::condvar_init!(done, "ResetCompletion::done");
+
+        let state: Arc<AsyncState> = state.into();
+        let raw = self.raw.clone();
+        let work_state = state.clone();
+        spawn_work_item!(workqueue::system(), move || {
+            let ret = raw.reset();
+            *work_state.result.lock() = Some(ret);
+            work_state.done.notify_all();
+        })?;
+
+        Ok(ResetCompletion { state })
+    }
+}
+
+/// Puts a control handed over with [`ResetControl::into_devm`].
+///
+/// # Safety
+///
+/// `ptr` must have been returned by `into_foreign` on an `Arc<RawControl>`.
+unsafe extern "C" fn devm_release(ptr: *mut c_void) {
+    // SAFETY: Guaranteed by the safety requirements of the function.
+    drop(unsafe { Arc::<RawControl>::from_foreign(ptr) });
+}
+
+impl ResetControl<Exclusive> {
+    /// Gets an exclusive reference to the reset line named `id` of `dev`.
+    ///
+    /// When `id` is `None`, the first line of the device is used.
+    ///
+    /// Devices whose line is wired to a GPIO instead, described by a `reset-gpios` property (or
+    /// `<id>-reset-gpios` when `id` is set), get a control driving that GPIO, which starts
+    /// asserted. Resets of such lines hold the GPIO high for 1 ms and sleep, as do all operations
+    /// if the GPIO controller sleeps.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn get_exclusive(dev: &impl RawDevice, id: Option<&CStr>) -> Result<Self> {
+        Self::get(dev, id, false, true)?.ok_or(ENOENT)
+    }
+
+    /// Like [`ExclusiveResetControl::get_exclusive`], but returns `None` if the device has no
+    /// such line.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn get_optional_exclusive(dev: &impl RawDevice, id: Option<&CStr>) -> Result<Option<Self>> {
+        Self::get(dev, id, true, true)
+    }
+
+    /// Asserts the reset line.
+    pub fn assert(&self) -> Result {
+        self.raw.assert()
+    }
+
+    /// Deasserts the reset line.
+    ///
+    /// Fails with `EBUSY` while the line is held asserted for system suspend.
+    pub fn deassert(&self) -> Result {
+        self.raw.deassert()
+    }
+
+    /// Sets whether the line must stay asserted while the system is suspended.
+    ///
+    /// The framework asserts such lines from its syscore operations, once all devices are
+    /// suspended, and puts them back in the state last requested by the consumer on resume,
+    /// before devices resume. Deasserts and resets fail with `EBUSY` in between. If a line cannot
+    /// be asserted, or its status shows it is still deasserted, the suspend fails. Syscore
+    /// operations do not run for suspend-to-idle, which leaves the lines alone.
+    ///
+    /// Fails with `ENOTSUPP` if the controller was not registered from Rust, or if its operations
+    /// cannot run with interrupts disabled, as it would not be enforced.
+    pub fn set_keep_asserted_in_suspend(&self, keep: bool) -> Result {
+        let raw = &*self.raw;
+        let controller = raw.topology.as_ref().and_then(|t| t.controller()).ok_or(ENOTSUPP)?;
+        with_kept_lines(|| {
+            let kept = KeptLines::find(controller).ok_or(ENOTSUPP)?;
+            kept.remove(raw);
+            raw.suspend.keep_asserted.store(keep, Ordering::Relaxed);
+            if keep {
+                kept.push(raw);
+            }
+            Ok(())
+        })
+    }
+}
+
+impl ResetControl<Released> {
+    /// Gets an exclusive reference to a reset line that still has to be acquired with
+    /// [`ReleasedResetControl::acquire`] before it can be asserted or deasserted.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn get_exclusive_released(dev: &impl RawDevice, id: Option<&CStr>) -> Result<Self> {
+        Self::get(dev, id, false, false)?.ok_or(ENOENT)
+    }
+
+    /// Acquires exclusive use of the line.
+    ///
+    /// Fails with `EBUSY` if another consumer holds the line. The line is released again when
+    /// the returned guard is dropped. The control is borrowed mutably meanwhile, so that it
+    /// cannot be acquired a second time and released by the first guard dropped.
+    pub fn acquire(&mut self) -> Result<AcquiredResetControl<'_>> {
+        // SAFETY: By the type invariants of `RawControl`, the pointer is valid.
+        to_result(unsafe { bindings::reset_control_acquire(self.as_ptr()) })?;
+        Ok(AcquiredResetControl { control: self })
+    }
+}
+
+/// Exclusive use of a line, returned by [`ReleasedResetControl::acquire`].
+///
+/// Releases the line when dropped, so that another consumer may acquire it.
+pub struct AcquiredResetControl<'a> {
+    control: &'a ReleasedResetControl,
+}
+
+impl AcquiredResetControl<'_> {
+    /// Asserts the reset line.
+    pub fn assert(&self) -> Result {
+        self.control.raw.assert()
+    }
+
+    /// Deasserts the reset line.
+    pub fn deassert(&self) -> Result {
+        self.control.raw.deassert()
+    }
+}
+
+impl Drop for AcquiredResetControl<'_> {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants of `RawControl`, the pointer is valid.
+        unsafe { bindings::reset_control_release(self.control.as_ptr()) };
+    }
+}
+
+impl ResetControl<Shared> {
+    /// Gets a shared reference to the reset line named `id` of `dev`.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn get_shared(dev: &impl RawDevice, id: Option<&CStr>) -> Result<Self> {
+        Self::get(dev, id, false, false)?.ok_or(ENOENT)
+    }
+
+    /// Like [`SharedResetControl::get_shared`], but returns `None` if the device has no such
+    /// line.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn get_optional_shared(dev: &impl RawDevice, id: Option<&CStr>) -> Result<Option<Self>> {
+        Self::get(dev, id, true, false)
+    }
+
+    /// Allows a line that has been reset to be reset again.
+    pub fn rearm(&self) -> Result {
+        self.raw.rearm()
+    }
+
+    /// Deasserts the line on behalf of this sharer.
+    ///
+    /// The line is kept deasserted until every returned [`SharedDeassert`] is dropped, so a
+    /// sharer can never assert the line under the feet of the others.
+    pub fn deassert(&self) -> Result<SharedDeassert> {
+        self.raw.deassert()?;
+        Ok(SharedDeassert {
+            control: self.clone(),
+        })
+    }
+}
+
+impl Clone for ResetControl<Shared> {
+    fn clone(&self) -> Self {
+        Self {
+            raw: self.raw.clone(),
+            _kind: PhantomData,
+        }
+    }
+}
+
+/// A deassertion of a shared line, returned by [`SharedResetControl::deassert`].
+///
+/// Gives up the deassertion when dropped.
+pub struct SharedDeassert {
+    control: SharedResetControl,
+}
+
+impl Drop for SharedDeassert {
+    fn drop(&mut self) {
+        // The C core only asserts the line once the last sharer gives up its deassertion.
+        let _ = self.control.raw.assert();
+    }
+}
+
+struct AsyncState {
+    result: SpinLock<Option<Result>>,
+    done: CondVar,
+}
+
+/// A handle to a reset queued by [`ResetControl::reset_async`].
+pub struct ResetCompletion {
+    state: Arc<AsyncState>,
+}
+
+impl ResetCompletion {
+    /// Returns the result of the reset, or `None` if it hasn't completed yet.
+    pub fn poll(&self) -> Option<Result> {
+        *self.state.result.lock()
+    }
+
+    /// Waits for the reset to complete and returns its result.
+    ///
+    /// Returns `EINTR` if the wait was interrupted by a signal, the reset still completes in that
+    /// case and the handle can be waited on again.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn wait(&self) -> Result {
+        let mut guard = self.state.result.lock();
+        loop {
+            if let Some(ret) = *guard {
+                return ret;
+            }
+            if self.state.done.wait(&mut guard) {
+                return Err(EINTR);
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Device coredumps captured when operations keep failing on a line.
+//!
+//! Providers opt in with [`ResetDriverOps::COREDUMP_AFTER_FAILURES`]. The dump is text, readable
+//! from `/sys/class/devcoredump/devcd*/data`:
+//!
+//! ```text
+//! driver: sun50i-r-ccu
+//! controller: 1000.reset-controller
+//! failed: Deassert on line 3 (usb), 5 times in a row
+//!
+//! lines:
+//! 3 (usb): asserted for 1200 ms, 5 failures
+//!
+//! last operations:
+//! 532871002 ns: Deassert on line 3 -> -110
+//!
+//! provider:
+//! <bytes from ResetDriverOps::coredump>
+//! ```
+//!
+//! Only lines with a failure or that are asserted are listed.
+//!
+//! [`ResetDriverOps::COREDUMP_AFTER_FAILURES`]: super::ResetDriverOps::COREDUMP_AFTER_FAILURES
+
+use super::{
+    stats::{LineTimes, OpHistory},
+    OfXlate, ResetOp,
+};
+use crate::{
+    bindings,
+    device::{self, RawDevice},
+    error::{code::*, Result},
+    pr_warn,
+    str::CStr,
+};
+
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+/// Context of a failure to dump.
+pub(crate) struct Failure<'a> {
+    /// [`super::ResetDriverOps::NAME`].
+    pub(crate) driver: &'a CStr,
+    pub(crate) op: ResetOp,
+    pub(crate) id: u64,
+    pub(crate) count: u32,
+    pub(crate) xlate: &'a OfXlate,
+    pub(crate) times: &'a LineTimes,
+    pub(crate) history: &'a OpHistory,
+}
+
+struct DumpWriter(Vec<u8>);
+
+impl Write for DumpWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.try_extend_from_slice(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+fn write_line_name(w: &mut DumpWriter, xlate: &OfXlate, id: u64) -> fmt::Result {
+    match xlate.name_of(id) {
+        Some(name) => write!(w, "{} ({})", id, name),
+        None => write!(w, "{}", id),
+    }
+}
+
+fn write_dump(w: &mut DumpWriter, dev: &device::Device, f: &Failure<'_>) -> fmt::Result {
+    writeln!(w, "driver: {}", f.driver)?;
+    writeln!(w, "controller: {}", dev.name())?;
+    write!(w, "failed: {:?} on line ", f.op)?;
+    write_line_name(w, f.xlate, f.id)?;
+    writeln!(w, ", {} times in a row\n\nlines:", f.count)?;
+    for id in 0..f.times.len() as u64 {
+        let failures = f.times.failures(id);
+        let asserted = f.times.get(id).and_then(|t| t.current);
+        if failures == 0 && asserted.is_none() {
+            continue;
+        }
+        write_line_name(w, f.xlate, id)?;
+        match asserted {
+            Some(t) => write!(w, ": asserted for {} ms", t.as_millis())?,
+            None => write!(w, ": deasserted")?,
+        }
+        writeln!(w, ", {} failures", failures)?;
+    }
+    writeln!(w, "\nlast operations:")?;
+    for r in f.history.iter() {
+        writeln!(w, "{} ns: {:?} on line {} -> {}", r.time, r.op, r.id, r.errno)?;
+    }
+    writeln!(w, "\nprovider:")
+}
+
+/// Hands a coredump of `failure` on the controller of `dev` to the devcoredump framework,
+/// followed by `blob`.
+///
+/// Must be called from process context. Failures are logged and otherwise ignored.
+pub(crate) fn capture(dev: &device::Device, failure: &Failure<'_>, blob: &[u8]) {
+    if let Err(e) = try_capture(dev, failure, blob) {
+        pr_warn!(
+            "{} {}: failed to capture reset coredump: {:?}\n",
+            failure.driver,
+            dev.name(),
+            e
+        );
+    }
+}
+
+#[cfg(CONFIG_DEV_COREDUMP)]
+fn try_capture(dev: &device::Device, failure: &Failure<'_>, blob: &[u8]) -> Result {
+    let mut w = DumpWriter(Vec::new());
+    write_dump(&mut w, dev, failure).map_err(|_| ENOMEM)?;
+    w.0.try_extend_from_slice(blob)?;
+
+    // SAFETY: `vmalloc` has no safety requirements.
+    let buf = unsafe { bindings::vmalloc(w.0.len() as _) } as *mut u8;
+    if buf.is_null() {
+        return Err(ENOMEM);
+    }
+    // SAFETY: `buf` was just allocated with room for `w.0.len()` bytes.
+    unsafe { core::ptr::copy_nonoverlapping(w.0.as_ptr(), buf, w.0.len()) };
+    // SAFETY: `dev` is a valid device and `buf` is a vmalloc'ed buffer of `w.0.len()` bytes,
+    // whose ownership is transferred to the devcoredump framework.
+    unsafe {
+        bindings::dev_coredumpv(
+            dev.raw_device(),
+            buf as *mut _,
+            w.0.len() as _,
+            bindings::GFP_KERNEL,
+        )
+    };
+    Ok(())
+}
+
+#[cfg(not(CONFIG_DEV_COREDUMP))]
+fn try_capture(_dev: &device::Device, _failure: &Failure<'_>, _blob: &[u8]) -> Result {
+    Err(ENOTSUPP)
+}
@@ -0,0 +1,92 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Ready-made [`ResetDriverOps::Data`] types for controllers with mutable state.
+//!
+//! Operations only get shared access to the data, so state that changes at run time (shadow
+//! registers, firmware sequence numbers, ...) has to be behind a lock.
+//!
+//! # Choosing a lock
+//!
+//! Consumers may call into the reset core with interrupts disabled or with spinlocks held, and
+//! the core calls the operations directly from there. Operations must therefore not sleep unless
+//! every consumer of the controller is known to call from process context:
+//!
+//! - [`SpinLockedResetData`] may be used from any context, and is the right choice for controllers
+//!   whose operations are plain register accesses. As operations may be called from interrupt
+//!   handlers, it must be locked with `lock_irqdisable`: an interrupt calling into the controller
+//!   while the lock is held on the same CPU would otherwise deadlock.
+//! - [`LockedResetData`] sleeps when contended, and may only be used by controllers whose
+//!   operations sleep anyway (e.g., because they talk to firmware over a mailbox, I2C or USB).
+//!
+//! ```ignore
+//! struct State { shadow: u32 }
+//!
+//! #[vtable]
+//! impl ResetDriverOps for MyReset {
+//!     type Data = SpinLockedResetData<State>;
+//!
+//!     fn assert(data: ArcBorrow<'_, SpinLock<State>>, req: ResetRequest) -> Result<i32> {
+//!         let mut state = data.lock_irqdisable();
+//!         state.shadow |= 1 << req.id();
+//!         Ok(0)
+//!     }
+//! }
+//!
+//! let data = new_spinlocked_reset_data!(State { shadow: 0 })?;
+//! reset_controller_register!(reg.as_mut(), &mut pdev, 32, data)?;
+//! ```
+//!
+//! The data is allocated with [`new_spinlocked_reset_data`] or [`new_locked_reset_data`], which
+//! are macros so that each driver gets its own lockdep class.
+//!
+//! [`ResetDriverOps::Data`]: super::ResetDriverOps::Data
+//! [`new_spinlocked_reset_data`]: crate::new_spinlocked_reset_data
+//! [`new_locked_reset_data`]: crate::new_locked_reset_data
+
+use crate::sync::{Arc, Mutex, SpinLock};
+
+/// Controller data behind a mutex, for controllers whose operations may sleep.
+pub type LockedResetData<T> = Arc<Mutex<T>>;
+
+/// Controller data behind a spinlock, for controllers whose operations may run in atomic context.
+pub type SpinLockedResetData<T> = Arc<SpinLock<T>>;
+
+/// Allocates and initialises a [`LockedResetData`] holding `data`.
+///
+/// The lockdep class of the mutex is defined where the macro is used.
+///
+/// [`LockedResetData`]: crate::reset::LockedResetData
+#[macro_export]
+macro_rules! new_locked_reset_data {
+    ($data:expr $(,)?) => {{
+        let data = $data;
+        (|| -> $crate::error::Result<$crate::reset::LockedResetData<_>> {
+            // SAFETY: `mutex_init` is called below.
+            let mut locked = ::core::pin::Pin::from($crate::sync::UniqueArc::try_new(unsafe {
+                $crate::sync::Mutex::new(data)
+            })?);
+            $crate::mutex_init!(locked.as_mut(), "LockedResetData");
+            Ok(locked.into())
+        })()
+    }};
+}
+
+/// Allocates and initialises a [`SpinLockedResetData`] holding `data`.
+///
+/// The lockdep class of the spinlock is defined where the macro is used.
+///
+/// [`SpinLockedResetData`]: crate::reset::SpinLockedResetData
+#[macro_export]
+macro_rules! new_spinlocked_reset_data {
+    ($data:expr $(,)?) => {{
+        let data = $data;
+        (|| -> $crate::error::Result<$crate::reset::SpinLockedResetData<_>> {
+            // SAFETY: `spinlock_init` is called below.
+            let mut locked = ::core::pin::Pin::from($crate::sync::UniqueArc::try_new(unsafe {
+                $crate::sync::SpinLock::new(data)
+            })?);
+            $crate::spinlock_init!(locked.as_mut(), "SpinLockedResetData");
+            Ok(locked.into())
+        })()
+    }};
+}
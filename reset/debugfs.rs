@@ -0,0 +1,114 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Debugfs files of the reset framework, under `/sys/kernel/debug/reset`.
+//!
+//! C header: [`include/linux/debugfs.h`](../../../../include/linux/debugfs.h)
+
+use crate::{bindings, str::CStr};
+
+/// Function printing the contents of a debugfs file.
+pub(crate) type ShowFn = fn(&mut SeqWriter) -> core::fmt::Result;
+
+/// Writer into the `seq_file` of a debugfs file being read.
+pub(crate) struct SeqWriter(*mut bindings::seq_file);
+
+impl core::fmt::Write for SeqWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        // SAFETY: `self.0` is the `seq_file` passed to `show`, valid for the duration of the call.
+        let ret = unsafe { bindings::seq_write(self.0, s.as_ptr().cast(), s.len()) };
+        if ret != 0 {
+            return Err(core::fmt::Error);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(CONFIG_DEBUG_FS)]
+mod imp {
+    use super::{SeqWriter, ShowFn};
+    use crate::{bindings, c_str, str::CStr, sync::smutex::Mutex};
+
+    use core::ffi::{c_int, c_void};
+
+    struct Root(*mut bindings::dentry);
+
+    // SAFETY: The dentry is only used to create files under it, which can be done from any
+    // thread.
+    unsafe impl Send for Root {}
+
+    static ROOT: Mutex<Option<Root>> = Mutex::new(None);
+
+    struct FileOps(bindings::file_operations);
+
+    // SAFETY: The operations are never modified.
+    unsafe impl Sync for FileOps {}
+
+    const fn zeroed_fops() -> bindings::file_operations {
+        // SAFETY: All fields of `file_operations` are integers or (function) pointers, for which
+        // all zeroes is a valid value.
+        unsafe { core::mem::transmute([0u8; core::mem::size_of::<bindings::file_operations>()]) }
+    }
+
+    static FOPS: FileOps = FileOps(bindings::file_operations {
+        open: Some(open),
+        read: Some(bindings::seq_read),
+        llseek: Some(bindings::seq_lseek),
+        release: Some(bindings::single_release),
+        ..zeroed_fops()
+    });
+
+    unsafe extern "C" fn open(inode: *mut bindings::inode, file: *mut bindings::file) -> c_int {
+        // SAFETY: The inode's private data is the `ShowFn` passed to `debugfs_create_file`.
+        unsafe { bindings::single_open(file, Some(show), (*inode).i_private) }
+    }
+
+    unsafe extern "C" fn show(m: *mut bindings::seq_file, _v: *mut c_void) -> c_int {
+        // SAFETY: `single_open` stored the `ShowFn` as private data of the `seq_file`.
+        let f: ShowFn = unsafe { core::mem::transmute((*m).private) };
+        // Writes only fail when the buffer is full, `seq_file` then notices the overflow and
+        // calls us again with a larger buffer, so the error is not reported.
+        let _ = f(&mut SeqWriter(m));
+        0
+    }
+
+    /// Creates a read-only file called `name` in the reset debugfs directory.
+    ///
+    /// Errors are ignored, debugfs is best effort.
+    pub(crate) fn create_file(name: &'static CStr, f: ShowFn) {
+        let mut root = ROOT.lock();
+        if root.is_none() {
+            // SAFETY: The name is a valid C string, and a null parent means the debugfs root.
+            let dir = unsafe {
+                bindings::debugfs_create_dir(c_str!("reset").as_char_ptr(), core::ptr::null_mut())
+            };
+            *root = Some(Root(dir));
+        }
+        if let Some(Root(dir)) = *root {
+            // SAFETY: `dir` was returned by `debugfs_create_dir` and is never removed, `FOPS`
+            // lives forever and `f` is a plain function pointer.
+            unsafe {
+                bindings::debugfs_create_file(
+                    name.as_char_ptr(),
+                    0o444,
+                    dir,
+                    f as *mut c_void,
+                    &FOPS.0,
+                )
+            };
+        }
+    }
+}
+
+#[cfg(not(CONFIG_DEBUG_FS))]
+mod imp {
+    use super::ShowFn;
+    use crate::str::CStr;
+
+    pub(crate) fn create_file(_name: &'static CStr, _f: ShowFn) {}
+}
+
+/// Creates a read-only file called `name` in the reset debugfs directory, whose contents are
+/// printed by `f`.
+pub(crate) fn create_file(name: &'static CStr, f: ShowFn) {
+    imp::create_file(name, f)
+}
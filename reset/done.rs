@@ -0,0 +1,127 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Waiting for lines to report that their reset completed.
+//!
+//! Some controllers return before the reset actually completes, and report completion later
+//! through a status bit or an interrupt. Operations of such controllers trigger the reset, then
+//! wait for completion with [`poll_done`] or a [`ResetDoneIrq`], so that consumers see a timeout
+//! instead of a spurious success:
+//!
+//! ```ignore
+//! fn reset(data: &Foo, req: ResetRequest) -> Result<i32> {
+//!     let id = req.id();
+//!     data.regs.writel(1 << id, RESET_TRIGGER);
+//!     poll_done(|| Ok(data.regs.readl(RESET_DONE) & (1 << id) != 0), TIMEOUTS.get(id))?;
+//!     Ok(0)
+//! }
+//! ```
+//!
+//! Operations may run in atomic context, where the waits busy-loop instead of sleeping, so
+//! timeouts should stay short.
+
+use super::offload;
+use crate::{
+    bindings,
+    delay::coarse_sleep,
+    error::{code::*, Result},
+};
+
+use alloc::vec::Vec;
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+/// Interval between two polls in atomic context, in microseconds.
+const ATOMIC_POLL_US: u64 = 10;
+
+/// Interval between two polls in process context.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Per-line timeouts of the wait for reset completion.
+#[derive(Clone, Copy, Debug)]
+pub struct DoneTimeouts {
+    /// Timeout of the lines missing from `lines`.
+    pub default: Duration,
+    /// Lines with their own timeout, e.g., blocks running a long self-test out of reset.
+    pub lines: &'static [(u64, Duration)],
+}
+
+impl DoneTimeouts {
+    /// Returns the timeout of line `id`.
+    pub fn get(&self, id: u64) -> Duration {
+        self.lines
+            .iter()
+            .find(|(line, _)| *line == id)
+            .map_or(self.default, |(_, timeout)| *timeout)
+    }
+}
+
+/// Polls `done` until it returns `true`, failing with `ETIMEDOUT` after `timeout`.
+///
+/// Sleeps between polls in process context, and busy-waits in atomic context. Errors returned by
+/// `done` are propagated.
+pub fn poll_done(mut done: impl FnMut() -> Result<bool>, timeout: Duration) -> Result {
+    // SAFETY: `ktime_get` has no safety requirements.
+    let start = unsafe { bindings::ktime_get() };
+    let timeout_ns = timeout.as_nanos().min(i64::MAX as u128) as i64;
+    loop {
+        if done()? {
+            return Ok(());
+        }
+        // SAFETY: `ktime_get` has no safety requirements.
+        if unsafe { bindings::ktime_get() }.saturating_sub(start) > timeout_ns {
+            // The last poll may have been delayed past the timeout, give it a final chance.
+            return if done()? { Ok(()) } else { Err(ETIMEDOUT) };
+        }
+        if offload::in_atomic_context() {
+            // SAFETY: `__udelay` has no safety requirements.
+            unsafe { bindings::__udelay(ATOMIC_POLL_US as _) };
+        } else {
+            coarse_sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// Completion of resets reported by an interrupt.
+///
+/// Operations arm a line before triggering its reset and wait for it, while the interrupt
+/// handler of the controller completes the lines it reports done. All methods may be called from
+/// any context.
+pub struct ResetDoneIrq {
+    done: Vec<AtomicBool>,
+}
+
+impl ResetDoneIrq {
+    /// Creates the completions of `nr_resets` lines.
+    pub fn try_new(nr_resets: u32) -> Result<Self> {
+        let mut done = Vec::try_with_capacity(nr_resets as usize)?;
+        for _ in 0..nr_resets {
+            done.try_push(AtomicBool::new(false))?;
+        }
+        Ok(Self { done })
+    }
+
+    fn line(&self, id: u64) -> Result<&AtomicBool> {
+        self.done.get(id as usize).ok_or(EINVAL)
+    }
+
+    /// Prepares to wait for line `id`, must be called before triggering its reset.
+    pub fn arm(&self, id: u64) -> Result {
+        self.line(id)?.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    /// Reports that the reset of line `id` completed, usually from the interrupt handler.
+    pub fn complete(&self, id: u64) {
+        if let Ok(line) = self.line(id) {
+            line.store(true, Ordering::Release);
+        }
+    }
+
+    /// Waits until line `id` is completed, failing with `ETIMEDOUT` after `timeout`.
+    pub fn wait(&self, id: u64, timeout: Duration) -> Result {
+        let line = self.line(id)?;
+        poll_done(|| Ok(line.load(Ordering::Acquire)), timeout)
+    }
+}
@@ -0,0 +1,91 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Registrations embedded in the driver data of a platform driver.
+//!
+//! The data returned by `platform::Driver::probe` lives exactly as long as the driver is bound,
+//! which is the lifetime of the controller. Drivers embed the [`ResetRegistration`] in their
+//! pinned data, and implement [`HasResetRegistration`] with
+//! [`crate::impl_has_reset_registration`] to reach it without unsafe code:
+//!
+//! ```ignore
+//! struct FooData {
+//!     regs: IoMem<FOO_SIZE>,
+//!     reset: ResetRegistration<FooOps>,
+//! }
+//!
+//! kernel::impl_has_reset_registration!(FooData, reset, FooOps);
+//!
+//! impl platform::Driver for FooDriver {
+//!     type Data = Pin<Box<FooData>>;
+//!
+//!     fn probe(dev: &mut platform::Device, _id: Option<&Self::IdInfo>) -> Result<Self::Data> {
+//!         let mut data = Pin::from(Box::try_new(FooData {
+//!             regs: ...,
+//!             reset: ResetRegistration::new(),
+//!         })?);
+//!         reset_controller_register!(data.as_mut().reset_registration(), dev, 32, ops_data)?;
+//!         Ok(data)
+//!     }
+//! }
+//! ```
+//!
+//! Dropping the data when the driver is unbound unregisters the controller and frees its
+//! [`ResetDriverOps::Data`].
+//!
+//! Controllers implemented in programmable logic and described in the overlay loading the image
+//! are covered the same way. Those exposed by the static part of the design instead follow the
+//! region with [`FpgaRegionResets`].
+//!
+//! [`ResetRegistration`]: super::ResetRegistration
+//! [`ResetDriverOps::Data`]: super::ResetDriverOps::Data
+//! [`FpgaRegionResets`]: super::FpgaRegionResets
+
+use super::{ResetDriverOps, ResetRegistration};
+
+use core::pin::Pin;
+
+/// Pinned driver data embedding a [`ResetRegistration`].
+///
+/// Implemented with [`crate::impl_has_reset_registration`].
+pub trait HasResetRegistration<T: ResetDriverOps> {
+    /// Returns the embedded registration.
+    fn reset_registration(self: Pin<&mut Self>) -> Pin<&mut ResetRegistration<T>>;
+}
+
+/// Implements [`HasResetRegistration`] for `$outer`, whose field `$field` is the registration of
+/// a controller with operations `$ops`.
+///
+/// Like `pin-project`, the macro makes sure that the registration cannot be moved out of a
+/// pinned `$outer`: implementing `Drop` or `Unpin` for `$outer` fails to compile. Driver data
+/// needing cleanup does it in the `Drop` of one of its fields.
+#[macro_export]
+macro_rules! impl_has_reset_registration {
+    ($outer:ty, $field:ident, $ops:ty) => {
+        impl $crate::reset::HasResetRegistration<$ops> for $outer {
+            fn reset_registration(
+                self: ::core::pin::Pin<&mut Self>,
+            ) -> ::core::pin::Pin<&mut $crate::reset::ResetRegistration<$ops>> {
+                // SAFETY: The registration is structurally pinned: `$outer` implements neither
+                // `Drop`, which could move the field out, nor `Unpin`, as checked below.
+                unsafe { self.map_unchecked_mut(|outer| &mut outer.$field) }
+            }
+        }
+
+        const _: () = {
+            // Conflicts with any `Drop` implementation of `$outer`.
+            trait MustNotImplDrop {}
+            #[allow(drop_bounds)]
+            impl<T: ::core::ops::Drop> MustNotImplDrop for T {}
+            impl MustNotImplDrop for $outer {}
+
+            // Conflicts with any `Unpin` implementation of `$outer`. The lifetime keeps the
+            // bound, which never holds, from being rejected as trivially false.
+            #[allow(dead_code)]
+            struct Pinned<'pin>(
+                ::core::marker::PhantomData<&'pin ()>,
+                $crate::reset::ResetRegistration<$ops>,
+            );
+            impl<'pin> ::core::marker::Unpin for $outer where Pinned<'pin>: ::core::marker::Unpin {}
+        };
+    };
+}
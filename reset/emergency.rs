@@ -0,0 +1,650 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Lines asserted by the framework in emergencies.
+//!
+//! Providers register lines together with the event that must force them into reset, e.g., a
+//! thermal zone tripping the cooling device of the controller. Entries are removed
+//! automatically when the controller goes away.
+//!
+//! The same table holds the lines asserted from syscore operations once all devices are
+//! suspended, and deasserted first on resume, for resets gating always-on blocks that must be
+//! ordered outside device power management. The syscore operations then assert the lines
+//! consumers [keep asserted] while the system is suspended.
+//!
+//! Events may fire in interrupt context, so the lines are kept in a fixed-size table behind a
+//! spinlock, and the operations are called with it held. Controllers whose operations sleep must
+//! set [`ResetDriverOps::OFFLOAD_FROM_ATOMIC`] to take part.
+//!
+//! The panic notifier runs with the other CPUs stopped, possibly while one of them held the
+//! lock. It never waits for the lock: it skips the table if it is locked, and calls the
+//! operations of the providers directly, without the accounting, logging and queueing of regular
+//! operations.
+//!
+//! [`ResetDriverOps::OFFLOAD_FROM_ATOMIC`]: super::ResetDriverOps::OFFLOAD_FROM_ATOMIC
+//! [keep asserted]: super::ExclusiveResetControl::set_keep_asserted_in_suspend
+
+use super::{control, ResetDevice};
+use crate::{
+    bindings,
+    error::{code::*, Result},
+    pr_err,
+    sync::{Lock, SpinLock},
+};
+
+use alloc::boxed::Box;
+use core::{
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
+
+/// Maximum number of emergency lines in the system.
+const MAX_LINES: usize = 32;
+
+/// What the framework does to an emergency line when its event fires.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmergencyAction {
+    /// Assert the line and leave it asserted.
+    Assert,
+    /// Pulse the line with a self-deasserting reset.
+    Pulse,
+}
+
+/// The event that fires an emergency line.
+pub(crate) enum Trigger {
+    /// A thermal zone tripped the cooling device of the given controller.
+    ThermalTrip(*mut bindings::reset_controller_dev),
+    /// The watchdog with the given id, or any watchdog if `None`, hit its pretimeout.
+    WatchdogPretimeout(Option<i32>),
+    /// The kernel panicked.
+    Panic,
+    /// The system is suspending, all devices are suspended. The lines are deasserted on resume.
+    SyscoreSuspend,
+}
+
+/// Runs an action on a line of a controller with the operations of its provider, bypassing the
+/// framework.
+///
+/// # Safety
+///
+/// The controller must be registered.
+pub(crate) type DirectFn =
+    unsafe fn(*mut bindings::reset_controller_dev, EmergencyAction, u64) -> Result;
+
+struct Entry {
+    trigger: Trigger,
+    action: EmergencyAction,
+    rcdev: *mut bindings::reset_controller_dev,
+    id: u64,
+    direct: DirectFn,
+}
+
+// SAFETY: `rcdev` is only used to call the controller's operations, which can be done from any
+// thread.
+unsafe impl Send for Entry {}
+
+struct Lines {
+    /// The entries in the order they were added, which is the order they fire in, followed by
+    /// the free slots.
+    entries: [Option<Entry>; MAX_LINES],
+}
+
+/// The table of emergency lines, allocated on first use and never freed.
+static LINES: AtomicPtr<SpinLock<Lines>> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Set while the table is locked, see [`with_lines`].
+static LOCKED: AtomicBool = AtomicBool::new(false);
+
+/// Runs `f` with `lines` locked, flagging it for the panic notifier.
+fn with_lines<R>(lines: &SpinLock<Lines>, f: impl FnOnce(&mut Lines) -> R) -> R {
+    let mut guard = lines.lock_irqdisable();
+    LOCKED.store(true, Ordering::Release);
+    let ret = f(&mut guard);
+    LOCKED.store(false, Ordering::Release);
+    ret
+}
+
+/// Returns the table of emergency lines, if it was ever allocated.
+fn lines() -> Option<&'static SpinLock<Lines>> {
+    // SAFETY: Once set, the pointer is valid and initialised forever.
+    unsafe { LINES.load(Ordering::Acquire).as_ref() }
+}
+
+/// Returns the table of emergency lines, allocating it if needed.
+fn lines_or_alloc() -> Result<&'static SpinLock<Lines>> {
+    if let Some(lines) = lines() {
+        return Ok(lines);
+    }
+
+    const NONE: Option<Entry> = None;
+    // SAFETY: `spinlock_init` is called below.
+    let mut new = Box::into_pin(Box::try_new(unsafe {
+        SpinLock::new(Lines {
+            entries: [NONE; MAX_LINES],
+        })
+    })?);
+    crate::spinlock_init!(new.as_mut(), "reset::emergency::LINES");
+    // SAFETY: The lock is never moved out of the box, which is leaked below.
+    let new = Box::into_raw(unsafe { Pin::into_inner_unchecked(new) });
+
+    match LINES.compare_exchange(
+        core::ptr::null_mut(),
+        new,
+        Ordering::AcqRel,
+        Ordering::Acquire,
+    ) {
+        Ok(_) => {}
+        // SAFETY: `new` was returned by `Box::into_raw` above and was not published.
+        Err(_) => drop(unsafe { Box::from_raw(new) }),
+    }
+    lines().ok_or(EINVAL)
+}
+
+/// Registers line `id` of `rcdev` to get `action` on `trigger`, with `direct` from the panic
+/// notifier.
+///
+/// Fails with `ENOSPC` if the table is full.
+///
+/// # Safety
+///
+/// `rcdev` must be valid until [`remove_controller`] is called for it, and `direct` must be safe
+/// to call with it until then.
+pub(crate) unsafe fn add(
+    trigger: Trigger,
+    action: EmergencyAction,
+    rcdev: *mut bindings::reset_controller_dev,
+    id: u64,
+    direct: DirectFn,
+) -> Result {
+    with_lines(lines_or_alloc()?, |lines| {
+        let slot = lines
+            .entries
+            .iter_mut()
+            .find(|e| e.is_none())
+            .ok_or(ENOSPC)?;
+        *slot = Some(Entry {
+            trigger,
+            action,
+            rcdev,
+            id,
+            direct,
+        });
+        Ok(())
+    })
+}
+
+/// Removes all the lines of `rcdev`.
+pub(crate) fn remove_controller(rcdev: *mut bindings::reset_controller_dev) {
+    // The cooling device may fire the lines until it is unregistered.
+    #[cfg(CONFIG_THERMAL_OF)]
+    thermal::remove(rcdev);
+    if let Some(lines) = lines() {
+        with_lines(lines, |lines| {
+            // Pack the remaining entries without reordering them, so that new entries go after
+            // them.
+            let mut kept = 0;
+            for i in 0..MAX_LINES {
+                match lines.entries[i].take() {
+                    Some(e) if e.rcdev != rcdev => {
+                        lines.entries[kept] = Some(e);
+                        kept += 1;
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+}
+
+/// Fires all lines whose trigger matches `pred`, returns how many succeeded.
+///
+/// This is best effort, failures are logged and the remaining lines are still fired.
+fn fire_matching(pred: impl Fn(&Trigger) -> bool) -> usize {
+    let lines = match lines() {
+        Some(lines) => lines,
+        None => return 0,
+    };
+
+    with_lines(lines, |lines| {
+        let mut fired = 0;
+        for e in lines.entries.iter().flatten().filter(|e| pred(&e.trigger)) {
+            // SAFETY: Entries are removed before their controller goes away.
+            let rcdev = unsafe { ResetDevice::from_raw(e.rcdev) };
+            let ret = match e.action {
+                EmergencyAction::Assert => rcdev.assert(e.id),
+                EmergencyAction::Pulse => rcdev.reset(e.id),
+            };
+            match ret {
+                Ok(()) => fired += 1,
+                Err(err) => pr_err!(
+                    "Emergency {:?} of reset line {} failed: {:?}\n",
+                    e.action,
+                    e.id,
+                    err
+                ),
+            }
+        }
+        fired
+    })
+}
+
+/// Fires the lines registered for kernel panics, returns how many succeeded, or `None` if the
+/// table is locked.
+///
+/// Must only be called from the panic notifier, with the other CPUs stopped.
+fn fire_in_panic() -> Option<usize> {
+    let lines = match lines() {
+        Some(lines) => lines,
+        None => return Some(0),
+    };
+    // A stopped CPU may hold the lock forever, never wait for it.
+    if LOCKED.load(Ordering::Acquire) {
+        return None;
+    }
+    // SAFETY: The table is not locked, so no stopped CPU was modifying it, and the other CPUs
+    // are stopped, so none can lock it anymore.
+    let lines = unsafe { &*lines.locked_data().get() };
+    let mut fired = 0;
+    for e in lines.entries.iter().flatten() {
+        if !matches!(e.trigger, Trigger::Panic) {
+            continue;
+        }
+        // SAFETY: Entries are removed before their controller goes away, and `direct` is safe to
+        // call with it until then.
+        if unsafe { (e.direct)(e.rcdev, e.action, e.id) }.is_ok() {
+            fired += 1;
+        }
+    }
+    Some(fired)
+}
+
+/// Deasserts all lines whose trigger matches `pred`, in the reverse order of [`fire_matching`].
+///
+/// This is best effort, failures are logged and the remaining lines are still deasserted.
+fn deassert_matching(pred: impl Fn(&Trigger) -> bool) {
+    let lines = match lines() {
+        Some(lines) => lines,
+        None => return,
+    };
+
+    with_lines(lines, |lines| {
+        for e in lines.entries.iter().rev().flatten().filter(|e| pred(&e.trigger)) {
+            // SAFETY: Entries are removed before their controller goes away.
+            let rcdev = unsafe { ResetDevice::from_raw(e.rcdev) };
+            if let Err(err) = rcdev.deassert(e.id) {
+                pr_err!("Deassert of reset line {} failed: {:?}\n", e.id, err);
+            }
+        }
+    });
+}
+
+#[cfg(CONFIG_THERMAL_OF)]
+mod thermal {
+    use super::{fire_matching, Trigger};
+    use crate::{
+        bindings,
+        error::{code::*, from_kernel_err_ptr, Result},
+        pr_crit,
+        str::CStr,
+        sync::smutex::Mutex,
+    };
+    use alloc::{boxed::Box, vec::Vec};
+    use core::{
+        ffi::{c_int, c_ulong},
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    /// The cooling device of a controller.
+    struct Cooling {
+        rcdev: *mut bindings::reset_controller_dev,
+        cdev: *mut bindings::thermal_cooling_device,
+        /// Whether a zone set the device to its tripped state, until it sets it back to 0.
+        tripped: AtomicBool,
+    }
+
+    // SAFETY: The controller is only compared, and the cooling device is only unregistered,
+    // which can be done from any thread.
+    unsafe impl Send for Cooling {}
+
+    struct Ops(bindings::thermal_cooling_device_ops);
+
+    // SAFETY: The operations are never modified.
+    unsafe impl Sync for Ops {}
+
+    const fn zeroed_ops() -> bindings::thermal_cooling_device_ops {
+        // SAFETY: All fields of `thermal_cooling_device_ops` are optional function pointers, for
+        // which all zeroes is `None`.
+        unsafe {
+            core::mem::transmute(
+                [0u8; core::mem::size_of::<bindings::thermal_cooling_device_ops>()],
+            )
+        }
+    }
+
+    static OPS: Ops = Ops(bindings::thermal_cooling_device_ops {
+        get_max_state: Some(get_max_state),
+        get_cur_state: Some(get_cur_state),
+        set_cur_state: Some(set_cur_state),
+        ..zeroed_ops()
+    });
+
+    static DEVICES: Mutex<Vec<Box<Cooling>>> = Mutex::new(Vec::new());
+
+    /// # Safety
+    ///
+    /// `cdev` must be one of the cooling devices registered in [`add`].
+    unsafe fn cooling<'a>(cdev: *mut bindings::thermal_cooling_device) -> &'a Cooling {
+        // SAFETY: By the safety requirements, `devdata` is the boxed `Cooling`, which is only
+        // freed once the device is unregistered.
+        unsafe { &*((*cdev).devdata as *const Cooling) }
+    }
+
+    unsafe extern "C" fn get_max_state(
+        _cdev: *mut bindings::thermal_cooling_device,
+        state: *mut c_ulong,
+    ) -> c_int {
+        // SAFETY: The thermal core passes a valid pointer.
+        unsafe { *state = 1 };
+        0
+    }
+
+    unsafe extern "C" fn get_cur_state(
+        cdev: *mut bindings::thermal_cooling_device,
+        state: *mut c_ulong,
+    ) -> c_int {
+        // SAFETY: Only the devices registered in `add` have these operations, and the thermal
+        // core passes a valid pointer.
+        unsafe { *state = cooling(cdev).tripped.load(Ordering::Relaxed) as _ };
+        0
+    }
+
+    unsafe extern "C" fn set_cur_state(
+        cdev: *mut bindings::thermal_cooling_device,
+        state: c_ulong,
+    ) -> c_int {
+        // SAFETY: Only the devices registered in `add` have these operations.
+        let c = unsafe { cooling(cdev) };
+        // Back to 0, the lines are left asserted for their consumers to deassert.
+        let was_tripped = c.tripped.swap(state != 0, Ordering::Relaxed);
+        if state != 0 && !was_tripped {
+            let n = fire_matching(|t| matches!(t, Trigger::ThermalTrip(r) if *r == c.rcdev));
+            // SAFETY: The cooling device is registered, so its type is a valid C string.
+            let name = unsafe { CStr::from_char_ptr((*cdev).type_) };
+            pr_crit!("{}: thermal trip, asserted {} reset line(s)\n", name, n);
+        }
+        0
+    }
+
+    /// Registers the cooling device of `rcdev`, if not done yet.
+    ///
+    /// # Safety
+    ///
+    /// `rcdev` must be valid until [`remove`] is called for it.
+    pub(super) unsafe fn add(rcdev: *mut bindings::reset_controller_dev) -> Result {
+        let mut devices = DEVICES.lock();
+        if devices.iter().any(|c| c.rcdev == rcdev) {
+            return Ok(());
+        }
+        // SAFETY: `rcdev` is valid.
+        if unsafe { (*rcdev).of_node }.is_null() {
+            return Err(ENODEV);
+        }
+        devices.try_reserve(1)?;
+        let mut cooling = Box::try_new(Cooling {
+            rcdev,
+            cdev: core::ptr::null_mut(),
+            tripped: AtomicBool::new(false),
+        })?;
+        // SAFETY: `rcdev` is valid, and so are its device and node. The device is unregistered
+        // in `remove`, before `cooling` is freed, and `OPS` lives forever.
+        cooling.cdev = from_kernel_err_ptr(unsafe {
+            bindings::thermal_of_cooling_device_register(
+                (*rcdev).of_node,
+                bindings::dev_name((*rcdev).dev),
+                &*cooling as *const Cooling as *mut _,
+                &OPS.0,
+            )
+        })?;
+        devices.try_push(cooling)?;
+        Ok(())
+    }
+
+    /// Unregisters the cooling device of `rcdev`, if any.
+    pub(super) fn remove(rcdev: *mut bindings::reset_controller_dev) {
+        let mut devices = DEVICES.lock();
+        if let Some(i) = devices.iter().position(|c| c.rcdev == rcdev) {
+            let cooling = devices.swap_remove(i);
+            // SAFETY: `cdev` was registered in `add`. Once this returns, its operations do not
+            // run anymore.
+            unsafe { bindings::thermal_cooling_device_unregister(cooling.cdev) };
+        }
+    }
+}
+
+#[cfg(CONFIG_WATCHDOG_PRETIMEOUT_GOV)]
+mod watchdog {
+    use super::{fire_matching, Trigger};
+    use crate::{bindings, error::to_result, error::Result, pr_crit, sync::smutex::Mutex};
+
+    struct Governor(bindings::watchdog_governor);
+
+    // SAFETY: The governor is never modified.
+    unsafe impl Sync for Governor {}
+
+    const fn name() -> [core::ffi::c_char; bindings::WATCHDOG_GOV_NAME_MAXLEN as usize] {
+        let mut name = [0; bindings::WATCHDOG_GOV_NAME_MAXLEN as usize];
+        let s = b"reset";
+        let mut i = 0;
+        while i < s.len() {
+            name[i] = s[i] as _;
+            i += 1;
+        }
+        name
+    }
+
+    const fn zeroed_governor() -> bindings::watchdog_governor {
+        // SAFETY: All fields of `watchdog_governor` are integers or (function) pointers, for
+        // which all zeroes is a valid value.
+        unsafe {
+            core::mem::transmute([0u8; core::mem::size_of::<bindings::watchdog_governor>()])
+        }
+    }
+
+    static GOVERNOR: Governor = Governor(bindings::watchdog_governor {
+        name: name(),
+        pretimeout: Some(pretimeout),
+        ..zeroed_governor()
+    });
+
+    static REGISTERED: Mutex<bool> = Mutex::new(false);
+
+    unsafe extern "C" fn pretimeout(wdd: *mut bindings::watchdog_device) {
+        // SAFETY: The watchdog core passes a valid device.
+        let wdd_id = unsafe { (*wdd).id };
+        let n = fire_matching(|t| match t {
+            Trigger::WatchdogPretimeout(w) => w.map_or(true, |w| w == wdd_id),
+            _ => false,
+        });
+        pr_crit!("watchdog{}: pretimeout, fired {} reset line(s)\n", wdd_id, n);
+    }
+
+    /// Registers the `reset` pretimeout governor, if not done yet.
+    pub(super) fn register_governor() -> Result {
+        let mut registered = REGISTERED.lock();
+        if !*registered {
+            // SAFETY: `GOVERNOR` lives forever, and the watchdog core does not modify it.
+            to_result(unsafe {
+                bindings::watchdog_register_governor(&GOVERNOR.0 as *const _ as *mut _)
+            })?;
+            *registered = true;
+        }
+        Ok(())
+    }
+}
+
+mod panic {
+    use super::fire_in_panic;
+    use crate::{bindings, error::to_result, error::Result, pr_emerg, sync::smutex::Mutex};
+    use core::cell::UnsafeCell;
+
+    struct Notifier(UnsafeCell<bindings::notifier_block>);
+
+    // SAFETY: The notifier block is only modified by the notifier chain, under its own lock.
+    unsafe impl Sync for Notifier {}
+
+    const fn zeroed_notifier() -> bindings::notifier_block {
+        // SAFETY: All fields of `notifier_block` are integers or (function) pointers, for which
+        // all zeroes is a valid value.
+        unsafe { core::mem::transmute([0u8; core::mem::size_of::<bindings::notifier_block>()]) }
+    }
+
+    static NOTIFIER: Notifier = Notifier(UnsafeCell::new(bindings::notifier_block {
+        notifier_call: Some(notify),
+        ..zeroed_notifier()
+    }));
+
+    static REGISTERED: Mutex<bool> = Mutex::new(false);
+
+    unsafe extern "C" fn notify(
+        _nb: *mut bindings::notifier_block,
+        _event: core::ffi::c_ulong,
+        _data: *mut core::ffi::c_void,
+    ) -> core::ffi::c_int {
+        match fire_in_panic() {
+            Some(0) => {}
+            Some(n) => pr_emerg!("panic: asserted {} reset line(s)\n", n),
+            None => pr_emerg!("panic: reset lines locked, not asserted\n"),
+        }
+        bindings::NOTIFY_DONE as _
+    }
+
+    /// Registers the panic notifier, if not done yet.
+    pub(super) fn register_notifier() -> Result {
+        let mut registered = REGISTERED.lock();
+        if !*registered {
+            // SAFETY: `NOTIFIER` lives forever and is never unregistered.
+            to_result(unsafe {
+                bindings::atomic_notifier_chain_register(
+                    &mut bindings::panic_notifier_list,
+                    NOTIFIER.0.get(),
+                )
+            })?;
+            *registered = true;
+        }
+        Ok(())
+    }
+}
+
+mod syscore {
+    use super::{control, deassert_matching, fire_matching, Trigger};
+    use crate::{bindings, pr_debug, sync::smutex::Mutex};
+    use core::cell::UnsafeCell;
+
+    struct Ops(UnsafeCell<bindings::syscore_ops>);
+
+    // SAFETY: The operations are only modified by the syscore code, under its own lock.
+    unsafe impl Sync for Ops {}
+
+    const fn zeroed_ops() -> bindings::syscore_ops {
+        // SAFETY: All fields of `syscore_ops` are integers or (function) pointers, for which all
+        // zeroes is a valid value.
+        unsafe { core::mem::transmute([0u8; core::mem::size_of::<bindings::syscore_ops>()]) }
+    }
+
+    static OPS: Ops = Ops(UnsafeCell::new(bindings::syscore_ops {
+        suspend: Some(suspend),
+        resume: Some(resume),
+        ..zeroed_ops()
+    }));
+
+    static REGISTERED: Mutex<bool> = Mutex::new(false);
+
+    unsafe extern "C" fn suspend() -> core::ffi::c_int {
+        if let Err(e) = control::suspend_kept_lines() {
+            return e.to_kernel_errno();
+        }
+        let n = fire_matching(|t| matches!(t, Trigger::SyscoreSuspend));
+        pr_debug!("syscore suspend: asserted {} reset line(s)\n", n);
+        0
+    }
+
+    unsafe extern "C" fn resume() {
+        deassert_matching(|t| matches!(t, Trigger::SyscoreSuspend));
+        // Failures are reported, the consumers find out when they resume.
+        let _ = control::resume_kept_lines();
+    }
+
+    /// Registers the syscore operations, if not done yet.
+    pub(super) fn register_ops() {
+        let mut registered = REGISTERED.lock();
+        if !*registered {
+            // SAFETY: `OPS` lives forever and is never unregistered.
+            unsafe { bindings::register_syscore_ops(OPS.0.get()) };
+            *registered = true;
+        }
+    }
+}
+
+/// Creates the trigger for a thermal zone tripping the cooling device of `rcdev`.
+///
+/// This also registers the cooling device, with the device tree node of `rcdev`, and fails with
+/// `ENODEV` if it has none. Zones bind it to their trip points through their `cooling-maps`.
+///
+/// # Safety
+///
+/// `rcdev` must be valid until [`remove_controller`] is called for it.
+pub(crate) unsafe fn thermal_trip(rcdev: *mut bindings::reset_controller_dev) -> Result<Trigger> {
+    #[cfg(CONFIG_THERMAL_OF)]
+    {
+        // SAFETY: By the safety requirements, `rcdev` is valid until `remove_controller`, which
+        // calls `thermal::remove`.
+        unsafe { thermal::add(rcdev) }?;
+        Ok(Trigger::ThermalTrip(rcdev))
+    }
+    #[cfg(not(CONFIG_THERMAL_OF))]
+    {
+        let _ = rcdev;
+        Err(ENOTSUPP)
+    }
+}
+
+/// Creates the trigger for system suspend.
+///
+/// This also registers the syscore operations firing the lines.
+pub(crate) fn syscore_suspend() -> Trigger {
+    syscore::register_ops();
+    Trigger::SyscoreSuspend
+}
+
+/// Registers the syscore operations, which also assert the lines consumers keep asserted while
+/// the system is suspended, see [`KeptLines`].
+///
+/// [`KeptLines`]: super::control::KeptLines
+pub(crate) fn register_syscore() {
+    syscore::register_ops();
+}
+
+/// Creates the trigger for a kernel panic.
+///
+/// This also registers the panic notifier firing the lines.
+pub(crate) fn panic() -> Result<Trigger> {
+    panic::register_notifier()?;
+    Ok(Trigger::Panic)
+}
+
+/// Creates the trigger for the pretimeout of the watchdog with id `watchdog`, or of any watchdog
+/// if `None`.
+///
+/// This also registers the `reset` pretimeout governor, which has to be selected for the
+/// watchdog through its `pretimeout_governor` sysfs attribute.
+pub(crate) fn watchdog_pretimeout(watchdog: Option<i32>) -> Result<Trigger> {
+    #[cfg(CONFIG_WATCHDOG_PRETIMEOUT_GOV)]
+    {
+        watchdog::register_governor()?;
+        Ok(Trigger::WatchdogPretimeout(watchdog))
+    }
+    #[cfg(not(CONFIG_WATCHDOG_PRETIMEOUT_GOV))]
+    {
+        let _ = watchdog;
+        Err(ENOTSUPP)
+    }
+}
@@ -0,0 +1,167 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Controllers implemented in programmable logic behind an FPGA region.
+//!
+//! The static part of a design can expose reset lines that only exist while an image is loaded
+//! in a region. With `of-fpga-region`, images are loaded by applying a device tree overlay
+//! targeting the node of the region, and the region is only reprogrammed after that overlay is
+//! removed. [`FpgaRegionResets`] follows those overlays: once one targeting the region has been
+//! applied, the controller is registered with the node of the region, and before it is removed,
+//! the controller is unregistered, which tells the consumers holding its lines, see
+//! [`ResetControl::on_provider_removed`].
+//!
+//! ```ignore
+//! fn probe(dev: &mut platform::Device, _id: Option<&Self::IdInfo>) -> Result<Self::Data> {
+//!     // SAFETY: `dev` is a valid device, and its node is valid or null.
+//!     let region = unsafe {
+//!         let np = (*dev.raw_device()).of_node;
+//!         bindings::of_get_child_by_name(np, c_str!("region").as_char_ptr())
+//!     };
+//!     let resets = FpgaRegionResets::<ShellOps>::try_new(dev, region, 8, ShellOps::map);
+//!     // SAFETY: `region` is null or was returned by `of_get_child_by_name`, and `try_new` took
+//!     // its own reference.
+//!     unsafe { bindings::of_node_put(region) };
+//!     resets
+//! }
+//! ```
+//!
+//! The overlay must not be applied yet when the [`FpgaRegionResets`] is created, as only the
+//! following overlays are seen.
+//!
+//! [`ResetControl::on_provider_removed`]: super::ResetControl::on_provider_removed
+
+use super::{ResetDriverOps, ResetRegistration};
+use crate::{
+    bindings,
+    device::{self, RawDevice},
+    error::{code::*, to_result, Result},
+    pr_warn,
+    sync::smutex::Mutex,
+};
+
+use alloc::boxed::Box;
+use core::{
+    cell::UnsafeCell,
+    ffi::{c_int, c_ulong, c_void},
+    marker::PhantomPinned,
+    pin::Pin,
+};
+
+/// Creates the data of the controller once the image is loaded.
+pub type FpgaLoadFn<T> = fn(&device::Device) -> Result<<T as ResetDriverOps>::Data>;
+
+/// A reset controller registered while an image is loaded in an FPGA region, see the [module]
+/// documentation.
+///
+/// The controller is unregistered, if needed, when this is dropped.
+///
+/// [module]: self
+pub struct FpgaRegionResets<T: ResetDriverOps> {
+    nb: UnsafeCell<bindings::notifier_block>,
+    dev: device::Device,
+    region: *mut bindings::device_node,
+    nr_resets: u32,
+    load: FpgaLoadFn<T>,
+    reg: Mutex<Option<Pin<Box<ResetRegistration<T>>>>>,
+    _pin: PhantomPinned,
+}
+
+// SAFETY: The notifier block is only modified by the notifier chain, under its own lock, the
+// region node is reference counted, and the registration is behind a mutex.
+unsafe impl<T: ResetDriverOps> Send for FpgaRegionResets<T> {}
+
+// SAFETY: As above.
+unsafe impl<T: ResetDriverOps> Sync for FpgaRegionResets<T> {}
+
+impl<T: ResetDriverOps> FpgaRegionResets<T> {
+    /// Follows the overlays targeting `region` to register a controller of `dev` with
+    /// `nr_resets` lines, whose data is created by `load` when an image is loaded.
+    ///
+    /// Takes a reference on `region`, which must be a valid device tree node, and fails with
+    /// `ENODEV` if it is null.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn try_new(
+        dev: &impl RawDevice,
+        region: *mut bindings::device_node,
+        nr_resets: u32,
+        load: FpgaLoadFn<T>,
+    ) -> Result<Pin<Box<Self>>> {
+        if region.is_null() {
+            return Err(ENODEV);
+        }
+        let this = Pin::from(Box::try_new(Self {
+            nb: UnsafeCell::new(bindings::notifier_block {
+                notifier_call: Some(Self::notify),
+                next: core::ptr::null_mut(),
+                priority: 0,
+            }),
+            // SAFETY: `dev` is a valid device.
+            dev: unsafe { device::Device::new(dev.raw_device()) },
+            // SAFETY: By the requirements above, `region` is a valid node. The reference is
+            // dropped with `this`.
+            region: unsafe { bindings::of_node_get(region) },
+            nr_resets,
+            load,
+            reg: Mutex::new(None),
+            _pin: PhantomPinned,
+        })?);
+        // SAFETY: `nb` is pinned, and unregistered when `this` is dropped. If the registration
+        // fails, dropping `this` unregisters a notifier that is not registered, which is allowed.
+        to_result(unsafe { bindings::of_overlay_notifier_register(this.nb.get()) })?;
+        Ok(this)
+    }
+
+    /// Registers the controller, unless it already is.
+    fn loaded(&self) -> Result {
+        let mut reg = self.reg.lock();
+        if reg.is_some() {
+            return Ok(());
+        }
+        let data = (self.load)(&self.dev)?;
+        let mut new = Pin::from(Box::try_new(ResetRegistration::new())?);
+        new.as_mut().register_inner(&self.dev, self.region, self.nr_resets, data)?;
+        *reg = Some(new);
+        Ok(())
+    }
+
+    /// Unregisters the controller, if it is registered.
+    fn unloading(&self) {
+        // Dropped outside the lock, consumers told of the removal may take some time.
+        let reg = self.reg.lock().take();
+        drop(reg);
+    }
+
+    unsafe extern "C" fn notify(
+        nb: *mut bindings::notifier_block,
+        action: c_ulong,
+        data: *mut c_void,
+    ) -> c_int {
+        // SAFETY: Only the notifier block of a `FpgaRegionResets` has this callback.
+        let this = unsafe { &*crate::container_of!(nb, Self, nb) };
+        // SAFETY: Overlay notifiers are called with a valid `of_overlay_notify_data`.
+        let target = unsafe { (*(data as *mut bindings::of_overlay_notify_data)).target };
+        if target != this.region {
+            return bindings::NOTIFY_DONE as _;
+        }
+        match action as u32 {
+            bindings::of_overlay_notify_action_OF_OVERLAY_POST_APPLY => {
+                if let Err(e) = this.loaded() {
+                    pr_warn!("{}: reset controller not registered: {:?}\n", this.dev.name(), e);
+                }
+            }
+            bindings::of_overlay_notify_action_OF_OVERLAY_PRE_REMOVE => this.unloading(),
+            _ => {}
+        }
+        bindings::NOTIFY_OK as _
+    }
+}
+
+impl<T: ResetDriverOps> Drop for FpgaRegionResets<T> {
+    fn drop(&mut self) {
+        // SAFETY: `nb` is registered in `try_new`, or unregistering it fails harmlessly.
+        unsafe { bindings::of_overlay_notifier_unregister(self.nb.get()) };
+        self.unloading();
+        // SAFETY: The reference was taken in `try_new`.
+        unsafe { bindings::of_node_put(self.region) };
+    }
+}
@@ -0,0 +1,192 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Per-line serialisation for reset controllers.
+//!
+//! Controllers with independent command registers per line only need to serialise operations on
+//! the same line. [`LineLocks`] provides one spinlock per line, to be kept in the controller's
+//! [`ResetDriverOps::Data`]:
+//!
+//! ```ignore
+//! fn deassert(data: &MyData, req: ResetRequest) -> Result<i32> {
+//!     data.locks.with_line(req.id(), || {
+//!         data.regs.start_command(req.id(), CMD_DEASSERT);
+//!         data.regs.wait_command_done(req.id())
+//!     })?
+//! }
+//!
+//! fn assert_many(data: &MyData, reqs: &[ResetRequest]) -> Result {
+//!     let ids = [reqs[0].id(), reqs[1].id()];
+//!     data.locks.with_lines(&ids, || data.regs.assert_lines(&ids))?
+//! }
+//! ```
+//!
+//! [`ResetDriverOps::Data`]: super::ResetDriverOps::Data
+
+use crate::{
+    bindings,
+    error::{code::*, Result},
+    types::Opaque,
+};
+
+use alloc::{boxed::Box, vec::Vec};
+use core::cell::UnsafeCell;
+
+/// Maximum number of lines locked at once by [`LineLocks::with_lines`], lockdep's
+/// `MAX_LOCK_DEPTH`.
+pub const MAX_LOCKED_LINES: usize = 48;
+
+/// The lock serialising [`LineLocks::with_lines`], and the lockdep classes of the array.
+struct Outer {
+    lock: Opaque<bindings::spinlock_t>,
+    lock_key: UnsafeCell<bindings::lock_class_key>,
+    /// The class of all the line locks of the array.
+    line_key: UnsafeCell<bindings::lock_class_key>,
+}
+
+/// One spinlock per line of a reset controller.
+///
+/// The locks disable interrupts, as operations may be called from interrupt handlers. All the
+/// line locks of an array share one lockdep class. Operations on several lines, e.g.,
+/// [`ResetDriverOps::assert_many`], hold them nested in an outer lock of the array, which
+/// lockdep is told about.
+///
+/// [`ResetDriverOps::assert_many`]: super::ResetDriverOps::assert_many
+pub struct LineLocks {
+    locks: Box<[Opaque<bindings::spinlock_t>]>,
+    /// Registered until `self` is dropped.
+    outer: Box<Outer>,
+}
+
+/// Releases a line lock taken by [`LineLocks::with_line`] when dropped.
+struct LineGuard(*mut bindings::spinlock_t, core::ffi::c_ulong);
+
+impl Drop for LineGuard {
+    fn drop(&mut self) {
+        // SAFETY: The lock was taken with `spin_lock_irqsave`, which returned the flags.
+        unsafe { bindings::spin_unlock_irqrestore(self.0, self.1) };
+    }
+}
+
+/// Releases a line lock nested in the outer lock when dropped.
+struct NestedGuard(*mut bindings::spinlock_t);
+
+impl Drop for NestedGuard {
+    fn drop(&mut self) {
+        // SAFETY: The lock was taken in `LineLocks::nest`.
+        unsafe { bindings::spin_unlock(self.0) };
+    }
+}
+
+impl LineLocks {
+    /// Allocates and initialises the locks of `nr_resets` lines.
+    pub fn try_new(nr_resets: u32) -> Result<Self> {
+        let mut locks = Vec::try_with_capacity(nr_resets as usize)?;
+        for _ in 0..nr_resets {
+            locks.try_push(Opaque::uninit())?;
+        }
+        let locks = locks.into_boxed_slice();
+        let outer = Box::try_new(Outer {
+            lock: Opaque::uninit(),
+            // SAFETY: All zeroes is a valid value for a key, which is registered below.
+            lock_key: UnsafeCell::new(unsafe { core::mem::zeroed() }),
+            // SAFETY: As above.
+            line_key: UnsafeCell::new(unsafe { core::mem::zeroed() }),
+        })?;
+
+        // SAFETY: The keys are boxed, and unregistered in `drop` before they are freed. The
+        // locks are initialised before use, and never moved out of their boxes.
+        unsafe {
+            bindings::lockdep_register_key(outer.lock_key.get());
+            bindings::lockdep_register_key(outer.line_key.get());
+            bindings::__spin_lock_init(
+                outer.lock.get(),
+                crate::c_str!("LineLocks::outer").as_char_ptr(),
+                outer.lock_key.get(),
+            );
+            for lock in locks.iter() {
+                bindings::__spin_lock_init(
+                    lock.get(),
+                    crate::c_str!("LineLocks::locks").as_char_ptr(),
+                    outer.line_key.get(),
+                );
+            }
+        }
+
+        Ok(Self { locks, outer })
+    }
+
+    /// Returns the number of lines.
+    pub fn len(&self) -> usize {
+        self.locks.len()
+    }
+
+    /// Returns whether there are no lines.
+    pub fn is_empty(&self) -> bool {
+        self.locks.is_empty()
+    }
+
+    /// Runs `f` with line `id` locked, fails with `EINVAL` if there is no such line.
+    pub fn with_line<R>(&self, id: u64, f: impl FnOnce() -> R) -> Result<R> {
+        let lock = self.locks.get(id as usize).ok_or(EINVAL)?.get();
+        // SAFETY: The lock was initialised in `try_new`.
+        let _guard = LineGuard(lock, unsafe { bindings::spin_lock_irqsave(lock) });
+        Ok(f())
+    }
+
+    /// Runs `f` with all the lines `ids` locked.
+    ///
+    /// The line locks are taken nested in the outer lock of the array, so that concurrent
+    /// callers cannot deadlock. `ids` must be strictly increasing, and hold at most
+    /// [`MAX_LOCKED_LINES`] lines. Fails with `EINVAL` otherwise, or if a line does not exist.
+    pub fn with_lines<R>(&self, ids: &[u64], f: impl FnOnce() -> R) -> Result<R> {
+        if ids.len() > MAX_LOCKED_LINES
+            || ids.windows(2).any(|w| w[0] >= w[1])
+            || ids.iter().any(|&id| id as usize >= self.locks.len())
+        {
+            return Err(EINVAL);
+        }
+        let outer = self.outer.lock.get();
+        // SAFETY: The lock was initialised in `try_new`.
+        let _guard = LineGuard(outer, unsafe { bindings::spin_lock_irqsave(outer) });
+        Ok(self.nest(ids, f))
+    }
+
+    /// Runs `f` with the lines `ids`, which exist, locked, with the outer lock held.
+    fn nest<R>(&self, ids: &[u64], f: impl FnOnce() -> R) -> R {
+        let (id, rest) = match ids.split_first() {
+            None => return f(),
+            Some(split) => split,
+        };
+        let lock = self.locks[*id as usize].get();
+        // SAFETY: The locks were initialised in `try_new`, and the outer lock is held.
+        unsafe {
+            #[cfg(CONFIG_DEBUG_LOCK_ALLOC)]
+            bindings::_raw_spin_lock_nest_lock(
+                &mut (*lock).__bindgen_anon_1.rlock,
+                &mut (*self.outer.lock.get()).__bindgen_anon_1.rlock.dep_map,
+            );
+            #[cfg(not(CONFIG_DEBUG_LOCK_ALLOC))]
+            bindings::spin_lock(lock);
+        }
+        let _guard = NestedGuard(lock);
+        self.nest(rest, f)
+    }
+}
+
+impl Drop for LineLocks {
+    fn drop(&mut self) {
+        // SAFETY: The keys were registered in `try_new`, and no lock of their classes can be
+        // held anymore.
+        unsafe {
+            bindings::lockdep_unregister_key(self.outer.lock_key.get());
+            bindings::lockdep_unregister_key(self.outer.line_key.get());
+        }
+    }
+}
+
+// SAFETY: The locks are only used through the C spinlock functions, and the keys only by
+// lockdep, which synchronise internally.
+unsafe impl Send for LineLocks {}
+
+// SAFETY: As above.
+unsafe impl Sync for LineLocks {}
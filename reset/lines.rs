@@ -0,0 +1,178 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Symbolic reset line identifiers shared by providers and consumers.
+
+use core::fmt;
+
+/// The hardware id of a reset line of a controller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineId(u64);
+
+impl LineId {
+    /// Creates a line id from its raw value.
+    pub const fn new(id: u64) -> Self {
+        Self(id)
+    }
+
+    /// Returns the raw value of the id.
+    pub const fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl From<LineId> for u64 {
+    fn from(id: LineId) -> u64 {
+        id.0
+    }
+}
+
+impl fmt::Display for LineId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Declares the reset lines of a controller as an enum.
+///
+/// Every line has a name, used as `reset-names` entry by consumers and in diagnostics, and a
+/// hardware id. The generated enum has:
+///
+/// - `ALL`, the list of lines,
+/// - `OF_XLATE_TABLE`, a table for [`OfXlate::Table`] whose bindings are the line ids,
+/// - `id()`, returning the [`LineId`] of the line,
+/// - `name()` and `c_name()`, returning its name,
+/// - `from_id()`, looking a line up by id, e.g., from [`ResetRequest::line`].
+///
+/// For instance, with a provider using `OfXlate::Table(FooLines::OF_XLATE_TABLE)`:
+///
+/// ```ignore
+/// declare_reset_lines! {
+///     /// Lines of the foo reset controller.
+///     pub enum FooLines {
+///         /// The USB controller.
+///         Usb("usb") = 3,
+///         Eth("eth") = 7,
+///     }
+/// }
+///
+/// // Provider side.
+/// match FooLines::from_id(req.line()) {
+///     Some(FooLines::Usb) => ...,
+///     ...
+/// }
+///
+/// // Consumer side.
+/// let usb = ResetControl::get_exclusive(dev, Some(FooLines::Usb.c_name()))?;
+/// ```
+///
+/// [`OfXlate::Table`]: crate::reset::OfXlate::Table
+/// [`ResetRequest::line`]: crate::reset::ResetRequest::line
+#[macro_export]
+macro_rules! declare_reset_lines {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $(
+                $(#[$line_meta:meta])*
+                $line:ident($line_name:literal) = $id:expr
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        #[repr(u64)]
+        $vis enum $name {
+            $(
+                $(#[$line_meta])*
+                $line = $id,
+            )*
+        }
+
+        impl $name {
+            /// All the lines.
+            pub const ALL: &'static [Self] = &[$(Self::$line),*];
+
+            /// Device tree translation table mapping the line ids to the lines.
+            pub const OF_XLATE_TABLE: &'static [$crate::reset::OfXlateEntry] = &[$(
+                $crate::reset::OfXlateEntry {
+                    binding: ($id) as u32,
+                    id: ($id) as u64,
+                    name: $line_name,
+                }
+            ),*];
+
+            /// Returns the hardware id of the line.
+            pub const fn id(self) -> $crate::reset::LineId {
+                $crate::reset::LineId::new(self as u64)
+            }
+
+            /// Returns the name of the line.
+            pub const fn name(self) -> &'static str {
+                match self {
+                    $(Self::$line => $line_name,)*
+                }
+            }
+
+            /// Returns the name of the line as a C string, e.g., to get a consumer reference.
+            pub const fn c_name(self) -> &'static $crate::str::CStr {
+                match self {
+                    $(Self::$line => $crate::c_str!($line_name),)*
+                }
+            }
+
+            /// Returns the line with id `id`, if any.
+            pub fn from_id(id: $crate::reset::LineId) -> Option<Self> {
+                Self::ALL.iter().copied().find(|line| line.id() == id)
+            }
+        }
+
+        impl From<$name> for $crate::reset::LineId {
+            fn from(line: $name) -> Self {
+                line.id()
+            }
+        }
+    };
+}
+
+/// Declares [`LineId`] constants for reset ids of a `dt-bindings` header.
+///
+/// The header, e.g., `include/dt-bindings/reset/sun50i-h6-ccu.h`, must be included from
+/// `rust/bindings/bindings_helper.h`, which makes its `#define`s available in [`crate::bindings`].
+/// Every listed constant is then re-declared with the same name in module `$module`, so the ids
+/// used by Rust drivers come from the same header as those of the device tree, and a constant
+/// missing from it fails the build.
+///
+/// ```ignore
+/// dt_reset_lines! {
+///     /// Reset ids of the H6 CCU.
+///     pub mod h6_ccu {
+///         RST_BUS_MMC0,
+///         RST_BUS_MMC1,
+///     }
+/// }
+///
+/// declare_reset_lines! {
+///     pub enum H6Lines {
+///         Mmc0("mmc0") = h6_ccu::RST_BUS_MMC0.get(),
+///         Mmc1("mmc1") = h6_ccu::RST_BUS_MMC1.get(),
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! dt_reset_lines {
+    (
+        $(#[$meta:meta])*
+        $vis:vis mod $module:ident {
+            $($const:ident),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis mod $module {
+            $(
+                #[allow(missing_docs)]
+                pub const $const: $crate::reset::LineId =
+                    $crate::reset::LineId::new($crate::bindings::$const as u64);
+            )*
+        }
+    };
+}
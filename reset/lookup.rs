@@ -0,0 +1,165 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Lookup entries, tying consumer devices to lines of controllers without firmware description.
+//!
+//! Controllers registered with [`ResetRegistration::register_anonymous`] have no device tree
+//! node, so consumers only find their lines through lookup entries. An entry names the
+//! controller by the name of its device, so drivers of hot-pluggable providers (e.g., USB or I2C
+//! dongles) should register the controller on a child device with a fixed name.
+//!
+//! ```ignore
+//! static LOOKUPS: [ResetLookup; 1] = [ResetLookup::new(
+//!     c_str!("reset-dongle.0"),
+//!     3,
+//!     c_str!("adc.0"),
+//!     Some(c_str!("adc")),
+//! )];
+//!
+//! reset::add_lookups(&LOOKUPS)?;
+//! ```
+//!
+//! The C reset core never removes lookup entries, so tables must live forever. Entries of a
+//! controller that is gone make consumers defer their probe until it comes back.
+//!
+//! [`ResetRegistration::register_anonymous`]: super::ResetRegistration::register_anonymous
+
+use crate::{
+    bindings,
+    error::Result,
+    fmt,
+    str::{CStr, CString},
+    sync::smutex::Mutex,
+};
+
+use alloc::{boxed::Box, vec::Vec};
+use core::cell::UnsafeCell;
+
+/// An entry tying line `index` of a controller to a consumer.
+#[repr(transparent)]
+pub struct ResetLookup(UnsafeCell<bindings::reset_control_lookup>);
+
+impl ResetLookup {
+    /// Creates an entry giving consumer device `dev_id` line `index` of the controller of
+    /// device `provider`, under the name `con_id`.
+    ///
+    /// A `None` `con_id` matches the consumer's unnamed lookups only.
+    pub const fn new(
+        provider: &'static CStr,
+        index: u32,
+        dev_id: &'static CStr,
+        con_id: Option<&'static CStr>,
+    ) -> Self {
+        Self(UnsafeCell::new(bindings::reset_control_lookup {
+            list: bindings::list_head {
+                next: core::ptr::null_mut(),
+                prev: core::ptr::null_mut(),
+            },
+            provider: provider.as_char_ptr(),
+            index,
+            dev_id: dev_id.as_char_ptr(),
+            con_id: match con_id {
+                Some(con_id) => con_id.as_char_ptr(),
+                None => core::ptr::null(),
+            },
+        }))
+    }
+}
+
+// SAFETY: The entries are only modified by the C reset core, under its lookup mutex.
+unsafe impl Sync for ResetLookup {}
+
+struct Table(*const ResetLookup);
+
+// SAFETY: The pointer is only compared, never dereferenced.
+unsafe impl Send for Table {}
+
+static ADDED: Mutex<Vec<Table>> = Mutex::new(Vec::new());
+
+/// Adds the entries of `table` to the C reset core.
+///
+/// Adding a table again, e.g., when a provider is plugged again, does nothing.
+#[cfg_attr(klint, klint::preempt_count(expect = 0))]
+pub fn add_lookups(table: &'static [ResetLookup]) -> Result {
+    if table.is_empty() {
+        return Ok(());
+    }
+    let mut added = ADDED.lock();
+    if added.iter().any(|t| t.0 == table.as_ptr()) {
+        return Ok(());
+    }
+    added.try_push(Table(table.as_ptr()))?;
+    // SAFETY: `table` lives forever and is a contiguous array of `reset_control_lookup`, and it
+    // was never added before, so its list heads are unused.
+    unsafe {
+        bindings::reset_controller_add_lookup(table.as_ptr() as *mut _, table.len() as _)
+    };
+    Ok(())
+}
+
+/// An entry built at runtime, with the strings it points to.
+struct Owned {
+    _lookup: Box<ResetLookup>,
+    _provider: CString,
+    dev_id: CString,
+    con_id: Option<CString>,
+}
+
+// SAFETY: The entry is only modified by the C reset core, and the strings are never modified.
+unsafe impl Send for Owned {}
+
+static OWNED: Mutex<Vec<Owned>> = Mutex::new(Vec::new());
+
+/// Adds an entry giving consumer device `dev_id` line `index` of the controller of device
+/// `provider`, under the name `con_id`.
+///
+/// The entry and its strings are never freed, as the C reset core never removes entries. Adding
+/// an entry again for the same consumer and name does nothing.
+pub(crate) fn add_owned(
+    provider: &CStr,
+    index: u32,
+    dev_id: &CStr,
+    con_id: Option<&CStr>,
+) -> Result {
+    let mut owned = OWNED.lock();
+    let same_con_id = |e: &Owned| match (&e.con_id, con_id) {
+        (Some(a), Some(b)) => a.as_bytes() == b.as_bytes(),
+        (None, None) => true,
+        _ => false,
+    };
+    if owned
+        .iter()
+        .any(|e| e.dev_id.as_bytes() == dev_id.as_bytes() && same_con_id(e))
+    {
+        return Ok(());
+    }
+
+    let provider = CString::try_from_fmt(fmt!("{}", provider))?;
+    let dev_id = CString::try_from_fmt(fmt!("{}", dev_id))?;
+    let con_id = match con_id {
+        Some(con_id) => Some(CString::try_from_fmt(fmt!("{}", con_id))?),
+        None => None,
+    };
+    let lookup = Box::try_new(ResetLookup(UnsafeCell::new(bindings::reset_control_lookup {
+        list: bindings::list_head {
+            next: core::ptr::null_mut(),
+            prev: core::ptr::null_mut(),
+        },
+        provider: provider.as_char_ptr(),
+        index,
+        dev_id: dev_id.as_char_ptr(),
+        con_id: con_id
+            .as_ref()
+            .map_or(core::ptr::null(), |con_id| con_id.as_char_ptr()),
+    })))?;
+    let raw = lookup.0.get();
+    owned.try_push(Owned {
+        _lookup: lookup,
+        _provider: provider,
+        dev_id,
+        con_id,
+    })?;
+    // SAFETY: The entry and the strings it points to are heap allocations kept in `OWNED`
+    // forever, and the entry was never added before, so its list head is unused.
+    unsafe { bindings::reset_controller_add_lookup(raw, 1) };
+    Ok(())
+}
@@ -0,0 +1,80 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Providers that are cells of a multi-function device.
+//!
+//! The reset function of an MFD (e.g., a PMIC) is usually a child platform device without a
+//! device tree node of its own, whose registers are accessed through the regmap of the parent.
+//! Consumers reference the node of the parent, so the controller is registered with it, and the
+//! regmap is borrowed from the parent:
+//!
+//! ```ignore
+//! fn probe(pdev: &mut platform::Device, _id: Option<&Self::IdInfo>) -> Result<Self::Data> {
+//!     let regmap = reset::parent_regmap(pdev, None)?;
+//!     let mut reg = Pin::from(Box::try_new(ResetRegistration::new())?);
+//!     reg.as_mut()
+//!         .register_mfd_cell(pdev, 16, Box::try_new(PmicResets { regmap })?)?;
+//!     Ok(reg)
+//! }
+//! ```
+
+use super::{ResetDriverOps, ResetRegistration};
+use crate::{
+    bindings,
+    device::RawDevice,
+    error::{code::*, Result},
+    platform,
+    str::CStr,
+};
+
+use core::{pin::Pin, ptr::NonNull};
+
+/// Returns the regmap called `name` of the parent of `dev`, or its only one if `name` is `None`.
+///
+/// The regmap is managed by the parent, which outlives `dev` and thus the driver data of `dev`.
+/// Fails with `ENODEV` if `dev` has no parent or the parent has no such regmap.
+pub fn parent_regmap(
+    dev: &impl RawDevice,
+    name: Option<&CStr>,
+) -> Result<NonNull<bindings::regmap>> {
+    // SAFETY: `dev` is a valid device.
+    let parent = unsafe { (*dev.raw_device()).parent };
+    if parent.is_null() {
+        return Err(ENODEV);
+    }
+    let name = name.map_or(core::ptr::null(), |name| name.as_char_ptr());
+    // SAFETY: `parent` is a valid device, and `name` is null or a valid C string.
+    NonNull::new(unsafe { bindings::dev_get_regmap(parent, name) }).ok_or(ENODEV)
+}
+
+impl<T: ResetDriverOps> ResetRegistration<T> {
+    /// Registers a reset controller provided by MFD cell `dev`, see the [module] documentation.
+    ///
+    /// The controller uses the device tree node of `dev` if it has one, and the one of its parent
+    /// otherwise, so that consumers referencing the parent find it. Fails with `ENODEV` if
+    /// neither has a node.
+    ///
+    /// [module]: self
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn register_mfd_cell(
+        self: Pin<&mut Self>,
+        dev: &mut platform::Device,
+        nr_resets: u32,
+        data: T::Data,
+    ) -> Result {
+        let raw_dev = dev.raw_device();
+        // SAFETY: `raw_dev` is valid.
+        let mut of_node = unsafe { (*raw_dev).of_node };
+        if of_node.is_null() {
+            // SAFETY: `raw_dev` is valid, and so is its parent if not null.
+            let parent = unsafe { (*raw_dev).parent };
+            if !parent.is_null() {
+                // SAFETY: `parent` is a valid device.
+                of_node = unsafe { (*parent).of_node };
+            }
+        }
+        if of_node.is_null() {
+            return Err(ENODEV);
+        }
+        self.register_inner(dev, of_node, nr_resets, data)
+    }
+}
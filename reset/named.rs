@@ -0,0 +1,120 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! All the reset lines of a consumer, indexed by name.
+//!
+//! Drivers with many optional lines get them in one go instead of chaining getters:
+//!
+//! ```ignore
+//! let resets = NamedResetControls::get_exclusive(&pdev)?;
+//! if let Some(ahb) = resets.get("ahb") {
+//!     ahb.deassert()?;
+//! }
+//! ```
+
+use super::{Exclusive, ResetControl, ResetKind, Shared};
+use crate::{
+    bindings, c_str,
+    device::RawDevice,
+    error::{code::*, Result},
+    fmt,
+    str::{CStr, CString},
+};
+
+use alloc::vec::Vec;
+
+/// The reset lines listed in the `reset-names` property of a consumer.
+pub struct NamedResetControls<K: ResetKind = Exclusive> {
+    lines: Vec<(CString, ResetControl<K>)>,
+}
+
+impl<K: ResetKind> NamedResetControls<K> {
+    fn get_all(dev: &impl RawDevice, acquired: bool) -> Result<Self> {
+        let mut lines = Vec::new();
+        // SAFETY: `dev` is a valid device.
+        let np = unsafe { (*dev.raw_device()).of_node };
+        if np.is_null() {
+            return Ok(Self { lines });
+        }
+
+        let prop = c_str!("reset-names");
+        // SAFETY: `np` is a valid node and `prop` is a valid C string. A null output only counts
+        // the strings.
+        let count = unsafe {
+            bindings::of_property_read_string_helper(
+                np,
+                prop.as_char_ptr(),
+                core::ptr::null_mut(),
+                0,
+                0,
+            )
+        };
+        if count == -(bindings::EINVAL as i32) {
+            // No `reset-names` property.
+            return Ok(Self { lines });
+        }
+        if count < 0 {
+            return Err(EINVAL);
+        }
+
+        for i in 0..count {
+            let mut name = core::ptr::null();
+            // SAFETY: `np` is a valid node, `prop` is a valid C string and `name` is valid for
+            // writing one string.
+            let ret = unsafe {
+                bindings::of_property_read_string_helper(np, prop.as_char_ptr(), &mut name, 1, i)
+            };
+            if ret < 0 {
+                return Err(EINVAL);
+            }
+            // SAFETY: On success, `name` points to a C string in the node, which we hold.
+            let name = unsafe { CStr::from_char_ptr(name) };
+            if let Some(control) = ResetControl::get(dev, Some(name), true, acquired)? {
+                lines.try_push((CString::try_from_fmt(fmt!("{}", name))?, control))?;
+            }
+        }
+        Ok(Self { lines })
+    }
+
+    /// Returns the line called `name`, or `None` if the consumer has no such line.
+    pub fn get(&self, name: &str) -> Option<&ResetControl<K>> {
+        self.lines
+            .iter()
+            .find(|(n, _)| n.as_bytes() == name.as_bytes())
+            .map(|(_, control)| control)
+    }
+
+    /// Returns an iterator over the lines and their names.
+    pub fn iter(&self) -> impl Iterator<Item = (&CStr, &ResetControl<K>)> {
+        self.lines.iter().map(|(name, control)| (&**name, control))
+    }
+
+    /// Returns the number of lines.
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Returns whether the consumer has no named lines.
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+}
+
+impl NamedResetControls<Exclusive> {
+    /// Gets exclusive references to all the named reset lines of `dev`.
+    ///
+    /// Lines whose provider is missing are left out, probe deferral is reported as an error.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn get_exclusive(dev: &impl RawDevice) -> Result<Self> {
+        Self::get_all(dev, true)
+    }
+}
+
+impl NamedResetControls<Shared> {
+    /// Gets shared references to all the named reset lines of `dev`.
+    ///
+    /// Lines whose provider is missing are left out, probe deferral is reported as an error.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn get_shared(dev: &impl RawDevice) -> Result<Self> {
+        Self::get_all(dev, false)
+    }
+}
@@ -0,0 +1,242 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Device tree reset specifier translation.
+//!
+//! C header: [`include/linux/of.h`](../../../../include/linux/of.h)
+
+use super::{ResetFlags, ResetRequest};
+use crate::{
+    bindings, c_str,
+    device::RawDevice,
+    error::{code::*, Result},
+    fmt,
+    str::{CStr, CString},
+};
+
+/// An entry of a table-based translation.
+///
+/// Maps the value found in the specifier cell to the hardware line id.
+#[derive(Clone, Copy)]
+pub struct OfXlateEntry {
+    /// Value of the specifier cell, usually a `dt-bindings` constant.
+    pub binding: u32,
+    /// Hardware line id handed to the operations.
+    pub id: u64,
+    /// Name of the line, used in diagnostics.
+    pub name: &'static str,
+}
+
+/// Stock translations from a device tree reset specifier to a line id.
+///
+/// Drivers select one through [`ResetDriverOps::OF_XLATE`].
+///
+/// [`ResetDriverOps::OF_XLATE`]: super::ResetDriverOps::OF_XLATE
+#[derive(Clone, Copy)]
+pub enum OfXlate {
+    /// `#reset-cells = <1>`, the cell is the line id.
+    OneCell,
+    /// `#reset-cells = <2>`, the first cell is the line id and the second one carries
+    /// [`ResetFlags`].
+    TwoCell,
+    /// `#reset-cells = <1>`, the cell is looked up in the given table.
+    Table(&'static [OfXlateEntry]),
+    /// `#reset-cells = <n_cells>`, the specifier is translated by `xlate`.
+    ///
+    /// `xlate` gets the specifier and the number of lines of the controller, and returns the
+    /// request for the line, or `EINVAL` if the specifier is invalid.
+    Custom {
+        /// Number of cells of a specifier.
+        n_cells: u32,
+        /// The translation.
+        xlate: fn(&OfPhandleArgs, u32) -> Result<ResetRequest>,
+    },
+}
+
+impl OfXlate {
+    /// Returns the number of cells a specifier must have for this translation.
+    pub const fn n_cells(&self) -> u32 {
+        match self {
+            OfXlate::OneCell | OfXlate::Table(_) => 1,
+            OfXlate::TwoCell => 2,
+            OfXlate::Custom { n_cells, .. } => *n_cells,
+        }
+    }
+
+    /// Translates specifier `spec` into a request for a line.
+    ///
+    /// Fails with `EINVAL` if the cell count does not match, the line does not exist or the flags
+    /// cell has unknown bits.
+    pub fn xlate(&self, spec: &OfPhandleArgs, nr_resets: u32) -> Result<ResetRequest> {
+        let args = spec.args();
+        if args.len() != self.n_cells() as usize {
+            return Err(EINVAL);
+        }
+
+        match self {
+            OfXlate::OneCell | OfXlate::TwoCell => {
+                if args[0] >= nr_resets {
+                    return Err(EINVAL);
+                }
+                let flags = match args.get(1) {
+                    Some(bits) => ResetFlags::from_bits(*bits)?,
+                    None => ResetFlags::empty(),
+                };
+                Ok(ResetRequest::new(args[0] as u64, flags))
+            }
+            OfXlate::Table(table) => table
+                .iter()
+                .find(|e| e.binding == args[0])
+                .map(|e| ResetRequest::new(e.id, ResetFlags::empty()))
+                .ok_or(EINVAL),
+            OfXlate::Custom { xlate, .. } => xlate(spec, nr_resets),
+        }
+    }
+
+    /// Returns the name of line `id`, if the translation knows it.
+    pub fn name_of(&self, id: u64) -> Option<&'static str> {
+        match self {
+            OfXlate::Table(table) => table.iter().find(|e| e.id == id).map(|e| e.name),
+            _ => None,
+        }
+    }
+
+    /// Returns the id of the line called `name`, if the translation knows it.
+    pub fn id_of(&self, name: &str) -> Option<u64> {
+        match self {
+            OfXlate::Table(table) => table.iter().find(|e| e.name == name).map(|e| e.id),
+            _ => None,
+        }
+    }
+}
+
+/// A device tree reset specifier: the node of the provider and the cells following it.
+#[repr(transparent)]
+pub struct OfPhandleArgs(bindings::of_phandle_args);
+
+impl OfPhandleArgs {
+    /// Creates a reference to a specifier from a raw pointer.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for the lifetime of the returned reference, and hold a reference to
+    /// its node.
+    pub unsafe fn from_raw<'a>(ptr: *const bindings::of_phandle_args) -> &'a Self {
+        // SAFETY: `Self` is a transparent wrapper, and the pointer is valid by the safety
+        // requirements.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns a raw pointer to the C specifier.
+    pub(crate) fn as_raw(&self) -> *const bindings::of_phandle_args {
+        &self.0
+    }
+
+    /// Returns the number of cells.
+    pub fn len(&self) -> usize {
+        self.args().len()
+    }
+
+    /// Returns whether the specifier has no cells.
+    pub fn is_empty(&self) -> bool {
+        self.args().is_empty()
+    }
+
+    /// Returns cell `index`, or `None` if there are not that many cells.
+    pub fn get(&self, index: usize) -> Option<u32> {
+        self.args().get(index).copied()
+    }
+
+    /// Returns the cells.
+    pub fn args(&self) -> &[u32] {
+        &self.0.args[..(self.0.args_count as usize).min(self.0.args.len())]
+    }
+
+    /// Returns the node of the provider.
+    pub fn node(&self) -> *mut bindings::device_node {
+        self.0.np
+    }
+
+    /// Returns the full name of the node of the provider.
+    pub fn node_name(&self) -> &CStr {
+        // SAFETY: By the safety requirements of `from_raw`, or the type invariants of `OfSpec`,
+        // the node is valid, and its name is a valid C string.
+        unsafe { CStr::from_char_ptr((*self.0.np).full_name) }
+    }
+}
+
+/// A device tree reset specifier of a consumer, holding a reference to the provider node.
+///
+/// # Invariants
+///
+/// The node is valid and a reference to it is released when [`OfSpec`] is dropped.
+pub(crate) struct OfSpec(OfPhandleArgs);
+
+impl OfSpec {
+    /// Parses the specifier of the line named `id` of `dev`, or of its first line if `None`.
+    ///
+    /// Returns `None` if `dev` has no device tree node or no such line.
+    pub(crate) fn parse(dev: &impl RawDevice, id: Option<&CStr>) -> Option<Self> {
+        // SAFETY: `dev` is a valid device.
+        let np = unsafe { (*dev.raw_device()).of_node };
+        if np.is_null() {
+            return None;
+        }
+
+        let index = match id {
+            Some(id) => {
+                // SAFETY: `np` is a valid node and both strings are valid C strings.
+                let index = unsafe {
+                    bindings::of_property_match_string(
+                        np,
+                        c_str!("reset-names").as_char_ptr(),
+                        id.as_char_ptr(),
+                    )
+                };
+                if index < 0 {
+                    return None;
+                }
+                index
+            }
+            None => 0,
+        };
+
+        let mut spec = bindings::of_phandle_args::default();
+        // SAFETY: `np` is a valid node, both strings are valid C strings and `spec` is valid for
+        // writes.
+        let ret = unsafe {
+            bindings::__of_parse_phandle_with_args(
+                np,
+                c_str!("resets").as_char_ptr(),
+                c_str!("#reset-cells").as_char_ptr(),
+                0,
+                index,
+                &mut spec,
+            )
+        };
+        if ret != 0 {
+            return None;
+        }
+        // INVARIANT: On success, we own a reference to `spec.np`.
+        Some(Self(OfPhandleArgs(spec)))
+    }
+
+    /// Returns the full name of the provider node.
+    pub(crate) fn provider_name(&self) -> Result<CString> {
+        CString::try_from_fmt(fmt!("{}", self.node_name()))
+    }
+}
+
+impl core::ops::Deref for OfSpec {
+    type Target = OfPhandleArgs;
+
+    fn deref(&self) -> &OfPhandleArgs {
+        &self.0
+    }
+}
+
+impl Drop for OfSpec {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, we own a reference to the node.
+        unsafe { bindings::of_node_put(self.0.node()) };
+    }
+}
@@ -0,0 +1,183 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Offloading of sleeping operations invoked from atomic context.
+//!
+//! Controllers backed by firmware have to sleep in their operations, but exclusive consumers may
+//! assert or deassert lines with interrupts disabled. Controllers that set
+//! [`ResetDriverOps::OFFLOAD_FROM_ATOMIC`] get such operations queued on a per-controller ordered
+//! workqueue instead: the atomic caller gets success back immediately, and the operation runs as
+//! soon as possible, in order with the other offloaded operations. The C core treats any
+//! non-zero return value as a failure, so queued operations cannot be told apart from completed
+//! ones by their caller. The provider waits for them with [`ResetRegistration::flush_queued`].
+//!
+//! Queueing never allocates, pending operations are kept in a fixed-size FIFO.
+//!
+//! [`ResetDriverOps::OFFLOAD_FROM_ATOMIC`]: super::ResetDriverOps::OFFLOAD_FROM_ATOMIC
+//! [`ResetRegistration::flush_queued`]: super::ResetRegistration::flush_queued
+
+use super::ResetOp;
+use crate::{
+    bindings,
+    error::{code::*, Result},
+    fmt, pr_err,
+    str::CStr,
+    sync::{Arc, CondVar, SpinLock, UniqueArc},
+    workqueue::{BoxedQueue, Work},
+};
+
+use core::pin::Pin;
+
+/// Maximum number of operations waiting to be run.
+const QUEUE_DEPTH: usize = 32;
+
+/// Runs operation `op` on line `id` of the controller, in process context.
+pub(crate) type RunFn = unsafe fn(*mut bindings::reset_controller_dev, ResetOp, u64) -> Result<i32>;
+
+/// Returns whether the caller runs in atomic context.
+///
+/// Without `CONFIG_PREEMPT_COUNT`, sections holding spinlocks cannot be detected and only
+/// interrupt context and disabled interrupts are.
+pub(crate) fn in_atomic_context() -> bool {
+    // SAFETY: The helper has no safety requirements.
+    unsafe { bindings::in_atomic_or_irqs_disabled() }
+}
+
+struct Pending {
+    ops: [(ResetOp, u64); QUEUE_DEPTH],
+    head: usize,
+    len: usize,
+    /// Number of operations queued so far.
+    queued: u64,
+    /// Number of operations that have run so far.
+    done: u64,
+}
+
+struct Offload {
+    pending: SpinLock<Pending>,
+    /// Signalled when an operation has run.
+    ran: CondVar,
+    work: Work,
+    rcdev: *mut bindings::reset_controller_dev,
+    run: RunFn,
+}
+
+// SAFETY: `rcdev` is only passed to `run`, which may be called from any thread.
+unsafe impl Send for Offload {}
+
+// SAFETY: `pending` is protected by its lock, and the other fields are never modified.
+unsafe impl Sync for Offload {}
+
+crate::impl_self_work_adapter!(Offload, work, |w| w.drain());
+
+impl Offload {
+    fn drain(&self) {
+        loop {
+            let (op, id) = {
+                let mut pending = self.pending.lock_irqdisable();
+                if pending.len == 0 {
+                    break;
+                }
+                let next = pending.ops[pending.head];
+                pending.head = (pending.head + 1) % QUEUE_DEPTH;
+                pending.len -= 1;
+                next
+            };
+
+            // SAFETY: `rcdev` outlives the queue, which is destroyed (and drained) first.
+            if let Err(e) = unsafe { (self.run)(self.rcdev, op, id) } {
+                pr_err!("Offloaded {:?} of reset line {} failed: {:?}\n", op, id, e);
+            }
+            self.pending.lock_irqdisable().done += 1;
+            self.ran.notify_all();
+        }
+    }
+}
+
+/// The ordered workqueue and FIFO of a controller offloading operations.
+pub(crate) struct OffloadQueue {
+    offload: Arc<Offload>,
+    queue: BoxedQueue,
+}
+
+impl OffloadQueue {
+    /// Creates the queue of controller `rcdev`, whose operations are run by `run`.
+    ///
+    /// # Safety
+    ///
+    /// `rcdev` must be valid until the returned queue is dropped.
+    pub(crate) unsafe fn try_new(
+        name: &CStr,
+        rcdev: *mut bindings::reset_controller_dev,
+        run: RunFn,
+    ) -> Result<Self> {
+        let mut offload = UniqueArc::try_new(Offload {
+            // SAFETY: `spinlock_init` is called below.
+            pending: unsafe {
+                SpinLock::new(Pending {
+                    ops: [(ResetOp::Status, 0); QUEUE_DEPTH],
+                    head: 0,
+                    len: 0,
+                    queued: 0,
+                    done: 0,
+                })
+            },
+            // SAFETY: `condvar_init` is called below.
+            ran: unsafe { CondVar::new() },
+            // SAFETY: `init_work_item` is called below.
+            work: unsafe { Work::new() },
+            rcdev,
+            run,
+        })?;
+
+        // SAFETY: The contents of a `UniqueArc` never move.
+        let pending = unsafe { Pin::new_unchecked(&mut offload.pending) };
+        crate::spinlock_init!(pending, "OffloadQueue::pending");
+        // SAFETY: The contents of a `UniqueArc` never move.
+        let ran = unsafe { Pin::new_unchecked(&mut offload.ran) };
+        crate::condvar_init!(ran, "OffloadQueue::ran");
+        crate::init_work_item!(&offload);
+
+        let queue = BoxedQueue::try_new(
+            fmt!("{}-reset", name),
+            bindings::WQ_UNBOUND | bindings::__WQ_ORDERED | bindings::WQ_MEM_RECLAIM,
+            1,
+        )?;
+
+        Ok(Self {
+            offload: offload.into(),
+            queue,
+        })
+    }
+
+    /// Queues operation `op` on line `id`, fails with `EBUSY` if the FIFO is full.
+    ///
+    /// Can be called from any context.
+    pub(crate) fn push(&self, op: ResetOp, id: u64) -> Result {
+        {
+            let mut pending = self.offload.pending.lock_irqdisable();
+            if pending.len == QUEUE_DEPTH {
+                return Err(EBUSY);
+            }
+            let tail = (pending.head + pending.len) % QUEUE_DEPTH;
+            pending.ops[tail] = (op, id);
+            pending.len += 1;
+            pending.queued += 1;
+        }
+        self.queue.enqueue(self.offload.clone());
+        Ok(())
+    }
+
+    /// Waits for the operations queued so far to have run.
+    ///
+    /// Fails with `EINTR` if interrupted by a signal.
+    pub(crate) fn flush(&self) -> Result {
+        let mut pending = self.offload.pending.lock_irqdisable();
+        let target = pending.queued;
+        while pending.done < target {
+            if self.offload.ran.wait(&mut pending) {
+                return Err(EINTR);
+            }
+        }
+        Ok(())
+    }
+}
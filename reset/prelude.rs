@@ -0,0 +1,18 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! The commonly used items of the reset abstractions, so that drivers need one import line:
+//!
+//! ```ignore
+//! use kernel::reset::prelude::*;
+//! ```
+
+pub use super::{
+    AcquiredResetControl, BulkDeassertGuard, BulkSharedDeassert, ExclusiveResetControl, LineId,
+    OfXlate, ResetControl, ResetControlArray, ResetControlBulk, ResetDriverOps, ResetFlags,
+    ResetRegistration, ResetRequest, SharedDeassert, SharedResetControl,
+};
+pub use crate::{
+    declare_reset_lines, dt_reset_lines, impl_has_reset_registration, reset_controller_register,
+    static_reset_registration,
+};
+pub use macros::vtable;
@@ -0,0 +1,176 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Power sequencing provider driving a set of reset lines.
+//!
+//! Devices such as WiFi/BT combo modules are brought up by the power sequencing framework. A
+//! [`ResetPwrseq`] provides one target, whose enable asserts its lines, waits, deasserts them and
+//! waits again, and whose disable asserts them. Consumers are matched through a phandle property
+//! of their device tree node pointing to the node of the provider, e.g.:
+//!
+//! ```text
+//! wifi@0 {
+//!         reset-pwrseq = <&wlan_pwrseq>;
+//! };
+//! ```
+//!
+//! Consumers then use `pwrseq_get(dev, "<target>")`, `pwrseq_power_on` and `pwrseq_power_off`.
+//!
+//! C header: [`include/linux/pwrseq/provider.h`](../../../../include/linux/pwrseq/provider.h)
+
+use super::{ResetControlBulk, RetryPolicy};
+use crate::{
+    bindings,
+    delay::coarse_sleep,
+    device::RawDevice,
+    error::{code::*, from_kernel_err_ptr, from_result, Result},
+    str::CStr,
+    ThisModule,
+};
+
+use alloc::boxed::Box;
+use core::{ffi::c_int, time::Duration};
+
+/// Delays of the power-on sequence of a [`ResetPwrseq`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PwrseqDelays {
+    /// Time the lines are held asserted before being deasserted.
+    pub assert: Duration,
+    /// Time to wait after deasserting the lines, before the target is considered powered on.
+    pub post_deassert: Duration,
+    /// How the whole sequence is retried when asserting or deasserting the lines fails.
+    pub retry: RetryPolicy,
+}
+
+struct Inner {
+    lines: ResetControlBulk,
+    delays: PwrseqDelays,
+    of_node: *mut bindings::device_node,
+    property: &'static CStr,
+}
+
+impl Inner {
+    /// # Safety
+    ///
+    /// `pwrseq` must be a device registered by [`ResetPwrseq::register`].
+    unsafe fn from_pwrseq<'a>(pwrseq: *mut bindings::pwrseq_device) -> &'a Self {
+        // SAFETY: The driver data is the `Inner` owned by the `ResetPwrseq`, which unregisters
+        // the device before freeing it.
+        unsafe { &*(bindings::pwrseq_device_get_drvdata(pwrseq) as *const Self) }
+    }
+}
+
+/// A power sequencing provider driving a set of reset lines, unregistered when dropped.
+pub struct ResetPwrseq {
+    pwrseq: *mut bindings::pwrseq_device,
+    _inner: Box<Inner>,
+}
+
+impl ResetPwrseq {
+    /// Registers target `target` of `dev`, which drives `lines` with `delays`.
+    ///
+    /// Consumers whose device tree node has a phandle to the node of `dev` in property `property`
+    /// match the provider.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn register(
+        dev: &impl RawDevice,
+        module: &'static ThisModule,
+        target: &'static CStr,
+        property: &'static CStr,
+        lines: ResetControlBulk,
+        delays: PwrseqDelays,
+    ) -> Result<Self> {
+        let raw_dev = dev.raw_device();
+        // SAFETY: `raw_dev` is a valid device.
+        let of_node = unsafe { (*raw_dev).of_node };
+        if of_node.is_null() {
+            return Err(ENODEV);
+        }
+        let inner = Box::try_new(Inner {
+            lines,
+            delays,
+            of_node,
+            property,
+        })?;
+
+        let unit = bindings::pwrseq_unit_data {
+            name: target.as_char_ptr(),
+            deps: core::ptr::null_mut(),
+            enable: Some(enable),
+            disable: Some(disable),
+        };
+        let target = bindings::pwrseq_target_data {
+            name: target.as_char_ptr(),
+            unit: &unit,
+            post_enable: None,
+        };
+        let mut targets = [&target as *const _, core::ptr::null()];
+        let config = bindings::pwrseq_config {
+            parent: raw_dev,
+            owner: module.as_ptr(),
+            drvdata: &*inner as *const Inner as *mut _,
+            match_: Some(match_consumer),
+            targets: targets.as_mut_ptr(),
+        };
+        // SAFETY: `config` and the data it points to are valid for the duration of the call, the
+        // framework copies what it keeps. The driver data outlives the registration.
+        let pwrseq = from_kernel_err_ptr(unsafe { bindings::pwrseq_device_register(&config) })?;
+        Ok(Self {
+            pwrseq,
+            _inner: inner,
+        })
+    }
+}
+
+impl Drop for ResetPwrseq {
+    fn drop(&mut self) {
+        // SAFETY: `pwrseq` was registered in `register`.
+        unsafe { bindings::pwrseq_device_unregister(self.pwrseq) };
+    }
+}
+
+// SAFETY: The lines can be used from any thread, and the device is only used to unregister it,
+// which can be done from any thread.
+unsafe impl Send for ResetPwrseq {}
+
+// SAFETY: `ResetPwrseq` has no methods taking `&self`.
+unsafe impl Sync for ResetPwrseq {}
+
+unsafe extern "C" fn enable(pwrseq: *mut bindings::pwrseq_device) -> c_int {
+    from_result(|| {
+        // SAFETY: The framework only calls this for devices registered by `ResetPwrseq`.
+        let inner = unsafe { Inner::from_pwrseq(pwrseq) };
+        inner.delays.retry.run(|| {
+            inner.lines.assert_all()?;
+            coarse_sleep(inner.delays.assert);
+            inner.lines.deassert_all()
+        })?;
+        coarse_sleep(inner.delays.post_deassert);
+        Ok(0)
+    })
+}
+
+unsafe extern "C" fn disable(pwrseq: *mut bindings::pwrseq_device) -> c_int {
+    from_result(|| {
+        // SAFETY: The framework only calls this for devices registered by `ResetPwrseq`.
+        let inner = unsafe { Inner::from_pwrseq(pwrseq) };
+        inner.lines.assert_all()?;
+        Ok(0)
+    })
+}
+
+unsafe extern "C" fn match_consumer(
+    pwrseq: *mut bindings::pwrseq_device,
+    dev: *mut bindings::device,
+) -> c_int {
+    // SAFETY: The framework only calls this for devices registered by `ResetPwrseq`.
+    let inner = unsafe { Inner::from_pwrseq(pwrseq) };
+    // SAFETY: `dev` is a valid device, and `property` a valid C string.
+    let np = unsafe { bindings::of_parse_phandle((*dev).of_node, inner.property.as_char_ptr(), 0) };
+    if np.is_null() {
+        return 0;
+    }
+    let matches = np == inner.of_node;
+    // SAFETY: `of_parse_phandle` returned a reference to `np`.
+    unsafe { bindings::of_node_put(np) };
+    matches as c_int
+}
@@ -0,0 +1,173 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Quarantine of controllers whose operations keep failing.
+//!
+//! Providers opt in with [`ResetDriverOps::QUARANTINE_AFTER_FAILURES`]. Once that many
+//! operations fail in a row, on any line, the controller is quarantined: a critical message is
+//! logged, listeners added with [`add_quarantine_listener`] are notified, and all further
+//! operations fail right away with `ENOTRECOVERABLE` instead of reaching, e.g., dead firmware
+//! again. Operations returning `ENOTSUPP`, or `EAGAIN` as the controller is only busy, do not
+//! count as failures.
+//!
+//! The quarantine lasts until the provider lifts it with
+//! [`ResetRegistration::lift_quarantine`], e.g., once the firmware is reloaded:
+//!
+//! ```ignore
+//! let _listener = reset::add_quarantine_listener(Box::try_new(|controller: &CStr| {
+//!     pr_info!("reset controller {} is gone, stopping\n", controller);
+//! })?)?;
+//! ```
+//!
+//! [`ResetDriverOps::QUARANTINE_AFTER_FAILURES`]: super::ResetDriverOps::QUARANTINE_AFTER_FAILURES
+//! [`ResetRegistration::lift_quarantine`]: super::ResetRegistration::lift_quarantine
+
+use super::ResetOp;
+use crate::{
+    device::{self, RawDevice},
+    error::{code::*, Error, Result},
+    pr_crit,
+    str::CStr,
+    sync::{smutex::Mutex, Arc, UniqueArc},
+    workqueue::{self, Work},
+};
+
+use alloc::{boxed::Box, vec::Vec};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+/// Called with the name of the device of a controller once it is quarantined.
+///
+/// Runs from the system workqueue, with the listeners locked, so it must not add or remove
+/// listeners.
+pub type QuarantineNotify = Box<dyn Fn(&CStr) + Send + Sync>;
+
+static LISTENERS: Mutex<Vec<(u64, QuarantineNotify)>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A listener of quarantined controllers, removed when dropped.
+pub struct QuarantineListener {
+    id: u64,
+}
+
+impl Drop for QuarantineListener {
+    fn drop(&mut self) {
+        LISTENERS.lock().retain(|(id, _)| *id != self.id);
+    }
+}
+
+/// Calls `notify` whenever a controller is quarantined, until the returned listener is dropped.
+#[cfg_attr(klint, klint::preempt_count(expect = 0))]
+pub fn add_quarantine_listener(notify: QuarantineNotify) -> Result<QuarantineListener> {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    LISTENERS.lock().try_push((id, notify))?;
+    Ok(QuarantineListener { id })
+}
+
+struct Notify {
+    work: Work,
+    dev: device::Device,
+}
+
+crate::impl_self_work_adapter!(Notify, work, |n| n.run());
+
+impl Notify {
+    fn run(&self) {
+        for (_, notify) in LISTENERS.lock().iter() {
+            notify(self.dev.name());
+        }
+    }
+}
+
+// SAFETY: `device::Device` may be used and dropped from any thread, and `work` is only used by
+// the workqueue.
+unsafe impl Send for Notify {}
+
+// SAFETY: `Notify` has no methods modifying it.
+unsafe impl Sync for Notify {}
+
+/// Consecutive failures of a controller, and whether it is quarantined.
+pub(crate) struct Quarantine {
+    threshold: u32,
+    failures: AtomicU32,
+    quarantined: AtomicBool,
+    notify: Option<Arc<Notify>>,
+}
+
+impl Quarantine {
+    /// Creates the state of a controller that is never quarantined.
+    pub(crate) const fn new() -> Self {
+        Self {
+            threshold: 0,
+            failures: AtomicU32::new(0),
+            quarantined: AtomicBool::new(false),
+            notify: None,
+        }
+    }
+
+    /// Quarantines the controller of `dev` after `threshold` failures in a row, never if 0.
+    pub(crate) fn init(&mut self, dev: &impl RawDevice, threshold: u32) -> Result {
+        self.threshold = threshold;
+        if threshold == 0 {
+            return Ok(());
+        }
+        let notify = UniqueArc::try_new(Notify {
+            // SAFETY: `init_work_item` is called below.
+            work: unsafe { Work::new() },
+            dev: device::Device::from_dev(dev),
+        })?;
+        crate::init_work_item!(&notify);
+        self.notify = Some(notify.into());
+        Ok(())
+    }
+
+    /// Fails with `ENOTRECOVERABLE` if the controller is quarantined.
+    pub(crate) fn check(&self) -> Result {
+        if self.quarantined.load(Ordering::Acquire) {
+            return Err(ENOTRECOVERABLE);
+        }
+        Ok(())
+    }
+
+    /// Returns whether the controller is quarantined.
+    pub(crate) fn is_quarantined(&self) -> bool {
+        self.quarantined.load(Ordering::Acquire)
+    }
+
+    /// Accounts for a successful operation.
+    pub(crate) fn succeeded(&self) {
+        if self.threshold != 0 {
+            self.failures.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Accounts for failure `e` of operation `op` on line `id` of the controller of driver
+    /// `driver` and device `dev`, quarantining it past the threshold.
+    ///
+    /// Can be called from any context.
+    pub(crate) fn failed(&self, driver: &CStr, dev: &CStr, op: ResetOp, id: u64, e: Error) {
+        if self.threshold == 0 || e == ENOTSUPP || e == EAGAIN {
+            return;
+        }
+        let count = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if count < self.threshold || self.quarantined.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        pr_crit!(
+            "{} {}: quarantined after {} failed operations in a row, last {:?} of line {}: {:?}\n",
+            driver,
+            dev,
+            count,
+            op,
+            id,
+            e
+        );
+        if let Some(notify) = &self.notify {
+            workqueue::system().enqueue(notify.clone());
+        }
+    }
+
+    /// Lets operations reach the controller again.
+    pub(crate) fn lift(&self) {
+        self.failures.store(0, Ordering::Relaxed);
+        self.quarantined.store(false, Ordering::Release);
+    }
+}
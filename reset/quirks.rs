@@ -0,0 +1,135 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Quirks set with kernel parameters.
+//!
+//! They let marginal boards be debugged in the field without rebuilding drivers. Each parameter
+//! is a comma-separated list of `<value>` entries, applying to all controllers, and
+//! `<dev>:<value>` entries, only applying to the controller of device `<dev>`:
+//!
+//! - `reset.deassert_delay_ms`: waits that many milliseconds after every deassert.
+//! - `reset.slow_op_us`: warns about operations taking longer than that many microseconds,
+//!   overriding [`ResetDriverOps::SLOW_OP_THRESHOLD`]. `0` turns the warning off.
+//! - `reset.quarantine_after`: quarantines controllers after that many failed operations in a
+//!   row, overriding [`ResetDriverOps::QUARANTINE_AFTER_FAILURES`]. `0` turns it off.
+//!
+//! For example, `reset.deassert_delay_ms=5,soc-rcc:20` delays deasserts by 20 ms on the
+//! controller of `soc-rcc` and by 5 ms on the others. A per-controller entry wins over a global
+//! one, and later entries win over earlier ones of the same kind. The values are read when
+//! controllers are registered, and can be read back under `/sys/module/reset/parameters/`.
+//!
+//! The deassert delay is a sleep in the operation path, so it is skipped for callers in atomic
+//! context. Without `CONFIG_PREEMPT_COUNT` those are only detected when interrupts are disabled,
+//! so on such kernels it must only be set for controllers whose consumers deassert from process
+//! context.
+//!
+//! [`ResetDriverOps::SLOW_OP_THRESHOLD`]: super::ResetDriverOps::SLOW_OP_THRESHOLD
+//! [`ResetDriverOps::QUARANTINE_AFTER_FAILURES`]: super::ResetDriverOps::QUARANTINE_AFTER_FAILURES
+
+use crate::{bindings, pr_warn, str::CStr};
+
+use core::{
+    cell::UnsafeCell,
+    ffi::{c_char, c_void},
+    time::Duration,
+};
+
+/// The value of a `charp` kernel parameter, set by the parameter code.
+#[repr(transparent)]
+struct CharpValue(UnsafeCell<*mut c_char>);
+
+// SAFETY: The value is only written by the parameter code, while parsing the command line before
+// any driver probes, or under the `kernel_param_lock` through sysfs, which is read-only here.
+unsafe impl Sync for CharpValue {}
+
+/// A kernel parameter, placed in the section the parameter code walks.
+#[repr(transparent)]
+struct KernelParam(bindings::kernel_param);
+
+// SAFETY: The parameter is never modified after it is built.
+unsafe impl Sync for KernelParam {}
+
+/// Declares a read-only `charp` kernel parameter called `reset.<name>`, in `value`.
+macro_rules! quirk_param {
+    ($value:ident, $param:ident, $name:literal) => {
+        static $value: CharpValue = CharpValue(UnsafeCell::new(core::ptr::null_mut()));
+
+        #[link_section = "__param"]
+        #[used]
+        static $param: KernelParam = KernelParam(bindings::kernel_param {
+            name: concat!("reset.", $name, "\0").as_ptr() as *const c_char,
+            mod_: core::ptr::null_mut(),
+            // SAFETY: Only the address of the operations is taken.
+            ops: unsafe { &bindings::param_ops_charp } as *const _,
+            perm: 0o444,
+            level: -1,
+            flags: 0,
+            __bindgen_anon_1: bindings::kernel_param__bindgen_ty_1 {
+                arg: $value.0.get() as *mut c_void,
+            },
+        });
+    };
+}
+
+quirk_param!(DEASSERT_DELAY, DEASSERT_DELAY_PARAM, "deassert_delay_ms");
+quirk_param!(SLOW_OP, SLOW_OP_PARAM, "slow_op_us");
+quirk_param!(QUARANTINE_AFTER, QUARANTINE_AFTER_PARAM, "quarantine_after");
+
+/// Returns the extra delay to wait after deasserting a line of the controller of device `dev`.
+pub(crate) fn deassert_delay(dev: &CStr) -> Duration {
+    Duration::from_millis(value_for(&DEASSERT_DELAY, "deassert_delay_ms", dev).unwrap_or(0))
+}
+
+/// Returns the duration above which operations of the controller of device `dev` are reported,
+/// `default` unless set with `reset.slow_op_us`.
+pub(crate) fn slow_op_threshold(dev: &CStr, default: Duration) -> Duration {
+    value_for(&SLOW_OP, "slow_op_us", dev).map_or(default, Duration::from_micros)
+}
+
+/// Returns the number of failed operations in a row after which the controller of device `dev`
+/// is quarantined, `default` unless set with `reset.quarantine_after`.
+pub(crate) fn quarantine_after(dev: &CStr, default: u32) -> u32 {
+    value_for(&QUARANTINE_AFTER, "quarantine_after", dev)
+        .map_or(default, |n| n.min(u32::MAX as u64) as u32)
+}
+
+/// Returns the value of parameter `param`, called `name`, for the controller of device `dev`, if
+/// set.
+fn value_for(param: &CharpValue, name: &str, dev: &CStr) -> Option<u64> {
+    // SAFETY: The parameter is read-only in sysfs, so the value is only set while parsing the
+    // command line, before any controller is registered.
+    let ptr = unsafe { *param.0.get() };
+    if ptr.is_null() {
+        return None;
+    }
+    // SAFETY: The parameter code sets the value to a valid C string that is never freed.
+    let value = unsafe { CStr::from_char_ptr(ptr) };
+    let value = match core::str::from_utf8(value.as_bytes()) {
+        Ok(value) => value,
+        Err(_) => {
+            pr_warn!("Ignoring invalid reset.{}\n", name);
+            return None;
+        }
+    };
+
+    let mut global = None;
+    let mut local = None;
+    for entry in value.split(',').filter(|entry| !entry.is_empty()) {
+        let (target, n) = match entry.rsplit_once(':') {
+            Some((target, n)) => (Some(target), n),
+            None => (None, entry),
+        };
+        let n = match n.parse::<u64>() {
+            Ok(n) => n,
+            Err(_) => {
+                pr_warn!("Ignoring invalid reset.{} entry {}\n", name, entry);
+                continue;
+            }
+        };
+        match target {
+            None => global = Some(n),
+            Some(target) if target.as_bytes() == dev.as_bytes() => local = Some(n),
+            Some(_) => {}
+        }
+    }
+    local.or(global)
+}
@@ -0,0 +1,65 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Rate limiting of the messages logged by the operations of a controller.
+//!
+//! Like `printk_ratelimited`, at most [`BURST`] messages are logged per [`INTERVAL_NS`]. The state
+//! is lock-free, so checks can happen from the atomic contexts operations run in. Each class of
+//! messages has its own state, so that, e.g., a flood of slow-operation warnings does not hide
+//! errors.
+
+use crate::bindings;
+
+use core::sync::atomic::{AtomicI64, AtomicU32, Ordering};
+
+/// Length of a rate limiting window, in nanoseconds.
+const INTERVAL_NS: i64 = 5_000_000_000;
+
+/// Number of messages logged per window.
+const BURST: u32 = 10;
+
+/// Rate limiting state of one class of messages of a controller.
+pub(crate) struct Ratelimit {
+    /// `ktime` at the beginning of the current window, 0 before the first message.
+    begin: AtomicI64,
+    /// Number of messages checked in the current window.
+    checked: AtomicU32,
+    /// Number of messages suppressed since the last one logged.
+    missed: AtomicU32,
+}
+
+impl Ratelimit {
+    pub(crate) const fn new() -> Self {
+        Self {
+            begin: AtomicI64::new(0),
+            checked: AtomicU32::new(0),
+            missed: AtomicU32::new(0),
+        }
+    }
+
+    /// Checks whether a message may be logged.
+    ///
+    /// Returns `None` if the message must be suppressed, or the number of messages suppressed
+    /// since the previous window, to be reported along with it.
+    pub(crate) fn check(&self) -> Option<u32> {
+        // SAFETY: `ktime_get` has no safety requirements.
+        let now = unsafe { bindings::ktime_get() };
+        let begin = self.begin.load(Ordering::Relaxed);
+        let mut missed = 0;
+        if (begin == 0 || now.saturating_sub(begin) >= INTERVAL_NS)
+            && self
+                .begin
+                .compare_exchange(begin, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.checked.store(0, Ordering::Relaxed);
+            missed = self.missed.swap(0, Ordering::Relaxed);
+        }
+
+        if self.checked.fetch_add(1, Ordering::Relaxed) < BURST {
+            Some(missed)
+        } else {
+            self.missed.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+}
@@ -0,0 +1,258 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Registry of the reset controllers registered through [`ResetRegistration`].
+//!
+//! The C core keeps its controller list private to `drivers/reset/core.c`, so controllers
+//! registered by C drivers are not visible here.
+//!
+//! Exported in `/sys/kernel/debug/reset/controllers`, one line per controller with the name of
+//! its driver, its device and its number of lines:
+//!
+//! ```text
+//! sun50i-r-ccu 7010000.clock 3
+//! ```
+//!
+//! [`ResetRegistration`]: super::ResetRegistration
+
+use super::{
+    debugfs::{self, SeqWriter},
+    of::OfPhandleArgs,
+    stats::LineTimes,
+    topology, ResetDevice, ResetOp, ResetRequest,
+};
+use crate::{
+    bindings, c_str,
+    error::{code::*, Result},
+    str::CStr,
+    sync::{
+        smutex::{Guard, Mutex},
+        Arc,
+    },
+};
+
+use alloc::vec::Vec;
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Calls the operation of a controller on several lines, see [`ResetDriverOps::assert_many`].
+///
+/// [`ResetDriverOps::assert_many`]: super::ResetDriverOps::assert_many
+pub(crate) type ManyFn =
+    unsafe fn(*mut bindings::reset_controller_dev, ResetOp, &[ResetRequest]) -> Result;
+
+/// A controller registered from Rust.
+struct Entry {
+    rcdev: *mut bindings::reset_controller_dev,
+    /// The name of the driver of the controller.
+    name: &'static CStr,
+    /// Calls the operations of the controller on several lines, if it has some.
+    many: Option<ManyFn>,
+    /// Set, under its lock, when the controller is removed, so that operations on several lines
+    /// can run without the registry locked.
+    removed: Arc<Mutex<bool>>,
+    /// The accounting of the lines of the controller, which also holds their flags.
+    line_times: *const LineTimes,
+}
+
+// SAFETY: The controllers and the accounting of their lines are only accessed through shared
+// references, and `ResetDevice` and `LineTimes` are `Sync`.
+unsafe impl Send for Entry {}
+
+static CONTROLLERS: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+static DEBUGFS_CREATED: AtomicBool = AtomicBool::new(false);
+
+/// Adds `rcdev`, registered by driver `name`, to the registry.
+///
+/// `many` calls the operations of `rcdev` on several lines, if it has some, and `lines` is the
+/// accounting of its lines.
+///
+/// # Safety
+///
+/// `rcdev` and `lines` must be valid, and `rcdev` must be removed with [`remove`] before either
+/// is freed. `many` must be safe to call with `rcdev` until then.
+pub(crate) unsafe fn add(
+    rcdev: *mut bindings::reset_controller_dev,
+    name: &'static CStr,
+    many: Option<ManyFn>,
+    lines: *const LineTimes,
+) -> Result {
+    let removed = Arc::try_new(Mutex::new(false))?;
+    CONTROLLERS.lock().try_push(Entry {
+        rcdev,
+        name,
+        many,
+        removed,
+        line_times: lines,
+    })?;
+    if !DEBUGFS_CREATED.swap(true, Ordering::Relaxed) {
+        debugfs::create_file(c_str!("controllers"), show);
+        debugfs::create_file(c_str!("asserted_time"), show_asserted_time);
+    }
+    Ok(())
+}
+
+/// Removes `rcdev` from the registry.
+///
+/// Waits for the operations on several lines running on `rcdev` to complete.
+pub(crate) fn remove(rcdev: *mut bindings::reset_controller_dev) {
+    let mut removed = None;
+    CONTROLLERS.lock().retain(|e| {
+        if e.rcdev == rcdev {
+            removed = Some(e.removed.clone());
+        }
+        e.rcdev != rcdev
+    });
+    if let Some(removed) = removed {
+        *removed.lock() = true;
+    }
+    topology::forget_controller(rcdev);
+}
+
+/// The reset controllers registered from Rust, locked against registration and removal.
+///
+/// Returned by [`registered_controllers`].
+pub struct ResetDevices {
+    guard: Guard<'static, Vec<Entry>>,
+}
+
+impl ResetDevices {
+    /// Returns an iterator over the controllers.
+    pub fn iter(&self) -> impl Iterator<Item = &ResetDevice> {
+        // SAFETY: Controllers are removed from the registry before they go away, and the lock
+        // held by `guard` prevents that from happening while the references exist.
+        self.guard.iter().map(|e| unsafe { ResetDevice::from_raw(e.rcdev) })
+    }
+
+    /// Returns the controller registered for the device tree node `np`.
+    ///
+    /// `np` is only compared against the nodes of the controllers, it is never dereferenced.
+    pub fn find_by_of_node(&self, np: *const bindings::device_node) -> Option<&ResetDevice> {
+        self.iter().find(|rcdev| rcdev.of_node() as *const _ == np)
+    }
+
+    /// Returns the controller whose device has the firmware node `fwnode`.
+    ///
+    /// `fwnode` is only compared against the nodes of the controllers, it is never dereferenced.
+    pub fn find_by_fwnode(&self, fwnode: *const bindings::fwnode_handle) -> Option<&ResetDevice> {
+        self.iter().find(|rcdev| rcdev.fwnode() as *const _ == fwnode)
+    }
+}
+
+/// Translates `spec` with the controller it points to, if the controller was registered from
+/// Rust and `pred` holds for its entry.
+fn translate_if(
+    spec: &OfPhandleArgs,
+    pred: impl Fn(&Entry) -> bool,
+) -> Option<(*mut bindings::reset_controller_dev, ResetRequest)> {
+    let controllers = CONTROLLERS.lock();
+    let e = controllers
+        .iter()
+        .filter(|e| pred(e))
+        // SAFETY: Controllers are removed from the registry before they go away, and the lock
+        // prevents that from happening during the iteration.
+        .find(|e| unsafe { (*e.rcdev).of_node } == spec.node())?;
+    // SAFETY: As above, and `spec` is a valid specifier.
+    let ret = unsafe { ((*e.rcdev).of_xlate?)(e.rcdev, spec.as_raw()) };
+    if ret < 0 {
+        return None;
+    }
+    let id = ret as u64;
+    // SAFETY: As above, the accounting lives as long as the controller.
+    let flags = unsafe { (*e.line_times).flags(id) };
+    Some((e.rcdev, ResetRequest::new(id, flags)))
+}
+
+/// Translates `spec` with the controller it points to, if the controller was registered from
+/// Rust.
+///
+/// The controller is only returned for comparisons, it may go away as soon as this returns.
+pub(crate) fn translate(
+    spec: &OfPhandleArgs,
+) -> Option<(*mut bindings::reset_controller_dev, ResetRequest)> {
+    translate_if(spec, |_| true)
+}
+
+/// Translates `spec` with the controller it points to, if the controller was registered from
+/// Rust with operations on several lines.
+pub(crate) fn many_request(spec: &OfPhandleArgs) -> Option<ResetRequest> {
+    translate_if(spec, |e| e.many.is_some()).map(|(_, req)| req)
+}
+
+/// Calls operation `op` on the lines `reqs` of the controller of device tree node `np` at once.
+///
+/// Returns `None` if the controller is not registered from Rust or cannot do it, in which case
+/// the caller falls back to the operations on each line.
+pub(crate) fn call_many(
+    np: *const bindings::device_node,
+    op: ResetOp,
+    reqs: &[ResetRequest],
+) -> Option<Result> {
+    let (rcdev, many, removed) = {
+        let controllers = CONTROLLERS.lock();
+        // SAFETY: Controllers are removed from the registry before they go away, and the lock
+        // prevents that from happening during the iteration.
+        let e = controllers.iter().find(|e| unsafe { (*e.rcdev).of_node } as *const _ == np)?;
+        (e.rcdev, e.many?, e.removed.clone())
+    };
+    // The registry is unlocked, so that other controllers are not held up and the provider may
+    // use the registry. `remove` waits for this lock before the controller goes away.
+    let removed = removed.lock();
+    if *removed {
+        return None;
+    }
+    // SAFETY: The controller is not removed yet, and `many` is safe to call with it until then.
+    match unsafe { many(rcdev, op, reqs) } {
+        Err(ENOTSUPP) => None,
+        ret => Some(ret),
+    }
+}
+
+/// Locks the registry and returns the reset controllers registered from Rust.
+///
+/// Controllers can neither be registered nor dropped until the returned value is dropped, so it
+/// should not be held for long.
+#[cfg_attr(klint, klint::preempt_count(expect = 0))]
+pub fn registered_controllers() -> ResetDevices {
+    ResetDevices {
+        guard: CONTROLLERS.lock(),
+    }
+}
+
+fn show(w: &mut SeqWriter) -> core::fmt::Result {
+    for e in CONTROLLERS.lock().iter() {
+        // SAFETY: Controllers are removed from the registry before they go away, and the lock
+        // prevents that from happening during the iteration.
+        let (dev_name, nr_resets) = unsafe {
+            let dev = (*e.rcdev).dev;
+            (CStr::from_char_ptr(bindings::dev_name(dev)), (*e.rcdev).nr_resets)
+        };
+        writeln!(w, "{} {} {}", e.name, dev_name, nr_resets)?;
+    }
+    Ok(())
+}
+
+/// Prints the time each line spent asserted, for the lines asserted at least once, in
+/// microseconds: the total, and the current assertion or `-` if the line is deasserted.
+fn show_asserted_time(w: &mut SeqWriter) -> core::fmt::Result {
+    for e in CONTROLLERS.lock().iter() {
+        // SAFETY: Controllers and their accounting are removed from the registry before they go
+        // away, and the lock prevents that from happening during the iteration.
+        let (dev_name, lines) = unsafe {
+            (CStr::from_char_ptr(bindings::dev_name((*e.rcdev).dev)), &*e.line_times)
+        };
+        for id in 0..lines.len() as u64 {
+            let time = match lines.get(id) {
+                Some(time) if !time.total.is_zero() || time.current.is_some() => time,
+                _ => continue,
+            };
+            write!(w, "{} {} {}", dev_name, id, time.total.as_micros())?;
+            match time.current {
+                Some(current) => writeln!(w, " {}", current.as_micros())?,
+                None => writeln!(w, " -")?,
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Retrying operations that fail transiently.
+//!
+//! Lines mediated by firmware may fail now and then, e.g., while the firmware is busy with
+//! another request. A [`RetryPolicy`] describes how often and after how long such operations are
+//! retried, so that consumers and helpers retry them consistently:
+//!
+//! ```ignore
+//! const POLICY: RetryPolicy = RetryPolicy {
+//!     max_attempts: 5,
+//!     backoff: Duration::from_millis(1),
+//!     max_backoff: Duration::from_millis(20),
+//!     errors: &[EAGAIN, ETIMEDOUT],
+//! };
+//!
+//! rstc.reset_with_timeout_retry(Duration::from_millis(10), Duration::from_millis(1), &POLICY)?;
+//! ```
+
+use crate::{
+    delay::coarse_sleep,
+    error::{code::*, Error, Result},
+};
+
+use core::time::Duration;
+
+/// How an operation failing transiently is retried.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Number of times the operation is tried, including the first one.
+    pub max_attempts: u32,
+    /// Sleep before the first retry, doubled before each of the next ones.
+    pub backoff: Duration,
+    /// Upper bound of the sleep between two attempts.
+    pub max_backoff: Duration,
+    /// Errors on which the operation is retried, others are returned at once.
+    pub errors: &'static [Error],
+}
+
+impl RetryPolicy {
+    /// Tries operations once.
+    pub const ONCE: Self = Self {
+        max_attempts: 1,
+        backoff: Duration::ZERO,
+        max_backoff: Duration::ZERO,
+        errors: &[],
+    };
+
+    /// Calls `op` until it succeeds, fails with an error not in `errors`, or was tried
+    /// `max_attempts` times, returning its last result.
+    ///
+    /// Sleeps between attempts, so must be called from process context.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn run<R>(&self, mut op: impl FnMut() -> Result<R>) -> Result<R> {
+        let mut delay = self.backoff.min(self.max_backoff);
+        let mut attempt = 1;
+        loop {
+            match op() {
+                Err(e) if attempt < self.max_attempts && self.errors.contains(&e) => {
+                    coarse_sleep(delay);
+                    delay = delay.saturating_mul(2).min(self.max_backoff);
+                    attempt += 1;
+                }
+                ret => return ret,
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::ONCE
+    }
+}
+
+/// Errors on which [`super::ResetControl::retry_busy`] retries.
+pub(crate) const BUSY: &[Error] = &[EAGAIN];
@@ -0,0 +1,85 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Registration-time self-test, enabled with `CONFIG_RESET_RUST_SELFTEST`.
+//!
+//! Reads the status of every line, and toggles the lines listed in
+//! [`ResetDriverOps::SELFTEST_SAFE_LINES`] checking that the status follows. Mismatches are only
+//! logged: they point at polarity or offset bugs during bring-up, but never fail the probe.
+//!
+//! [`ResetDriverOps::SELFTEST_SAFE_LINES`]: super::ResetDriverOps::SELFTEST_SAFE_LINES
+
+use super::{ResetDevice, ResetOp};
+use crate::{error::code::*, pr_info, pr_warn, str::CStr};
+
+/// Runs the self-test on `rcdev`, registered for device `name`.
+pub(crate) fn run(name: &CStr, rcdev: &ResetDevice, safe_lines: &[u64]) {
+    let has_status = rcdev.supports(ResetOp::Status);
+    let mut failures = 0;
+
+    if has_status {
+        for id in 0..rcdev.nr_resets() as u64 {
+            if let Err(e) = rcdev.status(id) {
+                pr_warn!("{}: selftest: status of line {} failed: {:?}\n", name, id, e);
+                failures += 1;
+            }
+        }
+    }
+
+    for &id in safe_lines {
+        if id >= rcdev.nr_resets() as u64 {
+            pr_warn!("{}: selftest: safe line {} out of range\n", name, id);
+            failures += 1;
+            continue;
+        }
+        let initial = if has_status { rcdev.status(id).ok() } else { None };
+        if !check(name, rcdev, id, ResetOp::Assert, has_status) {
+            failures += 1;
+        }
+        if !check(name, rcdev, id, ResetOp::Deassert, has_status) {
+            failures += 1;
+        }
+        if initial == Some(true) {
+            let _ = rcdev.assert(id);
+        }
+    }
+
+    if failures == 0 {
+        pr_info!("{}: selftest passed\n", name);
+    }
+}
+
+/// Runs `op` on line `id`, and checks the status it leaves the line in if `has_status`.
+fn check(name: &CStr, rcdev: &ResetDevice, id: u64, op: ResetOp, has_status: bool) -> bool {
+    let (ret, expected) = match op {
+        ResetOp::Assert => (rcdev.assert(id), true),
+        _ => (rcdev.deassert(id), false),
+    };
+    match ret {
+        Ok(()) => {}
+        Err(e) if e == ENOTSUPP => return true,
+        Err(e) => {
+            pr_warn!("{}: selftest: {:?} of line {} failed: {:?}\n", name, op, id, e);
+            return false;
+        }
+    }
+    if !has_status {
+        return true;
+    }
+    match rcdev.status(id) {
+        Ok(asserted) if asserted == expected => true,
+        Ok(asserted) => {
+            pr_warn!(
+                "{}: selftest: line {} reads {} after {:?}, wrong polarity or offset?\n",
+                name,
+                id,
+                if asserted { "asserted" } else { "deasserted" },
+                op
+            );
+            false
+        }
+        Err(e) => {
+            pr_warn!("{}: selftest: status of line {} failed: {:?}\n", name, id, e);
+            false
+        }
+    }
+}
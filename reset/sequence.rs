@@ -0,0 +1,232 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Power-on sequences of regulators, clocks and reset lines.
+//!
+//! Most peripheral drivers power their device up the same way: enable supplies, enable clocks,
+//! take the device out of reset, waiting between some of the steps, and undo what was done when
+//! a step fails. A [`PowerOnSequence`] does it from a list of steps:
+//!
+//! ```ignore
+//! let seq = PowerOnSequence::builder(&pdev)
+//!     .regulator(c_str!("vdd"), Duration::from_millis(1))?
+//!     .clock(c_str!("bus"), Duration::ZERO)?
+//!     .reset(c_str!("core"), Duration::from_micros(100))?
+//!     .build();
+//!
+//! seq.power_on()?;
+//! ```
+//!
+//! Each step waits for its delay once done. Reset lines are asserted before the first step, so
+//! that the device only leaves reset at the step of its line.
+
+use super::ExclusiveResetControl;
+use crate::{
+    bindings,
+    delay::coarse_sleep,
+    device::RawDevice,
+    error::{code::*, from_kernel_err_ptr, to_result, Result},
+    str::CStr,
+    sync::smutex::Mutex,
+};
+
+use alloc::vec::Vec;
+use core::{ptr::NonNull, time::Duration};
+
+/// What a step of a [`PowerOnSequence`] enables.
+///
+/// # Invariants
+///
+/// The regulators and clocks are valid and owned, and released when the resource is dropped.
+enum Resource {
+    /// A supply, enabled when powering on.
+    Regulator(NonNull<bindings::regulator>),
+    /// A clock, prepared and enabled when powering on.
+    Clock(NonNull<bindings::clk>),
+    /// A reset line, deasserted when powering on.
+    Reset(ExclusiveResetControl),
+}
+
+impl Resource {
+    fn enable(&self) -> Result {
+        match self {
+            // SAFETY: By the type invariants, `r` is valid.
+            Resource::Regulator(r) => to_result(unsafe { bindings::regulator_enable(r.as_ptr()) }),
+            Resource::Clock(c) => {
+                // SAFETY: By the type invariants, `c` is valid.
+                to_result(unsafe { bindings::clk_prepare(c.as_ptr()) })?;
+                // SAFETY: By the type invariants, `c` is valid and was just prepared.
+                let ret = to_result(unsafe { bindings::clk_enable(c.as_ptr()) });
+                if ret.is_err() {
+                    // SAFETY: By the type invariants, `c` is valid and was prepared above.
+                    unsafe { bindings::clk_unprepare(c.as_ptr()) };
+                }
+                ret
+            }
+            Resource::Reset(rstc) => rstc.deassert(),
+        }
+    }
+
+    /// Undoes [`Resource::enable`], which must have succeeded.
+    fn disable(&self) {
+        match self {
+            Resource::Regulator(r) => {
+                // SAFETY: By the type invariants, `r` is valid, and it is enabled.
+                let _ = unsafe { bindings::regulator_disable(r.as_ptr()) };
+            }
+            Resource::Clock(c) => {
+                // SAFETY: By the type invariants, `c` is valid, and it is prepared and enabled.
+                unsafe {
+                    bindings::clk_disable(c.as_ptr());
+                    bindings::clk_unprepare(c.as_ptr());
+                }
+            }
+            Resource::Reset(rstc) => {
+                let _ = rstc.assert();
+            }
+        }
+    }
+}
+
+impl Drop for Resource {
+    fn drop(&mut self) {
+        match self {
+            // SAFETY: By the type invariants, we own `r`.
+            Resource::Regulator(r) => unsafe { bindings::regulator_put(r.as_ptr()) },
+            // SAFETY: By the type invariants, we own `c`.
+            Resource::Clock(c) => unsafe { bindings::clk_put(c.as_ptr()) },
+            Resource::Reset(_) => {}
+        }
+    }
+}
+
+struct Step {
+    resource: Resource,
+    delay: Duration,
+}
+
+/// Builds a [`PowerOnSequence`], returned by [`PowerOnSequence::builder`].
+pub struct PowerOnSequenceBuilder<'a, D: RawDevice> {
+    dev: &'a D,
+    steps: Vec<Step>,
+}
+
+impl<D: RawDevice> PowerOnSequenceBuilder<'_, D> {
+    fn step(mut self, resource: Resource, delay: Duration) -> Result<Self> {
+        self.steps.try_push(Step { resource, delay })?;
+        Ok(self)
+    }
+
+    /// Adds a step enabling the supply `id` of the device, then waiting for `delay`.
+    pub fn regulator(self, id: &CStr, delay: Duration) -> Result<Self> {
+        // SAFETY: The device is valid, and `id` is a valid C string.
+        let r = from_kernel_err_ptr(unsafe {
+            bindings::regulator_get(self.dev.raw_device(), id.as_char_ptr())
+        })?;
+        // INVARIANT: `regulator_get` returned a reference to a valid regulator.
+        let r = Resource::Regulator(NonNull::new(r).ok_or(EINVAL)?);
+        self.step(r, delay)
+    }
+
+    /// Adds a step enabling the clock `id` of the device, then waiting for `delay`.
+    pub fn clock(self, id: &CStr, delay: Duration) -> Result<Self> {
+        // SAFETY: The device is valid, and `id` is a valid C string.
+        let c = from_kernel_err_ptr(unsafe {
+            bindings::clk_get(self.dev.raw_device(), id.as_char_ptr())
+        })?;
+        // INVARIANT: `clk_get` returned a reference to a valid clock.
+        let c = Resource::Clock(NonNull::new(c).ok_or(EINVAL)?);
+        self.step(c, delay)
+    }
+
+    /// Adds a step deasserting the reset line `id` of the device, then waiting for `delay`.
+    pub fn reset(self, id: &CStr, delay: Duration) -> Result<Self> {
+        let rstc = ExclusiveResetControl::get_exclusive(self.dev, Some(id))?;
+        self.step(Resource::Reset(rstc), delay)
+    }
+
+    /// Adds a step deasserting `rstc`, then waiting for `delay`.
+    pub fn reset_control(self, rstc: ExclusiveResetControl, delay: Duration) -> Result<Self> {
+        self.step(Resource::Reset(rstc), delay)
+    }
+
+    /// Returns the sequence, powered off.
+    pub fn build(self) -> PowerOnSequence {
+        PowerOnSequence {
+            steps: self.steps,
+            powered: Mutex::new(false),
+        }
+    }
+}
+
+/// Regulators, clocks and reset lines powered on and off together, in order.
+///
+/// Powered off when dropped, if powered on.
+pub struct PowerOnSequence {
+    steps: Vec<Step>,
+    powered: Mutex<bool>,
+}
+
+impl PowerOnSequence {
+    /// Returns a builder for the sequence of `dev`, initially without steps.
+    pub fn builder<D: RawDevice>(dev: &D) -> PowerOnSequenceBuilder<'_, D> {
+        PowerOnSequenceBuilder {
+            dev,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Runs the steps in order, doing nothing if the sequence is already powered on.
+    ///
+    /// When a step fails, the steps done so far are undone in reverse order and its error is
+    /// returned.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn power_on(&self) -> Result {
+        let mut powered = self.powered.lock();
+        if *powered {
+            return Ok(());
+        }
+        for step in &self.steps {
+            if let Resource::Reset(rstc) = &step.resource {
+                rstc.assert()?;
+            }
+        }
+        for (i, step) in self.steps.iter().enumerate() {
+            if let Err(e) = step.resource.enable() {
+                self.steps[..i].iter().rev().for_each(|s| s.resource.disable());
+                return Err(e);
+            }
+            coarse_sleep(step.delay);
+        }
+        *powered = true;
+        Ok(())
+    }
+
+    /// Undoes the steps in reverse order, without waiting, doing nothing if the sequence is
+    /// powered off.
+    #[cfg_attr(klint, klint::preempt_count(expect = 0))]
+    pub fn power_off(&self) {
+        let mut powered = self.powered.lock();
+        if *powered {
+            self.steps.iter().rev().for_each(|s| s.resource.disable());
+            *powered = false;
+        }
+    }
+
+    /// Returns whether the sequence is powered on.
+    pub fn is_powered(&self) -> bool {
+        *self.powered.lock()
+    }
+}
+
+impl Drop for PowerOnSequence {
+    fn drop(&mut self) {
+        self.power_off();
+    }
+}
+
+// SAFETY: Regulators and clocks may be used and released from any thread, and so may
+// exclusive controls.
+unsafe impl Send for PowerOnSequence {}
+
+// SAFETY: Powering on and off is serialized by `powered`.
+unsafe impl Sync for PowerOnSequence {}
@@ -0,0 +1,42 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Snapshots of the state of the lines of a controller.
+//!
+//! Hardware state is lost when, e.g., the firmware of the provider is reloaded or an FPGA is
+//! reconfigured. Providers take a snapshot before, and restore it once the hardware is back:
+//!
+//! ```ignore
+//! let snapshot = reg.snapshot()?;
+//! reload_firmware(&data)?;
+//! reg.restore(&snapshot)?;
+//! ```
+
+use alloc::vec::Vec;
+
+/// The state of every line of a controller, returned by
+/// [`ResetRegistration::snapshot`].
+///
+/// [`ResetRegistration::snapshot`]: super::ResetRegistration::snapshot
+#[derive(Clone, Debug)]
+pub struct LineSnapshot {
+    /// Whether each line is asserted, `None` if its state could not be read.
+    pub(crate) lines: Vec<Option<bool>>,
+}
+
+impl LineSnapshot {
+    /// Returns the number of lines in the snapshot.
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Returns whether the snapshot has no lines.
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Returns whether line `id` was asserted, or `None` if its state is unknown or there is no
+    /// such line.
+    pub fn is_asserted(&self, id: u64) -> Option<bool> {
+        self.lines.get(id as usize).copied().flatten()
+    }
+}
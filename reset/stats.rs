@@ -0,0 +1,270 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Per-line accounting of reset controller operations.
+
+use super::{ResetFlags, ResetOp};
+use crate::{
+    bindings,
+    error::{code::*, Result},
+};
+
+use alloc::vec::Vec;
+use core::{
+    sync::atomic::{AtomicI64, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+/// Time a line has spent asserted, as seen by the framework.
+///
+/// Lines are only accounted from the first assert going through the framework, as the state
+/// the bootloader left them in is unknown.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AssertedTime {
+    /// Total time the line spent asserted, including the current assertion.
+    pub total: Duration,
+    /// Time since the line was asserted, or `None` if it is deasserted.
+    pub current: Option<Duration>,
+}
+
+/// Accounting of one line.
+///
+/// Only public for [`crate::static_reset_registration`].
+#[doc(hidden)]
+#[derive(Default)]
+pub struct LineTime {
+    /// `ktime` of the last assert, 0 while the line is deasserted.
+    asserted_at: AtomicI64,
+    /// Accumulated nanoseconds of completed assertions.
+    total_ns: AtomicU64,
+    /// Number of consecutive failed operations.
+    failures: AtomicU32,
+    /// Flags of the line, as translated from the specifiers of its consumers, with
+    /// [`FLAGS_RECORDED`] set once translated.
+    flags: AtomicU32,
+}
+
+/// Set in [`LineTime::flags`] once flags were recorded for the line.
+const FLAGS_RECORDED: u32 = 1 << 31;
+
+impl LineTime {
+    /// A line that was never operated.
+    pub const NEW: Self = Self {
+        asserted_at: AtomicI64::new(0),
+        total_ns: AtomicU64::new(0),
+        failures: AtomicU32::new(0),
+        flags: AtomicU32::new(0),
+    };
+}
+
+enum Lines {
+    Owned(Vec<LineTime>),
+    Static(&'static [LineTime]),
+}
+
+/// Asserted-time accounting for every line of a controller.
+///
+/// Updates are lock-free, so they can happen from the atomic contexts operations run in.
+pub(crate) struct LineTimes(Lines);
+
+impl LineTimes {
+    /// Creates the accounting of a controller without lines.
+    pub(crate) const fn new() -> Self {
+        Self(Lines::Owned(Vec::new()))
+    }
+
+    /// Creates the accounting of a controller whose lines are kept in `lines`.
+    pub(crate) const fn new_static(lines: &'static [LineTime]) -> Self {
+        Self(Lines::Static(lines))
+    }
+
+    /// Allocates the accounting of `nr_resets` lines, unless it is static, in which case it must
+    /// have exactly `nr_resets` lines.
+    pub(crate) fn init(&mut self, nr_resets: u32) -> Result {
+        if let Lines::Static(lines) = self.0 {
+            return if lines.len() == nr_resets as usize {
+                Ok(())
+            } else {
+                Err(EINVAL)
+            };
+        }
+        let mut lines = Vec::try_with_capacity(nr_resets as usize)?;
+        for _ in 0..nr_resets {
+            lines.try_push(LineTime::default())?;
+        }
+        self.0 = Lines::Owned(lines);
+        Ok(())
+    }
+
+    fn lines(&self) -> &[LineTime] {
+        match &self.0 {
+            Lines::Owned(lines) => lines,
+            Lines::Static(lines) => lines,
+        }
+    }
+
+    fn now() -> i64 {
+        // SAFETY: `ktime_get` has no safety requirements.
+        unsafe { bindings::ktime_get() }
+    }
+
+    /// Accounts for a successful `op` on line `id`.
+    pub(crate) fn record(&self, op: ResetOp, id: u64) {
+        let line = match self.lines().get(id as usize) {
+            Some(line) => line,
+            None => return,
+        };
+
+        line.failures.store(0, Ordering::Relaxed);
+        match op {
+            ResetOp::Assert => {
+                let _ = line.asserted_at.compare_exchange(
+                    0,
+                    Self::now(),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                );
+            }
+            ResetOp::Deassert => {
+                let since = line.asserted_at.swap(0, Ordering::Relaxed);
+                if since != 0 {
+                    let ns = Self::now().saturating_sub(since).max(0) as u64;
+                    line.total_ns.fetch_add(ns, Ordering::Relaxed);
+                }
+            }
+            ResetOp::Reset | ResetOp::Status => {}
+        }
+    }
+
+    /// Accounts for a failed operation on line `id`, returns the number of consecutive failures.
+    pub(crate) fn record_failure(&self, id: u64) -> u32 {
+        self.lines()
+            .get(id as usize)
+            .map_or(0, |line| line.failures.fetch_add(1, Ordering::Relaxed) + 1)
+    }
+
+    /// Returns the number of consecutive failed operations on line `id`.
+    pub(crate) fn failures(&self, id: u64) -> u32 {
+        self.lines()
+            .get(id as usize)
+            .map_or(0, |line| line.failures.load(Ordering::Relaxed))
+    }
+
+    /// Returns the number of lines.
+    pub(crate) fn len(&self) -> usize {
+        self.lines().len()
+    }
+
+    /// Returns the asserted time of line `id`, or `None` if there is no such line.
+    pub(crate) fn get(&self, id: u64) -> Option<AssertedTime> {
+        let line = self.lines().get(id as usize)?;
+        let since = line.asserted_at.load(Ordering::Relaxed);
+        let total = Duration::from_nanos(line.total_ns.load(Ordering::Relaxed));
+        let current = if since != 0 {
+            Some(Duration::from_nanos(Self::now().saturating_sub(since).max(0) as u64))
+        } else {
+            None
+        };
+        Some(AssertedTime {
+            total: total + current.unwrap_or_default(),
+            current,
+        })
+    }
+
+    /// Records `flags` as the flags of line `id`, as translated from a specifier.
+    ///
+    /// The C core only knows line ids, so a line has a single set of flags. Fails with `EINVAL` if
+    /// there is no such line, or if another specifier gave it different flags.
+    pub(crate) fn record_flags(&self, id: u64, flags: ResetFlags) -> Result {
+        let line = self.lines().get(id as usize).ok_or(EINVAL)?;
+        let new = flags.bits() | FLAGS_RECORDED;
+        match line
+            .flags
+            .compare_exchange(0, new, Ordering::Relaxed, Ordering::Relaxed)
+        {
+            Ok(_) => Ok(()),
+            Err(old) if old == new => Ok(()),
+            Err(_) => Err(EINVAL),
+        }
+    }
+
+    /// Returns the flags recorded for line `id`, empty if it was never translated.
+    pub(crate) fn flags(&self, id: u64) -> ResetFlags {
+        let bits = self
+            .lines()
+            .get(id as usize)
+            .map_or(0, |line| line.flags.load(Ordering::Relaxed));
+        ResetFlags(bits & !FLAGS_RECORDED)
+    }
+}
+
+/// Number of operations kept in an [`OpHistory`].
+const HISTORY_DEPTH: usize = 16;
+
+/// An operation recorded in an [`OpHistory`].
+pub(crate) struct OpRecord {
+    /// `ktime` of the operation.
+    pub(crate) time: i64,
+    pub(crate) op: ResetOp,
+    pub(crate) id: u64,
+    /// 0 on success, the negative errno on failure.
+    pub(crate) errno: i32,
+}
+
+/// The last operations of a controller.
+///
+/// Updates are lock-free, so they can happen from the atomic contexts operations run in. Entries
+/// may be torn by concurrent updates, which is fine for diagnostics.
+pub(crate) struct OpHistory {
+    next: AtomicUsize,
+    times: [AtomicI64; HISTORY_DEPTH],
+    /// Line id in the low 32 bits, op above, errno in the top 16 bits, 0 for unused entries.
+    entries: [AtomicU64; HISTORY_DEPTH],
+}
+
+impl OpHistory {
+    pub(crate) const fn new() -> Self {
+        const TIME: AtomicI64 = AtomicI64::new(0);
+        const ENTRY: AtomicU64 = AtomicU64::new(0);
+        Self {
+            next: AtomicUsize::new(0),
+            times: [TIME; HISTORY_DEPTH],
+            entries: [ENTRY; HISTORY_DEPTH],
+        }
+    }
+
+    /// Records operation `op` on line `id`, which returned `ret`.
+    pub(crate) fn record<T>(&self, op: ResetOp, id: u64, ret: &Result<T>) {
+        let errno = match ret {
+            Ok(_) => 0,
+            Err(e) => e.to_kernel_errno(),
+        };
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % HISTORY_DEPTH;
+        let packed = (id & 0xffff_ffff)
+            | (op as u64 + 1) << 32
+            | ((-errno) as u16 as u64) << 48;
+        self.times[i].store(LineTimes::now(), Ordering::Relaxed);
+        self.entries[i].store(packed, Ordering::Relaxed);
+    }
+
+    /// Returns the recorded operations, oldest first.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = OpRecord> + '_ {
+        let next = self.next.load(Ordering::Relaxed);
+        (0..HISTORY_DEPTH).filter_map(move |n| {
+            let i = (next + n) % HISTORY_DEPTH;
+            let packed = self.entries[i].load(Ordering::Relaxed);
+            let op = match (packed >> 32) & 0xffff {
+                1 => ResetOp::Reset,
+                2 => ResetOp::Assert,
+                3 => ResetOp::Deassert,
+                4 => ResetOp::Status,
+                _ => return None,
+            };
+            Some(OpRecord {
+                time: self.times[i].load(Ordering::Relaxed),
+                op,
+                id: packed & 0xffff_ffff,
+                errno: -((packed >> 48) as i32),
+            })
+        })
+    }
+}
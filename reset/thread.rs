@@ -0,0 +1,276 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Operations run in order by a dedicated kernel thread.
+//!
+//! Controllers fronting firmware mailboxes that cannot reorder or interleave commands set
+//! [`ResetDriverOps::THREADED_OPS`]. All their operations then go through a FIFO processed by a
+//! kernel thread of their own, one at a time and in submission order:
+//!
+//! - callers in process context wait for their operation to run and get its result, blocking
+//!   while the FIFO is full;
+//! - callers in atomic context get success back as soon as resets, asserts and deasserts are
+//!   queued, `EBUSY` if the FIFO is full, and `EAGAIN` for status queries. The provider waits for
+//!   the queued operations with [`ResetRegistration::flush_queued`].
+//!
+//! [`ResetDriverOps::THREADED_OPS`]: super::ResetDriverOps::THREADED_OPS
+//! [`ResetRegistration::flush_queued`]: super::ResetRegistration::flush_queued
+
+use super::{
+    offload::{self, RunFn},
+    ResetOp,
+};
+use crate::{
+    bindings,
+    delay::coarse_sleep,
+    error::{code::*, Result},
+    fmt, pr_err,
+    str::CStr,
+    sync::{Arc, CondVar, SpinLock, UniqueArc},
+    task::Task,
+};
+
+use core::{pin::Pin, time::Duration};
+
+/// Maximum number of operations waiting to be run.
+const FIFO_DEPTH: usize = 16;
+
+/// The result of an operation, for the caller waiting for it.
+struct Done {
+    result: SpinLock<Option<Result<i32>>>,
+    done: CondVar,
+}
+
+struct Item {
+    op: ResetOp,
+    id: u64,
+    /// `None` for operations queued from atomic context, which nobody waits for.
+    done: Option<Arc<Done>>,
+}
+
+struct Fifo {
+    items: [Option<Item>; FIFO_DEPTH],
+    head: usize,
+    len: usize,
+    /// Set when the thread has to exit once the FIFO is empty.
+    stopping: bool,
+    /// Set by the thread when it exits.
+    exited: bool,
+    /// Number of items queued so far.
+    submitted: u64,
+    /// Number of items that have run so far.
+    completed: u64,
+}
+
+struct Shared {
+    fifo: SpinLock<Fifo>,
+    /// Signalled when an item is queued or the thread has to stop.
+    queued: CondVar,
+    /// Signalled when an item is taken from the FIFO or has run, or when the thread exits.
+    taken: CondVar,
+    rcdev: *mut bindings::reset_controller_dev,
+    run: RunFn,
+}
+
+// SAFETY: `rcdev` is only passed to `run`, which may be called from any thread.
+unsafe impl Send for Shared {}
+
+// SAFETY: `fifo` is protected by its lock, and the other fields are never modified.
+unsafe impl Sync for Shared {}
+
+impl Shared {
+    fn thread(&self) {
+        loop {
+            let item = {
+                let mut fifo = self.fifo.lock_irqdisable();
+                loop {
+                    if fifo.len != 0 {
+                        let head = fifo.head;
+                        fifo.head = (head + 1) % FIFO_DEPTH;
+                        fifo.len -= 1;
+                        break fifo.items[head].take();
+                    }
+                    if fifo.stopping {
+                        fifo.exited = true;
+                        self.taken.notify_all();
+                        return;
+                    }
+                    // Kernel threads do not get signals, so the wait is never interrupted.
+                    let _ = self.queued.wait(&mut fifo);
+                }
+            };
+            self.taken.notify_all();
+            let item = match item {
+                Some(item) => item,
+                None => continue,
+            };
+
+            // SAFETY: `rcdev` outlives the thread, which is stopped (and drains the FIFO) first.
+            let ret = unsafe { (self.run)(self.rcdev, item.op, item.id) };
+            match item.done {
+                Some(done) => {
+                    *done.result.lock() = Some(ret);
+                    done.done.notify_all();
+                }
+                None => {
+                    if let Err(e) = ret {
+                        let (op, id) = (item.op, item.id);
+                        pr_err!("Queued {:?} of reset line {} failed: {:?}\n", op, id, e);
+                    }
+                }
+            }
+            self.fifo.lock_irqdisable().completed += 1;
+            self.taken.notify_all();
+        }
+    }
+}
+
+/// The kernel thread and FIFO of a controller with threaded operations.
+pub(crate) struct OpThread {
+    shared: Arc<Shared>,
+}
+
+impl OpThread {
+    /// Starts the thread of controller `rcdev`, whose operations are run by `run`.
+    ///
+    /// # Safety
+    ///
+    /// `rcdev` must be valid until the returned thread is dropped.
+    pub(crate) unsafe fn try_new(
+        name: &CStr,
+        rcdev: *mut bindings::reset_controller_dev,
+        run: RunFn,
+    ) -> Result<Self> {
+        const NONE: Option<Item> = None;
+        let mut shared = Pin::from(UniqueArc::try_new(Shared {
+            // SAFETY: `spinlock_init` is called below.
+            fifo: unsafe {
+                SpinLock::new(Fifo {
+                    items: [NONE; FIFO_DEPTH],
+                    head: 0,
+                    len: 0,
+                    stopping: false,
+                    exited: false,
+                    submitted: 0,
+                    completed: 0,
+                })
+            },
+            // SAFETY: `condvar_init` is called below.
+            queued: unsafe { CondVar::new() },
+            // SAFETY: `condvar_init` is called below.
+            taken: unsafe { CondVar::new() },
+            rcdev,
+            run,
+        })?);
+
+        // SAFETY: `fifo` is pinned when `shared` is.
+        let fifo = unsafe { shared.as_mut().map_unchecked_mut(|s| &mut s.fifo) };
+        crate::spinlock_init!(fifo, "OpThread::fifo");
+        // SAFETY: `queued` is pinned when `shared` is.
+        let queued = unsafe { shared.as_mut().map_unchecked_mut(|s| &mut s.queued) };
+        crate::condvar_init!(queued, "OpThread::queued");
+        // SAFETY: `taken` is pinned when `shared` is.
+        let taken = unsafe { shared.as_mut().map_unchecked_mut(|s| &mut s.taken) };
+        crate::condvar_init!(taken, "OpThread::taken");
+
+        let shared: Arc<Shared> = shared.into();
+        let thread_shared = shared.clone();
+        Task::spawn(fmt!("{}-reset", name), move || thread_shared.thread())?;
+        Ok(Self { shared })
+    }
+
+    /// Runs operation `op` on line `id` on the thread, after the operations queued before it.
+    ///
+    /// Waits for the operation and returns its result in process context, where it fails with
+    /// `EINTR` if interrupted by a signal, the operation still running later. Only queues it in
+    /// atomic context, see the module documentation.
+    pub(crate) fn submit(&self, op: ResetOp, id: u64) -> Result<i32> {
+        let atomic = offload::in_atomic_context();
+        if atomic && op == ResetOp::Status {
+            return Err(EAGAIN);
+        }
+        let done = if atomic {
+            None
+        } else {
+            let mut done = Pin::from(UniqueArc::try_new(Done {
+                // SAFETY: `spinlock_init` is called below.
+                result: unsafe { SpinLock::new(None) },
+                // SAFETY: `condvar_init` is called below.
+                done: unsafe { CondVar::new() },
+            })?);
+            // SAFETY: `result` is pinned when `done` is.
+            let result = unsafe { done.as_mut().map_unchecked_mut(|d| &mut d.result) };
+            crate::spinlock_init!(result, "OpThread::result");
+            // SAFETY: `done` is pinned when its container is.
+            let cv = unsafe { done.as_mut().map_unchecked_mut(|d| &mut d.done) };
+            crate::condvar_init!(cv, "OpThread::done");
+            Some(Arc::<Done>::from(done))
+        };
+
+        {
+            let mut fifo = self.shared.fifo.lock_irqdisable();
+            while fifo.len == FIFO_DEPTH {
+                if atomic {
+                    return Err(EBUSY);
+                }
+                if self.shared.taken.wait(&mut fifo) {
+                    return Err(EINTR);
+                }
+            }
+            let tail = (fifo.head + fifo.len) % FIFO_DEPTH;
+            fifo.items[tail] = Some(Item {
+                op,
+                id,
+                done: done.clone(),
+            });
+            fifo.len += 1;
+            fifo.submitted += 1;
+        }
+        self.shared.queued.notify_one();
+
+        let done = match done {
+            Some(done) => done,
+            // Any other value would be taken for a failure by the C core.
+            None => return Ok(0),
+        };
+        let mut result = done.result.lock();
+        loop {
+            if let Some(ret) = *result {
+                return ret;
+            }
+            if done.done.wait(&mut result) {
+                return Err(EINTR);
+            }
+        }
+    }
+
+    /// Waits for the items queued so far to have run.
+    ///
+    /// Fails with `EINTR` if interrupted by a signal.
+    pub(crate) fn flush(&self) -> Result {
+        let mut fifo = self.shared.fifo.lock_irqdisable();
+        let target = fifo.submitted;
+        while fifo.completed < target {
+            if self.shared.taken.wait(&mut fifo) {
+                return Err(EINTR);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for OpThread {
+    fn drop(&mut self) {
+        let mut fifo = self.shared.fifo.lock_irqdisable();
+        fifo.stopping = true;
+        self.shared.queued.notify_one();
+        // The queued operations run before the thread exits.
+        while !fifo.exited {
+            if self.shared.taken.wait(&mut fifo) {
+                // A signal is pending, wait without spinning on it.
+                drop(fifo);
+                coarse_sleep(Duration::from_millis(1));
+                fifo = self.shared.fifo.lock_irqdisable();
+            }
+        }
+    }
+}
@@ -0,0 +1,284 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Provider to line to consumer topology, built from successful consumer gets.
+//!
+//! Exported in `/sys/kernel/debug/reset/topology`, one line per reference held by a consumer:
+//!
+//! ```text
+//! reset-controller@1000 [12] -> 2000.ethernet (exclusive)
+//! ```
+//!
+//! The lines of controllers registered from Rust are also tracked by line id, so that providers
+//! can query who holds them with [`ResetRegistration::line_holders`]. They are exported in
+//! `/sys/kernel/debug/reset/owners`, one line per held line with its owner or its number of
+//! sharers:
+//!
+//! ```text
+//! reset-controller@1000 12: 2000.ethernet
+//! reset-controller@1000 3: 2 sharers
+//! ```
+//!
+//! Only consumers going through the Rust abstractions are known, those written in C are not.
+//!
+//! Consumers holding lines of a controller registered from Rust are also told when it goes away,
+//! e.g., on hot-unplug or overlay removal, see [`ResetControl::on_provider_removed`].
+//!
+//! [`ResetRegistration::line_holders`]: super::ResetRegistration::line_holders
+//! [`ResetControl::on_provider_removed`]: super::ResetControl::on_provider_removed
+
+use super::{
+    debugfs::{self, SeqWriter},
+    of::OfSpec,
+    registry,
+};
+use crate::{
+    bindings, c_str,
+    device::{self, RawDevice},
+    error::{code::*, Result},
+    str::CString,
+    sync::{smutex::Mutex, Arc},
+};
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+struct Entry {
+    key: u64,
+    consumer: device::Device,
+    provider_name: CString,
+    args: [u32; bindings::MAX_PHANDLE_ARGS as usize],
+    nargs: usize,
+    shared: bool,
+    /// The controller, if registered from Rust, and the id of the line.
+    line: Option<(*mut bindings::reset_controller_dev, u64)>,
+    removal: Arc<Removal>,
+}
+
+// SAFETY: The controller is only compared, never dereferenced.
+unsafe impl Send for Entry {}
+
+/// Who holds a line of a controller, see [`ResetRegistration::line_holders`].
+///
+/// [`ResetRegistration::line_holders`]: super::ResetRegistration::line_holders
+pub enum LineHolders {
+    /// No consumer holds the line.
+    Free,
+    /// The line is held exclusively by a consumer device.
+    Exclusive(device::Device),
+    /// The line is shared by this number of references.
+    Shared(usize),
+}
+
+static ENTRIES: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+static NEXT_KEY: AtomicU64 = AtomicU64::new(1);
+static DEBUGFS_CREATED: AtomicBool = AtomicBool::new(false);
+
+/// Called when the controller of a line is about to be unregistered.
+///
+/// Runs in process context, while the operations of the controller still work, so the consumer
+/// may put its device in a safe state. It must not call [`ResetControl::on_provider_removed`].
+///
+/// [`ResetControl::on_provider_removed`]: super::ResetControl::on_provider_removed
+pub type RemovalNotify = Box<dyn Fn() + Send + Sync>;
+
+/// Whether the controller of a line went away, shared by a handle and its entry.
+struct Removal {
+    removed: AtomicBool,
+    notify: Mutex<Option<RemovalNotify>>,
+}
+
+/// A consumer reference recorded in the topology, removed when dropped.
+pub(crate) struct TopologyHandle {
+    key: u64,
+    /// The controller, if registered from Rust, so that its removal is known.
+    controller: Option<*mut bindings::reset_controller_dev>,
+    removal: Arc<Removal>,
+}
+
+// SAFETY: The controller is only compared, never dereferenced.
+unsafe impl Send for TopologyHandle {}
+
+// SAFETY: As above.
+unsafe impl Sync for TopologyHandle {}
+
+impl TopologyHandle {
+    /// Returns the controller of the line, if it was registered from Rust.
+    pub(crate) fn controller(&self) -> Option<*mut bindings::reset_controller_dev> {
+        self.controller
+    }
+
+    /// Returns whether the controller of the line was unregistered.
+    pub(crate) fn is_removed(&self) -> bool {
+        self.removal.removed.load(Ordering::Acquire)
+    }
+
+    /// Fails with `ENODEV` if the controller of the line was unregistered.
+    pub(crate) fn check_not_removed(&self) -> Result {
+        if self.is_removed() {
+            return Err(ENODEV);
+        }
+        Ok(())
+    }
+
+    /// Calls `notify` when the controller of the line is about to be unregistered, replacing the
+    /// previous callback.
+    ///
+    /// Fails with `ENOTSUPP` if the controller was not registered from Rust, and with `ENODEV` if
+    /// it is already gone.
+    pub(crate) fn set_removal_notify(&self, notify: RemovalNotify) -> Result {
+        if self.controller.is_none() {
+            return Err(ENOTSUPP);
+        }
+        let mut current = self.removal.notify.lock();
+        self.check_not_removed()?;
+        *current = Some(notify);
+        Ok(())
+    }
+}
+
+impl Drop for TopologyHandle {
+    fn drop(&mut self) {
+        ENTRIES.lock().retain(|e| e.key != self.key);
+    }
+}
+
+/// Records that `dev` got a reference to the line described by `spec`.
+///
+/// This is best effort: lines that fail to be recorded are left out of the topology.
+pub(crate) fn record(
+    dev: &impl RawDevice,
+    spec: &OfSpec,
+    shared: bool,
+) -> Option<TopologyHandle> {
+    let provider_name = spec.provider_name();
+
+    let mut args = [0; bindings::MAX_PHANDLE_ARGS as usize];
+    args[..spec.args().len()].copy_from_slice(spec.args());
+
+    let key = NEXT_KEY.fetch_add(1, Ordering::Relaxed);
+    let removal = Arc::try_new(Removal {
+        removed: AtomicBool::new(false),
+        notify: Mutex::new(None),
+    })
+    .ok()?;
+    let line = registry::translate(spec).map(|(rcdev, req)| (rcdev, req.id()));
+    let entry = Entry {
+        key,
+        consumer: device::Device::from_dev(dev),
+        provider_name: provider_name.ok()?,
+        args,
+        nargs: spec.args().len(),
+        shared,
+        line,
+        removal: removal.clone(),
+    };
+    ENTRIES.lock().try_push(entry).ok()?;
+
+    if !DEBUGFS_CREATED.swap(true, Ordering::Relaxed) {
+        debugfs::create_file(c_str!("topology"), show);
+        debugfs::create_file(c_str!("owners"), show_owners);
+    }
+
+    Some(TopologyHandle {
+        key,
+        controller: line.map(|(rcdev, _)| rcdev),
+        removal,
+    })
+}
+
+/// Tells the consumers of the lines of `rcdev` that it is about to be unregistered, then makes
+/// their controls fail with `ENODEV`.
+///
+/// Must be called from process context, while the operations of `rcdev` still work.
+pub(crate) fn provider_removed(rcdev: *mut bindings::reset_controller_dev) {
+    let mut removals = Vec::new();
+    for e in ENTRIES.lock().iter() {
+        if !e.line.map_or(false, |(r, _)| r == rcdev) {
+            continue;
+        }
+        if removals.try_push(e.removal.clone()).is_err() {
+            // Consumers are not notified without memory, but their controls still stop working.
+            e.removal.removed.store(true, Ordering::Release);
+        }
+    }
+    // The callbacks run without the entries locked, so that they can use the topology.
+    for removal in &removals {
+        let notify = removal.notify.lock();
+        if let Some(notify) = &*notify {
+            notify();
+        }
+        removal.removed.store(true, Ordering::Release);
+    }
+}
+
+/// Forgets the line ids of the lines of `rcdev`, which goes away.
+pub(crate) fn forget_controller(rcdev: *mut bindings::reset_controller_dev) {
+    for e in ENTRIES.lock().iter_mut() {
+        if e.line.map_or(false, |(r, _)| r == rcdev) {
+            e.line = None;
+        }
+    }
+}
+
+/// Returns who holds line `id` of `rcdev`.
+pub(crate) fn holders(rcdev: *mut bindings::reset_controller_dev, id: u64) -> LineHolders {
+    let entries = ENTRIES.lock();
+    let mut held = entries.iter().filter(|e| e.line == Some((rcdev, id)));
+    match held.next() {
+        None => LineHolders::Free,
+        Some(e) if !e.shared => LineHolders::Exclusive(device::Device::from_dev(&e.consumer)),
+        Some(_) => LineHolders::Shared(1 + held.count()),
+    }
+}
+
+/// Returns whether any line of `rcdev` is held.
+pub(crate) fn any_held(rcdev: *mut bindings::reset_controller_dev) -> bool {
+    ENTRIES
+        .lock()
+        .iter()
+        .any(|e| e.line.map_or(false, |(r, _)| r == rcdev))
+}
+
+fn show_owners(w: &mut SeqWriter) -> core::fmt::Result {
+    let entries = ENTRIES.lock();
+    for (i, e) in entries.iter().enumerate() {
+        let line = match e.line {
+            Some(line) => line,
+            None => continue,
+        };
+        // Each line is listed once, at its first reference.
+        if entries[..i].iter().any(|prev| prev.line == Some(line)) {
+            continue;
+        }
+        write!(w, "{} {}: ", &*e.provider_name, line.1)?;
+        if e.shared {
+            let sharers = entries.iter().filter(|e| e.line == Some(line)).count();
+            writeln!(w, "{} sharers", sharers)?;
+        } else {
+            writeln!(w, "{}", e.consumer.name())?;
+        }
+    }
+    Ok(())
+}
+
+fn show(w: &mut SeqWriter) -> core::fmt::Result {
+    for e in ENTRIES.lock().iter() {
+        write!(w, "{} [", &*e.provider_name)?;
+        for (i, arg) in e.args[..e.nargs].iter().enumerate() {
+            if i != 0 {
+                w.write_char(' ')?;
+            }
+            write!(w, "{}", arg)?;
+        }
+        writeln!(
+            w,
+            "] -> {} ({})",
+            e.consumer.name(),
+            if e.shared { "shared" } else { "exclusive" }
+        )?;
+    }
+    Ok(())
+}
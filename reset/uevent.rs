@@ -0,0 +1,152 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Uevents sent to userspace when designated lines change.
+//!
+//! Providers list the lines with [`ResetRegistration::set_uevent_lines`]. Every successful assert,
+//! deassert or reset of one of them sends a `change` uevent for the controller device with:
+//!
+//! - `RESET_LINE=<id>`,
+//! - `RESET_EVENT=asserted`, `deasserted` or `pulsed`,
+//! - `RESET_NAME=<name>`, if the device tree translation knows the name of the line.
+//!
+//! Operations may run in atomic context while uevents are sent from process context, so events are
+//! kept in a fixed-size FIFO drained by the system workqueue. Events are dropped if it overflows.
+//!
+//! [`ResetRegistration::set_uevent_lines`]: super::ResetRegistration::set_uevent_lines
+
+use super::{OfXlate, ResetOp};
+use crate::{
+    bindings,
+    device::{self, RawDevice},
+    error::Result,
+    fmt, pr_warn,
+    str::CString,
+    sync::{Arc, SpinLock, UniqueArc},
+    workqueue::{self, Work},
+};
+
+use core::pin::Pin;
+
+/// Maximum number of events waiting to be sent.
+const QUEUE_DEPTH: usize = 16;
+
+struct Pending {
+    events: [(ResetOp, u64); QUEUE_DEPTH],
+    head: usize,
+    len: usize,
+}
+
+struct Inner {
+    pending: SpinLock<Pending>,
+    work: Work,
+    dev: device::Device,
+    xlate: OfXlate,
+}
+
+crate::impl_self_work_adapter!(Inner, work, |w| w.drain());
+
+impl Inner {
+    fn drain(&self) {
+        loop {
+            let (op, id) = {
+                let mut pending = self.pending.lock_irqdisable();
+                if pending.len == 0 {
+                    break;
+                }
+                let next = pending.events[pending.head];
+                pending.head = (pending.head + 1) % QUEUE_DEPTH;
+                pending.len -= 1;
+                next
+            };
+            if let Err(e) = self.send(op, id) {
+                pr_warn!("Failed to send uevent for reset line {}: {:?}\n", id, e);
+            }
+        }
+    }
+
+    fn send(&self, op: ResetOp, id: u64) -> Result {
+        let event = match op {
+            ResetOp::Assert => "asserted",
+            ResetOp::Deassert => "deasserted",
+            _ => "pulsed",
+        };
+        let line = CString::try_from_fmt(fmt!("RESET_LINE={}", id))?;
+        let event = CString::try_from_fmt(fmt!("RESET_EVENT={}", event))?;
+        let name = match self.xlate.name_of(id) {
+            Some(name) => Some(CString::try_from_fmt(fmt!("RESET_NAME={}", name))?),
+            None => None,
+        };
+
+        let mut envp = [
+            line.as_char_ptr() as *mut _,
+            event.as_char_ptr() as *mut _,
+            name.as_ref().map_or(core::ptr::null_mut(), |n| n.as_char_ptr() as *mut _),
+            core::ptr::null_mut(),
+        ];
+        // SAFETY: `dev` is a valid device, and `envp` is a null-terminated array of C strings
+        // that outlive the call, which does not modify them.
+        crate::error::to_result(unsafe {
+            bindings::kobject_uevent_env(
+                &mut (*self.dev.raw_device()).kobj,
+                bindings::kobject_action_KOBJ_CHANGE,
+                envp.as_mut_ptr(),
+            )
+        })
+    }
+}
+
+// SAFETY: `pending` is protected by its lock, and the other fields are never modified.
+unsafe impl Sync for Inner {}
+
+// SAFETY: `device::Device` may be dropped from any thread, and `xlate` only holds static data.
+unsafe impl Send for Inner {}
+
+/// The uevents of a controller waiting to be sent.
+pub(crate) struct UeventQueue {
+    inner: Arc<Inner>,
+}
+
+impl UeventQueue {
+    /// Creates the queue of the controller of `dev`, naming lines with `xlate`.
+    pub(crate) fn try_new(dev: &impl RawDevice, xlate: OfXlate) -> Result<Self> {
+        let mut inner = UniqueArc::try_new(Inner {
+            // SAFETY: `spinlock_init` is called below.
+            pending: unsafe {
+                SpinLock::new(Pending {
+                    events: [(ResetOp::Status, 0); QUEUE_DEPTH],
+                    head: 0,
+                    len: 0,
+                })
+            },
+            // SAFETY: `init_work_item` is called below.
+            work: unsafe { Work::new() },
+            dev: device::Device::from_dev(dev),
+            xlate,
+        })?;
+
+        // SAFETY: The contents of a `UniqueArc` never move.
+        let pending = unsafe { Pin::new_unchecked(&mut inner.pending) };
+        crate::spinlock_init!(pending, "UeventQueue::pending");
+        crate::init_work_item!(&inner);
+
+        Ok(Self {
+            inner: inner.into(),
+        })
+    }
+
+    /// Queues the uevent for operation `op` on line `id`.
+    ///
+    /// Can be called from any context.
+    pub(crate) fn push(&self, op: ResetOp, id: u64) {
+        {
+            let mut pending = self.inner.pending.lock_irqdisable();
+            if pending.len == QUEUE_DEPTH {
+                return;
+            }
+            let tail = (pending.head + pending.len) % QUEUE_DEPTH;
+            pending.events[tail] = (op, id);
+            pending.len += 1;
+        }
+        workqueue::system().enqueue(self.inner.clone());
+    }
+}
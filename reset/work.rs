@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Deferred operations on a reset line.
+//!
+//! A [`ResetWorkItem`] owns a control and the action to run on it, so that drivers can schedule
+//! a reset from interrupt context, or for later, without their own work item plumbing:
+//!
+//! ```ignore
+//! let work = ResetWorkItem::try_new(rstc, ResetAction::Reset)?;
+//! // In the error interrupt handler:
+//! work.schedule();
+//! ```
+
+use super::ExclusiveResetControl;
+use crate::{
+    error::Result,
+    pr_err,
+    sync::{Arc, UniqueArc},
+    workqueue::{self, Work},
+};
+
+/// The action run by a [`ResetWorkItem`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResetAction {
+    /// Trigger a self-deasserting reset.
+    Reset,
+    /// Assert the line.
+    Assert,
+    /// Deassert the line.
+    Deassert,
+}
+
+struct Inner {
+    work: Work,
+    control: ExclusiveResetControl,
+    action: ResetAction,
+}
+
+crate::impl_self_work_adapter!(Inner, work, |w| w.run());
+
+impl Inner {
+    fn run(&self) {
+        let ret = match self.action {
+            ResetAction::Reset => self.control.reset(),
+            ResetAction::Assert => self.control.assert(),
+            ResetAction::Deassert => self.control.deassert(),
+        };
+        if let Err(e) = ret {
+            pr_err!("Deferred {:?} of reset line failed: {:?}\n", self.action, e);
+        }
+    }
+}
+
+/// An action on a reset line, run on the system workqueue when scheduled.
+pub struct ResetWorkItem {
+    inner: Arc<Inner>,
+}
+
+impl ResetWorkItem {
+    /// Creates a work item running `action` on `control`.
+    pub fn try_new(control: ExclusiveResetControl, action: ResetAction) -> Result<Self> {
+        let inner = UniqueArc::try_new(Inner {
+            // SAFETY: `init_work_item` is called below.
+            work: unsafe { Work::new() },
+            control,
+            action,
+        })?;
+        crate::init_work_item!(&inner);
+        Ok(Self {
+            inner: inner.into(),
+        })
+    }
+
+    /// Queues the action, returns `false` if it was already queued and has not run yet.
+    ///
+    /// Can be called from any context, including interrupt handlers. The control is kept alive
+    /// until the action has run, even if the work item is dropped.
+    pub fn schedule(&self) -> bool {
+        workqueue::system().enqueue(self.inner.clone())
+    }
+
+    /// Returns the action run by the work item.
+    pub fn action(&self) -> ResetAction {
+        self.inner.action
+    }
+}
@@ -0,0 +1,311 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Microbenchmark of the overhead of the Rust reset controller abstraction.
+//!
+//! Registers two in-memory controllers with the same lines: one through [`ResetRegistration`],
+//! and a reference one filling in `struct reset_controller_dev` directly, the way a minimal C
+//! driver does. Both are then driven through their `reset_control_ops`, so the difference
+//! between them is the cost of the Rust adapter: dispatch, borrowing the driver data and
+//! converting errors. Bind it with a node like:
+//!
+//! ```text
+//! reset-bench {
+//!     compatible = "linux,reset-bench";
+//!     #reset-cells = <1>;
+//! };
+//! ```
+//!
+//! The average time of each operation, in nanoseconds, is measured at probe time and exported
+//! under `/sys/kernel/debug/reset_bench/`:
+//!
+//! ```text
+//! rust_assert_ns  rust_deassert_ns  rust_status_ns
+//! c_assert_ns     c_deassert_ns     c_status_ns
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    ffi::{c_int, c_ulong},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use kernel::{
+    bindings, c_str,
+    device::RawDevice,
+    error::to_result,
+    module_platform_driver, of, platform,
+    prelude::*,
+    reset::{registered_controllers, ResetDevice, ResetDriverOps, ResetRegistration, ResetRequest},
+    str::CStr,
+};
+
+module_platform_driver! {
+    type: ResetBench,
+    name: "rust_reset_bench",
+    author: "Rust for Linux Contributors",
+    description: "Microbenchmark of the Rust reset controller abstraction",
+    license: "GPL",
+    params: {
+        iterations: u32 {
+            default: 100000,
+            permissions: 0,
+            description: "Number of times each operation is measured",
+        },
+    },
+}
+
+/// Number of lines of both controllers.
+const NR_LINES: u32 = 8;
+
+/// The emulated lines, `true` when asserted.
+struct Lines([AtomicBool; NR_LINES as usize]);
+
+impl Lines {
+    const fn new() -> Self {
+        const DEASSERTED: AtomicBool = AtomicBool::new(false);
+        Self([DEASSERTED; NR_LINES as usize])
+    }
+
+    fn line(&self, id: u64) -> Option<&AtomicBool> {
+        self.0.get(id as usize)
+    }
+}
+
+struct BenchOps;
+
+#[vtable]
+impl ResetDriverOps for BenchOps {
+    type Data = Box<Lines>;
+
+    fn assert(data: &Lines, req: ResetRequest) -> Result<i32> {
+        data.line(req.id()).ok_or(EINVAL)?.store(true, Ordering::Relaxed);
+        Ok(0)
+    }
+
+    fn deassert(data: &Lines, req: ResetRequest) -> Result<i32> {
+        data.line(req.id()).ok_or(EINVAL)?.store(false, Ordering::Relaxed);
+        Ok(0)
+    }
+
+    fn status(data: &Lines, req: ResetRequest) -> Result<i32> {
+        Ok(data.line(req.id()).ok_or(EINVAL)?.load(Ordering::Relaxed) as i32)
+    }
+}
+
+/// The reference controller, registered without the Rust abstraction.
+struct RefController {
+    rcdev: UnsafeCell<bindings::reset_controller_dev>,
+    lines: Lines,
+    registered: bool,
+}
+
+impl RefController {
+    /// # Safety
+    ///
+    /// `rcdev` must be embedded in a [`RefController`].
+    unsafe fn lines<'a>(rcdev: *mut bindings::reset_controller_dev) -> &'a Lines {
+        // SAFETY: By the safety requirements, `rcdev` is embedded in a `RefController`.
+        unsafe { &(*kernel::container_of!(rcdev, RefController, rcdev)).lines }
+    }
+}
+
+impl Drop for RefController {
+    fn drop(&mut self) {
+        if self.registered {
+            // SAFETY: `rcdev` was registered in `ResetBench::probe`.
+            unsafe { bindings::reset_controller_unregister(self.rcdev.get()) };
+        }
+    }
+}
+
+unsafe extern "C" fn ref_assert(rcdev: *mut bindings::reset_controller_dev, id: c_ulong) -> c_int {
+    // SAFETY: Only the reference controller has these operations.
+    match unsafe { RefController::lines(rcdev) }.line(id as u64) {
+        Some(line) => {
+            line.store(true, Ordering::Relaxed);
+            0
+        }
+        None => EINVAL.to_kernel_errno(),
+    }
+}
+
+unsafe extern "C" fn ref_deassert(
+    rcdev: *mut bindings::reset_controller_dev,
+    id: c_ulong,
+) -> c_int {
+    // SAFETY: Only the reference controller has these operations.
+    match unsafe { RefController::lines(rcdev) }.line(id as u64) {
+        Some(line) => {
+            line.store(false, Ordering::Relaxed);
+            0
+        }
+        None => EINVAL.to_kernel_errno(),
+    }
+}
+
+unsafe extern "C" fn ref_status(rcdev: *mut bindings::reset_controller_dev, id: c_ulong) -> c_int {
+    // SAFETY: Only the reference controller has these operations.
+    match unsafe { RefController::lines(rcdev) }.line(id as u64) {
+        Some(line) => line.load(Ordering::Relaxed) as c_int,
+        None => EINVAL.to_kernel_errno(),
+    }
+}
+
+struct RefOps(bindings::reset_control_ops);
+
+// SAFETY: The operations are never modified.
+unsafe impl Sync for RefOps {}
+
+static REF_OPS: RefOps = RefOps(bindings::reset_control_ops {
+    reset: None,
+    assert: Some(ref_assert),
+    deassert: Some(ref_deassert),
+    status: Some(ref_status),
+});
+
+/// Average time of each operation, in nanoseconds, read by debugfs.
+#[derive(Default)]
+struct Results {
+    rust: [u64; 3],
+    c: [u64; 3],
+}
+
+/// Returns the average time of `op` on `rcdev` over `iterations` calls, in nanoseconds.
+fn measure(rcdev: &ResetDevice, iterations: u32, op: impl Fn(&ResetDevice, u64)) -> u64 {
+    // SAFETY: `ktime_get` has no safety requirements.
+    let start = unsafe { bindings::ktime_get() };
+    for i in 0..iterations {
+        op(rcdev, (i % NR_LINES) as u64);
+    }
+    // SAFETY: `ktime_get` has no safety requirements.
+    let elapsed = unsafe { bindings::ktime_get() }.saturating_sub(start).max(0) as u64;
+    elapsed / iterations.max(1) as u64
+}
+
+/// Measures assert, deassert and status on `rcdev`, called through its operations like the C
+/// core does.
+fn measure_all(rcdev: &ResetDevice, iterations: u32) -> [u64; 3] {
+    // SAFETY: `rcdev` is registered, so its operations are valid.
+    let ops = unsafe { &*(*rcdev.as_ptr()).ops };
+    [ops.assert, ops.deassert, ops.status].map(|op| match op {
+        Some(op) => measure(rcdev, iterations, |r, id| {
+            // SAFETY: `r` is registered, and `op` is one of its operations.
+            let _ = unsafe { op(r.as_ptr(), id as c_ulong) };
+        }),
+        None => 0,
+    })
+}
+
+struct Bench {
+    dir: *mut bindings::dentry,
+    _results: Box<Results>,
+    _reference: Pin<Box<RefController>>,
+    _reg: Pin<Box<ResetRegistration<BenchOps>>>,
+}
+
+impl Drop for Bench {
+    fn drop(&mut self) {
+        // The files point into `_results`, so they go first.
+        // SAFETY: `dir` is the directory created in `probe`, or an error pointer or null, which
+        // `debugfs_remove` ignores.
+        unsafe { bindings::debugfs_remove(self.dir) };
+    }
+}
+
+// SAFETY: The debugfs directory is only removed, which can be done from any thread, and the
+// controllers can be used and unregistered from any thread.
+unsafe impl Send for Bench {}
+
+// SAFETY: `Bench` has no methods taking `&self`.
+unsafe impl Sync for Bench {}
+
+struct ResetBench;
+
+impl platform::Driver for ResetBench {
+    type Data = Box<Bench>;
+
+    kernel::define_of_id_table! {(), [
+        (of::DeviceId::Compatible(b"linux,reset-bench"), None),
+    ]}
+
+    fn probe(dev: &mut platform::Device, _id_info: Option<&Self::IdInfo>) -> Result<Self::Data> {
+        let iterations = *iterations.read();
+
+        let mut reg = Pin::from(Box::try_new(ResetRegistration::new())?);
+        kernel::reset_controller_register!(
+            reg.as_mut(),
+            dev,
+            NR_LINES,
+            Box::try_new(Lines::new())?
+        )?;
+
+        let mut reference = Pin::from(Box::try_new(RefController {
+            // SAFETY: All fields of `reset_controller_dev` are integers or (function) pointers,
+            // for which all zeroes is a valid value.
+            rcdev: UnsafeCell::new(unsafe { core::mem::zeroed() }),
+            lines: Lines::new(),
+            registered: false,
+        })?);
+        // SAFETY: Nothing is moved out of the box.
+        let reference_mut = unsafe { reference.as_mut().get_unchecked_mut() };
+        let rcdev = reference_mut.rcdev.get_mut();
+        rcdev.ops = &REF_OPS.0;
+        rcdev.owner = THIS_MODULE.as_ptr();
+        // No device tree node: consumers must never find the reference controller.
+        rcdev.dev = dev.raw_device();
+        rcdev.nr_resets = NR_LINES;
+        // SAFETY: `rcdev` is pinned, and unregistered when `reference` is dropped.
+        to_result(unsafe { bindings::reset_controller_register(reference_mut.rcdev.get()) })?;
+        reference_mut.registered = true;
+
+        let mut results = Box::try_new(Results::default())?;
+        {
+            // SAFETY: `dev` is a valid device.
+            let np = unsafe { (*dev.raw_device()).of_node };
+            let controllers = registered_controllers();
+            let rust = controllers.find_by_of_node(np).ok_or(ENODEV)?;
+            results.rust = measure_all(rust, iterations);
+        }
+        // SAFETY: The reference controller is registered and lives until the end of `probe`.
+        let reference_dev = unsafe { ResetDevice::from_raw(reference.rcdev.get()) };
+        results.c = measure_all(reference_dev, iterations);
+
+        pr_info!(
+            "assert/deassert/status in ns: rust {:?}, c {:?}\n",
+            results.rust,
+            results.c
+        );
+
+        Ok(Box::try_new(Bench {
+            dir: create_files(&mut results),
+            _results: results,
+            _reference: reference,
+            _reg: reg,
+        })?)
+    }
+}
+
+/// Creates the debugfs directory with the files of `results`, best effort.
+fn create_files(results: &mut Results) -> *mut bindings::dentry {
+    // SAFETY: The name is a valid C string, and a null parent means the debugfs root.
+    let dir = unsafe {
+        bindings::debugfs_create_dir(c_str!("reset_bench").as_char_ptr(), core::ptr::null_mut())
+    };
+    let files: [(&CStr, &mut u64); 6] = {
+        let [ra, rd, rs] = &mut results.rust;
+        let [ca, cd, cs] = &mut results.c;
+        [
+            (c_str!("rust_assert_ns"), ra),
+            (c_str!("rust_deassert_ns"), rd),
+            (c_str!("rust_status_ns"), rs),
+            (c_str!("c_assert_ns"), ca),
+            (c_str!("c_deassert_ns"), cd),
+            (c_str!("c_status_ns"), cs),
+        ]
+    };
+    for (name, value) in files {
+        // SAFETY: The name is a valid C string, and `value` lives in the boxed results, which
+        // outlive the directory.
+        unsafe { bindings::debugfs_create_u64(name.as_char_ptr(), 0o444, dir, value) };
+    }
+    dir
+}
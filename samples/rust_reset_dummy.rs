@@ -0,0 +1,102 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! In-memory dummy reset controller.
+//!
+//! Emulates a controller whose lines only exist in memory, so that consumer drivers can be
+//! developed and tested on machines without the real hardware. Bind it with a node like:
+//!
+//! ```text
+//! reset_dummy: reset-controller {
+//!     compatible = "linux,reset-dummy";
+//!     #reset-cells = <1>;
+//! };
+//! ```
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use kernel::{
+    module_platform_driver, of, platform,
+    prelude::*,
+    reset::{ResetDriverOps, ResetRegistration, ResetRequest},
+};
+
+module_platform_driver! {
+    type: ResetDummy,
+    name: "rust_reset_dummy",
+    author: "Rust for Linux Contributors",
+    description: "In-memory dummy reset controller",
+    license: "GPL",
+    params: {
+        nr_lines: u32 {
+            default: 32,
+            permissions: 0,
+            description: "Number of emulated reset lines",
+        },
+    },
+}
+
+/// The emulated lines, `true` when asserted.
+struct DummyLines {
+    asserted: Vec<AtomicBool>,
+}
+
+impl DummyLines {
+    fn line(&self, req: ResetRequest) -> Result<&AtomicBool> {
+        self.asserted.get(req.id() as usize).ok_or(EINVAL)
+    }
+}
+
+struct ResetDummyOps;
+
+#[vtable]
+impl ResetDriverOps for ResetDummyOps {
+    type Data = Box<DummyLines>;
+
+    fn reset(data: &DummyLines, req: ResetRequest) -> Result<i32> {
+        // A pulse leaves the line deasserted.
+        data.line(req)?.store(false, Ordering::Relaxed);
+        Ok(0)
+    }
+
+    fn assert(data: &DummyLines, req: ResetRequest) -> Result<i32> {
+        data.line(req)?.store(true, Ordering::Relaxed);
+        Ok(0)
+    }
+
+    fn deassert(data: &DummyLines, req: ResetRequest) -> Result<i32> {
+        data.line(req)?.store(false, Ordering::Relaxed);
+        Ok(0)
+    }
+
+    fn status(data: &DummyLines, req: ResetRequest) -> Result<i32> {
+        Ok(data.line(req)?.load(Ordering::Relaxed) as i32)
+    }
+}
+
+struct ResetDummy;
+
+impl platform::Driver for ResetDummy {
+    type Data = Pin<Box<ResetRegistration<ResetDummyOps>>>;
+
+    kernel::define_of_id_table! {(), [
+        (of::DeviceId::Compatible(b"linux,reset-dummy"), None),
+    ]}
+
+    fn probe(dev: &mut platform::Device, _id_info: Option<&Self::IdInfo>) -> Result<Self::Data> {
+        let nr = *nr_lines.read();
+        let mut asserted = Vec::try_with_capacity(nr as usize)?;
+        for _ in 0..nr {
+            // Lines come out of reset like on most real controllers.
+            asserted.try_push(AtomicBool::new(false))?;
+        }
+
+        let mut reg = Pin::from(Box::try_new(ResetRegistration::new())?);
+        kernel::reset_controller_register!(
+            reg.as_mut(),
+            dev,
+            nr,
+            Box::try_new(DummyLines { asserted })?
+        )?;
+        pr_info!("{} dummy reset lines registered\n", nr);
+        Ok(reg)
+    }
+}